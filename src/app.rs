@@ -1,17 +1,65 @@
 use crate::audio_player::AudioPlayer;
+use crate::audio_player::chain::ChainEditor;
+use crate::audio_player::dynamics::{DYNAMICS_PARAMS, DynamicsParam};
+use crate::audio_player::editor::{self, EDIT_PARAMS, SampleEditor};
+use crate::audio_player::effects::{
+    DELAY_PARAMS, DISTORTION_PARAMS, DelayParam, DistortionParam, EffectKind, FILTER_PARAMS,
+    FilterParam, MODULATION_EFFECTS, MODULATION_PARAMS, ModulationParam,
+};
+use crate::audio_player::envelope::{ADSR_PARAMS, AdsrParam};
+use crate::audio_player::history::History;
+use crate::audio_player::looping::{LoopEditor, LoopMarker, LoopRegion};
+use crate::audio_player::params::ParamList;
+use crate::audio_player::presets::{PresetBrowser, PresetPrompt};
+use crate::audio_player::recorder::RecorderState;
+use crate::audio_player::sample::{DecodedAudio, WavFormat};
+use crate::command::{self, Command, CommandLine};
+use crate::config::Config;
 use crate::file_manager::FileManager;
-use crossterm::event::KeyCode;
+use crate::keymap::{Action, KeyChord};
+use crate::ui::{self, BrowserLayout, MainLayout};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::{Position, Rect};
 use rodio::OutputStreamHandle;
 use std::io;
+use std::path::{Path, PathBuf};
+
+// Highest count that can be typed before an action
+const MAX_COUNT: usize = 9999;
+// Steps Ctrl-d/Ctrl-u take in the modes that set a single value
+const PAGE_STEPS: usize = 10;
 
 // Define possible app modes for UI
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AppMode {
     Normal,
     Volume,
     Pitch,
     Filter,
     FileBrowser, // New mode for file browsing
+    Loop,
+    Edit,
+    Record,
+    Chain,
+    Mixer,
+    Stereo,
+    Dynamics,
+    Delay,
+    Modulation,
+    Distortion,
+    SynthFilter,
+    Envelope,
+    Presets,
+    Messages,
+}
+
+// What a held mouse button is dragging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Drag {
+    Volume,
+    Speed,
+    Lowpass,
+    Overview,
 }
 
 // App state
@@ -20,264 +68,1744 @@ pub struct App {
     pub file_manager: FileManager,
     pub should_quit: bool,
     pub mode: AppMode,
-    pub show_help: bool,
+    // Keys typed so far of a binding that takes more than one
+    pub pending_keys: Vec<KeyChord>,
+    // Count typed before an action, and the last action (with its count)
+    // for `.` to repeat
+    pub count: Option<usize>,
+    last_action: Option<(Action, Option<usize>)>,
+    // Size of the terminal, for paging through lists and working out what
+    // the mouse is on
+    pub screen: Rect,
+    drag: Option<Drag>,
     pub current_audio_file: Option<String>, // Add this to track the current audio file name
+    pub current_audio_path: Option<PathBuf>,
+    pub loop_editor: LoopEditor,
+    pub sample_editor: SampleEditor,
+    pub chain_editor: ChainEditor,
+    pub dynamics_params: ParamList<DynamicsParam>,
+    pub delay_params: ParamList<DelayParam>,
+    // Modulation mode: which effect, and which of its parameters
+    pub modulation_effects: ParamList<EffectKind>,
+    pub modulation_params: ParamList<ModulationParam>,
+    pub distortion_params: ParamList<DistortionParam>,
+    pub filter_params: ParamList<FilterParam>,
+    pub envelope_params: ParamList<AdsrParam>,
+    // Voice selected in the mixer, by id so it sticks when others finish
+    pub mixer_selected: Option<u64>,
+    // Line selected in the message log
+    pub message_selected: usize,
+    pub preset_browser: PresetBrowser,
+    // Preset name of the A/B slot that isn't active
+    ab_other_preset: Option<String>,
+    // Undo/redo of effect changes
+    pub history: History,
+    pub config: Config,
+    // Problems with the config file, shown until a key is pressed
+    pub config_errors: Vec<String>,
+    pub command_line: CommandLine,
 }
 
 impl App {
-    pub fn new(stream_handle: Option<OutputStreamHandle>) -> Self {
+    pub fn new(
+        stream_handle: Option<OutputStreamHandle>,
+        config: Config,
+        config_errors: Vec<String>,
+    ) -> Self {
+        let player = AudioPlayer::new(stream_handle, &config);
+        let history = History::new(&player.effect_manager);
+
         Self {
-            player: AudioPlayer::new(stream_handle),
-            file_manager: FileManager::new(),
+            player,
+            file_manager: FileManager::new(config.start_dir.clone()),
             should_quit: false,
             mode: AppMode::Normal,
-            show_help: false,
+            pending_keys: Vec::new(),
+            count: None,
+            last_action: None,
+            screen: Rect::new(0, 0, 80, 24),
+            drag: None,
             current_audio_file: None,
+            current_audio_path: None,
+            loop_editor: LoopEditor::new(),
+            sample_editor: SampleEditor::new(),
+            chain_editor: ChainEditor::new(),
+            dynamics_params: ParamList::new(&DYNAMICS_PARAMS),
+            delay_params: ParamList::new(&DELAY_PARAMS),
+            modulation_effects: ParamList::new(&MODULATION_EFFECTS),
+            modulation_params: ParamList::new(&MODULATION_PARAMS),
+            distortion_params: ParamList::new(&DISTORTION_PARAMS),
+            filter_params: ParamList::new(&FILTER_PARAMS),
+            envelope_params: ParamList::new(&ADSR_PARAMS),
+            mixer_selected: None,
+            message_selected: 0,
+            preset_browser: PresetBrowser::new(),
+            ab_other_preset: None,
+            history,
+            config,
+            config_errors,
+            command_line: CommandLine::new(),
         }
     }
 
-    pub fn handle_key_events(&mut self, key_code: KeyCode) -> io::Result<()> {
-        // Handle global keys first
-        match key_code {
-            KeyCode::Char('q') => {
-                if self.mode == AppMode::Normal {
-                    self.should_quit = true;
+    fn set_current_file(&mut self, path: &Path) {
+        self.current_audio_file = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+        self.current_audio_path = Some(path.to_path_buf());
+    }
+
+    pub fn handle_key_events(&mut self, key: KeyEvent) -> io::Result<()> {
+        // Any key closes the config errors
+        if !self.config_errors.is_empty() {
+            self.config_errors.clear();
+            return Ok(());
+        }
+        self.command_line.result = None;
+
+        // Typing a preset name takes every key, q and Esc included
+        if self.mode == AppMode::Presets && self.preset_browser.prompt.is_some() {
+            self.handle_preset_prompt(key.code);
+            return Ok(());
+        }
+        if self.command_line.active {
+            return self.handle_command_line(key);
+        }
+
+        let chord = KeyChord::from_event(&key);
+
+        // Digits before a binding are a count (5j), unless they're bound
+        if self.pending_keys.is_empty()
+            && chord.modifiers.is_empty()
+            && let KeyCode::Char(c) = chord.code
+            && let Some(digit) = c.to_digit(10)
+            && (digit > 0 || self.count.is_some())
+        {
+            let lookup = self.config.keymap.lookup(self.mode, &[chord]);
+            if lookup.action.is_none() && !lookup.more {
+                let count = self.count.unwrap_or(0) * 10 + digit as usize;
+                self.count = Some(count.min(MAX_COUNT));
+                return Ok(());
+            }
+        }
+
+        self.pending_keys.push(chord);
+        let lookup = self.config.keymap.lookup(self.mode, &self.pending_keys);
+
+        if lookup.more {
+            // Wait for the rest of a longer binding, like Space then v
+            return Ok(());
+        }
+        if let Some(action) = lookup.action {
+            self.pending_keys.clear();
+            return self.run_counted(action);
+        }
+
+        // Nothing starts with these keys. If the keys before the last one
+        // were bound by themselves, do that and start over from the last key
+        let last = self.pending_keys.pop();
+        let earlier = if self.pending_keys.is_empty() {
+            None
+        } else {
+            self.config
+                .keymap
+                .lookup(self.mode, &self.pending_keys)
+                .action
+        };
+        self.pending_keys.clear();
+        if let (Some(action), Some(last)) = (earlier, last) {
+            self.run_counted(action)?;
+            self.pending_keys.push(last);
+            let lookup = self.config.keymap.lookup(self.mode, &self.pending_keys);
+            if !lookup.more {
+                self.pending_keys.clear();
+                if let Some(action) = lookup.action {
+                    self.run_counted(action)?;
+                }
+            }
+        } else {
+            self.count = None;
+        }
+        Ok(())
+    }
+
+    pub fn handle_mouse_event(&mut self, mouse: MouseEvent) -> io::Result<()> {
+        // A click closes the config errors, like a key does
+        if !self.config_errors.is_empty() {
+            if matches!(mouse.kind, MouseEventKind::Down(_)) {
+                self.config_errors.clear();
+            }
+            return Ok(());
+        }
+        // Leave the mouse alone while something is being typed
+        if self.command_line.active || self.preset_browser.prompt.is_some() {
+            return Ok(());
+        }
+
+        let at = Position::new(mouse.column, mouse.row);
+        if self.mode == AppMode::FileBrowser {
+            return self.handle_browser_mouse(mouse.kind, at);
+        }
+
+        let layout = MainLayout::new(self.screen);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.pending_keys.clear();
+                self.count = None;
+                self.drag = [
+                    (layout.volume, Drag::Volume),
+                    (layout.speed, Drag::Speed),
+                    (layout.lowpass, Drag::Lowpass),
+                    (layout.overview, Drag::Overview),
+                ]
+                .into_iter()
+                .find(|(area, _)| area.contains(at))
+                .map(|(_, drag)| drag);
+                if let Some(drag) = self.drag {
+                    self.drag_to(drag, &layout, at.x)?;
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some(drag) = self.drag {
+                    self.drag_to(drag, &layout, at.x)?;
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.drag = None,
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let up = mouse.kind == MouseEventKind::ScrollUp;
+                let effects = &mut self.player.effect_manager;
+                // The wheel turns a gauge it's over, and scrolls anywhere else
+                if layout.volume.contains(at) {
+                    effects.change_volume(up, &self.config.volume);
+                } else if layout.speed.contains(at) {
+                    effects.change_pitch(up, &self.config.speed);
+                } else if layout.lowpass.contains(at) {
+                    effects.change_lowpass(up, &self.config.lowpass);
                 } else {
-                    // In other modes, q returns to normal mode
-                    self.mode = AppMode::Normal;
+                    return self.run_action(if up { Action::Prev } else { Action::Next });
+                }
+            }
+            _ => {}
+        }
+
+        self.history.record(&self.player.effect_manager);
+        Ok(())
+    }
+
+    // A click selects a file, and a click on the selected one opens or
+    // plays it
+    fn handle_browser_mouse(&mut self, kind: MouseEventKind, at: Position) -> io::Result<()> {
+        let list = BrowserLayout::new(self.screen).list;
+        match kind {
+            MouseEventKind::Down(MouseButton::Left) if list.contains(at) => {
+                let index = ui::list_offset(self.file_manager.selected_index, list.height)
+                    + (at.y - list.y) as usize;
+                if index == self.file_manager.selected_index {
+                    return self.run_action(Action::Select);
+                }
+                if index < self.file_manager.entries.len() {
+                    self.file_manager.selected_index = index;
+                }
+            }
+            MouseEventKind::ScrollUp => return self.run_action(Action::Prev),
+            MouseEventKind::ScrollDown => return self.run_action(Action::Next),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Set what's being dragged from the column the mouse is in
+    fn drag_to(&mut self, drag: Drag, layout: &MainLayout, column: u16) -> io::Result<()> {
+        let area = layout.inner(match drag {
+            Drag::Volume => layout.volume,
+            Drag::Speed => layout.speed,
+            Drag::Lowpass => layout.lowpass,
+            Drag::Overview => layout.overview,
+        });
+        if area.width == 0 {
+            return Ok(());
+        }
+        let offset = column.clamp(area.left(), area.right() - 1) - area.left();
+        // Gauges reach both ends of their range within the bar
+        let fraction = offset as f32 / area.width.saturating_sub(1).max(1) as f32;
+
+        let effects = &mut self.player.effect_manager;
+        match drag {
+            Drag::Volume => effects.volume = self.config.volume.at_fraction(fraction),
+            Drag::Speed => effects.playback_speed = self.config.speed.at_fraction(fraction),
+            Drag::Lowpass => {
+                effects.lowpass_cutoff = self.config.lowpass.at_fraction(fraction).round() as u32
+            }
+            Drag::Overview => {
+                // The same column to frame mapping the overview is drawn with
+                let Some(path) = self.current_audio_path.clone() else {
+                    return Ok(());
+                };
+                let Some(frames) = self.player.decoded_audio(&path).map(|audio| audio.frames())
+                else {
+                    return Ok(());
+                };
+                let frame = offset as usize * frames / area.width as usize;
+                if let Err(e) = self.player.seek(&path, frame) {
+                    self.player.messages.error(&e.to_string());
                 }
+            }
+        }
+        Ok(())
+    }
+
+    // Run an action as many times as the count typed before it says, and
+    // remember it for `.`
+    fn run_counted(&mut self, action: Action) -> io::Result<()> {
+        let count = self.count.take();
+        let (action, count) = match action {
+            // A new count replaces the one the action had, as in Vim
+            Action::Repeat => match self.last_action {
+                Some((last, last_count)) => (last, count.or(last_count)),
+                None => return Ok(()),
+            },
+            _ => (action, count),
+        };
+        if action.is_repeatable() {
+            self.last_action = Some((action, count));
+        }
+
+        if self.jump(action, count) {
+            return Ok(());
+        }
+        let times = if action.takes_count() {
+            count.unwrap_or(1)
+        } else {
+            1
+        };
+        for _ in 0..times {
+            self.run_action(action)?;
+        }
+        Ok(())
+    }
+
+    // gg/G and Ctrl-d/Ctrl-u in modes with a list. A count with gg or G goes
+    // to that line. Returns false if it's not a list motion or there's no list
+    fn jump(&mut self, action: Action, count: Option<usize>) -> bool {
+        if !matches!(
+            action,
+            Action::Top | Action::Bottom | Action::HalfPageDown | Action::HalfPageUp
+        ) {
+            return false;
+        }
+        let Some((selected, len)) = self.list_position() else {
+            return false;
+        };
+        if len == 0 {
+            return true;
+        }
+
+        let page = self.page_size() * count.unwrap_or(1);
+        let index = match action {
+            Action::Top => count.map_or(0, |line| line - 1),
+            Action::Bottom => count.map_or(len - 1, |line| line - 1),
+            Action::HalfPageDown => selected + page,
+            _ => selected.saturating_sub(page),
+        };
+        self.select_in_list(index.min(len - 1));
+        true
+    }
+
+    // Selected entry and length of the list the current mode goes through
+    fn list_position(&self) -> Option<(usize, usize)> {
+        match self.mode {
+            AppMode::FileBrowser => Some((
+                self.file_manager.selected_index,
+                self.file_manager.entries.len(),
+            )),
+            AppMode::Dynamics => Some((
+                self.dynamics_params.selected,
+                self.dynamics_params.params().len(),
+            )),
+            AppMode::Delay => Some((self.delay_params.selected, self.delay_params.params().len())),
+            AppMode::Modulation => Some((
+                self.modulation_effects.selected,
+                self.modulation_effects.params().len(),
+            )),
+            AppMode::Distortion => Some((
+                self.distortion_params.selected,
+                self.distortion_params.params().len(),
+            )),
+            AppMode::SynthFilter => Some((
+                self.filter_params.selected,
+                self.filter_params.params().len(),
+            )),
+            AppMode::Envelope => Some((
+                self.envelope_params.selected,
+                self.envelope_params.params().len(),
+            )),
+            AppMode::Edit => Some((self.sample_editor.selected, EDIT_PARAMS.len())),
+            AppMode::Chain => Some((
+                self.chain_editor.selected,
+                self.player.effect_manager.chain.len(),
+            )),
+            AppMode::Presets => {
+                Some((self.preset_browser.selected, self.player.preset_store.len()))
+            }
+            AppMode::Mixer => Some((
+                self.mixer_selected_index().unwrap_or(0),
+                self.player.voices.len(),
+            )),
+            AppMode::Messages => Some((self.message_selected, self.player.messages.len())),
+            _ => None,
+        }
+    }
+
+    fn select_in_list(&mut self, index: usize) {
+        match self.mode {
+            AppMode::FileBrowser => self.file_manager.selected_index = index,
+            AppMode::Dynamics => self.dynamics_params.selected = index,
+            AppMode::Delay => self.delay_params.selected = index,
+            AppMode::Modulation => self.modulation_effects.selected = index,
+            AppMode::Distortion => self.distortion_params.selected = index,
+            AppMode::SynthFilter => self.filter_params.selected = index,
+            AppMode::Envelope => self.envelope_params.selected = index,
+            AppMode::Edit => self.sample_editor.selected = index,
+            AppMode::Chain => self.chain_editor.selected = index,
+            AppMode::Presets => self.preset_browser.selected = index,
+            AppMode::Mixer => self.mixer_selected = Some(self.player.voices[index].id),
+            AppMode::Messages => self.message_selected = index,
+            _ => {}
+        }
+    }
+
+    // Lines Ctrl-d/Ctrl-u move, about half of the list on screen
+    fn page_size(&self) -> usize {
+        let height = if self.mode == AppMode::FileBrowser {
+            BrowserLayout::new(self.screen).list.height
+        } else {
+            MainLayout::new(self.screen).panel.height
+        };
+        (height / 2).max(1) as usize
+    }
+
+    fn run_action(&mut self, action: Action) -> io::Result<()> {
+        // Handle global actions first
+        match action {
+            Action::Quit => {
+                self.should_quit = true;
                 return Ok(());
             }
-            KeyCode::Esc => {
-                // Escape always returns to normal mode
+            Action::Back => {
+                // Back to normal mode, from any other
                 self.mode = AppMode::Normal;
-                self.show_help = false;
                 return Ok(());
             }
+            Action::SwitchAb => {
+                // A/B works from every mode, so it can be flipped while tweaking
+                self.switch_ab();
+                return Ok(());
+            }
+            Action::Undo => {
+                self.history.undo(&mut self.player.effect_manager);
+                self.clamp_chain_selection();
+                return Ok(());
+            }
+            Action::Redo => {
+                self.history.redo(&mut self.player.effect_manager);
+                self.clamp_chain_selection();
+                return Ok(());
+            }
+            Action::CommandLine => {
+                self.command_line.open();
+                return Ok(());
+            }
+            Action::Nop => return Ok(()),
             _ => {}
         }
 
-        // Handle mode-specific keys
+        // Handle mode-specific actions
         match self.mode {
-            AppMode::Normal => self.handle_normal_mode(key_code)?,
-            AppMode::Volume => self.handle_volume_mode(key_code),
-            AppMode::Pitch => self.handle_pitch_mode(key_code),
-            AppMode::Filter => self.handle_filter_mode(key_code),
-            AppMode::FileBrowser => self.handle_file_browser_mode(key_code)?,
+            AppMode::Normal => self.handle_normal_mode(action)?,
+            AppMode::Volume => self.handle_volume_mode(action),
+            AppMode::Pitch => self.handle_pitch_mode(action),
+            AppMode::Filter => self.handle_filter_mode(action),
+            AppMode::FileBrowser => self.handle_file_browser_mode(action)?,
+            AppMode::Loop => self.handle_loop_mode(action)?,
+            AppMode::Edit => self.handle_edit_mode(action),
+            AppMode::Record => self.handle_record_mode(action),
+            AppMode::Chain => self.handle_chain_mode(action),
+            AppMode::Mixer => self.handle_mixer_mode(action),
+            AppMode::Stereo => self.handle_stereo_mode(action),
+            AppMode::Dynamics => self.handle_dynamics_mode(action),
+            AppMode::Delay => self.handle_delay_mode(action),
+            AppMode::Modulation => self.handle_modulation_mode(action),
+            AppMode::Distortion => self.handle_distortion_mode(action),
+            AppMode::SynthFilter => self.handle_synth_filter_mode(action),
+            AppMode::Envelope => self.handle_envelope_mode(action),
+            AppMode::Presets => self.handle_presets_mode(action),
+            AppMode::Messages => self.handle_messages_mode(action),
         }
 
+        self.history.record(&self.player.effect_manager);
         Ok(())
     }
 
-    fn handle_normal_mode(&mut self, key_code: KeyCode) -> io::Result<()> {
-        match key_code {
-            KeyCode::Char(' ') => {
-                // Leader key - toggle help menu
-                self.show_help = !self.show_help;
-            }
-            KeyCode::Char('p') => {
-                // Instead of hardcoded file, use selected file
-                if let Some(file_path) = self.file_manager.get_selected_file() {
-                    if !file_path.is_dir() && self.file_manager.is_audio_file(&file_path) {
-                        // Set current audio file name
-                        self.current_audio_file = file_path
-                            .file_name()
-                            .map(|name| name.to_string_lossy().to_string());
-
-                        self.player
-                            .play_sound(file_path.to_str().unwrap_or("example.wav"), false)?;
-                    }
+    fn handle_normal_mode(&mut self, action: Action) -> io::Result<()> {
+        match action {
+            Action::Play => self.play_selected(false),
+            Action::Loop => self.play_selected(true),
+            Action::Open(mode) => self.open_mode(mode),
+            Action::ToggleReverb => {
+                self.player.effect_manager.toggle_reverb();
+            }
+            Action::Stop => {
+                self.player.stop_all();
+            }
+            Action::NextPreset => {
+                self.cycle_preset(true);
+            }
+            Action::PrevPreset => {
+                self.cycle_preset(false);
+            }
+            Action::CopyAb => {
+                let ab = &mut self.player.ab;
+                ab.copy_to_other(&self.player.effect_manager);
+                self.ab_other_preset = self.preset_browser.current.clone();
+                let message = format!(
+                    "Copied {} to {}",
+                    ab.active.name(),
+                    ab.active.other().name()
+                );
+                self.player.messages.info(&message);
+            }
+            Action::ToggleLoudnessMatch => {
+                let ab = &mut self.player.ab;
+                ab.loudness_match = !ab.loudness_match;
+                let message = if ab.loudness_match {
+                    "A/B loudness matching on"
                 } else {
-                    // Fallback to example.wav if no file selected
-                    self.player.play_sound("example.wav", false)?;
+                    "A/B loudness matching off"
+                };
+                self.player.messages.info(message);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Play the file selected in the browser
+    fn play_selected(&mut self, is_looping: bool) {
+        match self.file_manager.get_selected_file() {
+            Some(path) if !path.is_dir() && self.file_manager.is_audio_file(&path) => {
+                self.play_file(&path, is_looping)
+            }
+            Some(_) => self.player.messages.warn("Select an audio file to play"),
+            // Fall back to the example when nothing is selected
+            None => {
+                if let Err(e) = self.player.play_sound(Path::new("example.wav"), is_looping) {
+                    self.player.messages.error(&e.to_string());
                 }
             }
-            KeyCode::Char('r') => {
-                // Loop selected file
-                if let Some(file_path) = self.file_manager.get_selected_file() {
-                    if !file_path.is_dir() && self.file_manager.is_audio_file(&file_path) {
-                        // Set current audio file name
-                        self.current_audio_file = file_path
-                            .file_name()
-                            .map(|name| name.to_string_lossy().to_string());
+        }
+    }
 
-                        self.player
-                            .play_sound(file_path.to_str().unwrap_or("example.wav"), true)?;
+    // Make a file the current one and play it, telling why if it can't be
+    fn play_file(&mut self, path: &Path, is_looping: bool) {
+        self.set_current_file(path);
+        if let Err(e) = self.player.play_sound(path, is_looping) {
+            self.player.messages.error(&e.to_string());
+        }
+    }
+
+    fn open_mode(&mut self, mode: AppMode) {
+        match mode {
+            AppMode::FileBrowser => {
+                self.mode = AppMode::FileBrowser;
+                // Refresh files when entering browser
+                self.file_manager.refresh_files();
+            }
+            AppMode::Loop => {
+                // Edit the loop of the current file, or the selected one
+                if self.current_audio_path.is_none()
+                    && let Some(file_path) = self.file_manager.get_selected_file()
+                    && !file_path.is_dir()
+                    && self.file_manager.is_audio_file(&file_path)
+                {
+                    self.set_current_file(&file_path);
+                }
+                if let Some(path) = self.current_audio_path.clone() {
+                    self.mode = AppMode::Loop;
+                    // Decode up front so markers can be shown in seconds
+                    if let Err(e) = self.player.load_decoded(&path) {
+                        self.player.messages.error(&e.to_string());
                     }
                 } else {
-                    // Fallback to example.wav if no file selected
-                    self.player.play_sound("example.wav", true)?;
+                    self.player
+                        .messages
+                        .warn("Select an audio file to edit its loop");
                 }
             }
-            KeyCode::Char('v') => {
-                if self.show_help {
-                    self.mode = AppMode::Volume;
-                    self.show_help = false;
+            AppMode::Edit => {
+                // Edit the current file, or the selected one
+                if self.current_audio_path.is_none()
+                    && let Some(file_path) = self.file_manager.get_selected_file()
+                    && !file_path.is_dir()
+                    && self.file_manager.is_audio_file(&file_path)
+                {
+                    self.set_current_file(&file_path);
+                }
+                if let Some(path) = self.current_audio_path.clone() {
+                    self.sample_editor.open(&path);
+                    self.mode = AppMode::Edit;
+                } else {
+                    self.player.messages.warn("Select an audio file to edit");
                 }
             }
-            KeyCode::Char('c') => {
-                if self.show_help {
-                    self.mode = AppMode::Pitch;
-                    self.show_help = false;
+            AppMode::Messages => {
+                // Start at the newest, and the log shows what the toasts did
+                self.message_selected = self.player.messages.len().saturating_sub(1);
+                self.player.messages.dismiss_toasts();
+                self.mode = AppMode::Messages;
+            }
+            _ => self.mode = mode,
+        }
+    }
+
+    fn switch_ab(&mut self) {
+        self.player.ab.switch(&mut self.player.effect_manager);
+        std::mem::swap(&mut self.preset_browser.current, &mut self.ab_other_preset);
+        // The history belongs to the other slot now
+        self.history.reset(&self.player.effect_manager);
+        self.clamp_chain_selection();
+    }
+
+    // The chain may have been replaced by a shorter one
+    fn clamp_chain_selection(&mut self) {
+        self.chain_editor.selected = self
+            .chain_editor
+            .selected
+            .min(self.player.effect_manager.chain.len().saturating_sub(1));
+    }
+
+    fn handle_volume_mode(&mut self, action: Action) {
+        let effects = &mut self.player.effect_manager;
+        let range = &self.config.volume;
+
+        match action {
+            Action::VolumeDown => effects.change_volume(false, range),
+            Action::VolumeUp => effects.change_volume(true, range),
+            Action::HalfPageDown => {
+                (0..PAGE_STEPS).for_each(|_| effects.change_volume(false, range))
+            }
+            Action::HalfPageUp => (0..PAGE_STEPS).for_each(|_| effects.change_volume(true, range)),
+            Action::Top => effects.volume = range.max,
+            Action::Bottom => effects.volume = range.min,
+            _ => {}
+        }
+    }
+
+    fn handle_pitch_mode(&mut self, action: Action) {
+        let effects = &mut self.player.effect_manager;
+        let range = &self.config.speed;
+
+        match action {
+            Action::SpeedDown => effects.change_pitch(false, range),
+            Action::SpeedUp => effects.change_pitch(true, range),
+            Action::HalfPageDown => {
+                (0..PAGE_STEPS).for_each(|_| effects.change_pitch(false, range))
+            }
+            Action::HalfPageUp => (0..PAGE_STEPS).for_each(|_| effects.change_pitch(true, range)),
+            Action::Top => effects.playback_speed = range.max,
+            Action::Bottom => effects.playback_speed = range.min,
+            _ => {}
+        }
+    }
+
+    fn handle_filter_mode(&mut self, action: Action) {
+        let effects = &mut self.player.effect_manager;
+        let range = &self.config.lowpass;
+
+        match action {
+            Action::LowpassDown => effects.change_lowpass(false, range),
+            Action::LowpassUp => effects.change_lowpass(true, range),
+            Action::HalfPageDown => {
+                (0..PAGE_STEPS).for_each(|_| effects.change_lowpass(false, range))
+            }
+            Action::HalfPageUp => (0..PAGE_STEPS).for_each(|_| effects.change_lowpass(true, range)),
+            Action::Top => effects.lowpass_cutoff = range.max.round() as u32,
+            Action::Bottom => effects.lowpass_cutoff = range.min.round() as u32,
+            _ => {}
+        }
+    }
+
+    fn handle_stereo_mode(&mut self, action: Action) {
+        match action {
+            Action::BalanceLeft => {
+                self.player
+                    .effect_manager
+                    .change_balance(false, &self.config.balance);
+            }
+            Action::BalanceRight => {
+                self.player
+                    .effect_manager
+                    .change_balance(true, &self.config.balance);
+            }
+            Action::WidthDown => {
+                self.player
+                    .effect_manager
+                    .change_stereo_width(false, &self.config.stereo_width);
+            }
+            Action::WidthUp => {
+                self.player
+                    .effect_manager
+                    .change_stereo_width(true, &self.config.stereo_width);
+            }
+            Action::HalfPageDown => {
+                for _ in 0..PAGE_STEPS {
+                    self.player
+                        .effect_manager
+                        .change_stereo_width(false, &self.config.stereo_width);
                 }
             }
-            KeyCode::Char('g') => {
-                if self.show_help {
-                    self.mode = AppMode::Filter;
-                    self.show_help = false;
+            Action::HalfPageUp => {
+                for _ in 0..PAGE_STEPS {
+                    self.player
+                        .effect_manager
+                        .change_stereo_width(true, &self.config.stereo_width);
                 }
             }
-            KeyCode::Char('f') => {
-                if self.show_help {
-                    self.mode = AppMode::FileBrowser;
-                    self.show_help = false;
-                    // Refresh files when entering browser
-                    self.file_manager.refresh_files();
+            Action::Top => {
+                self.player.effect_manager.stereo_width = self.config.stereo_width.max;
+            }
+            Action::Bottom => {
+                self.player.effect_manager.stereo_width = self.config.stereo_width.min;
+            }
+            Action::CenterBalance => {
+                self.player.effect_manager.center_balance();
+            }
+            Action::ToggleMono => {
+                self.player.effect_manager.toggle_mono();
+            }
+            Action::SwapChannels => {
+                self.player.effect_manager.toggle_swap_channels();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_dynamics_mode(&mut self, action: Action) {
+        let param = self.dynamics_params.selected_param();
+        let dynamics = &mut self.player.effect_manager.dynamics;
+
+        match action {
+            Action::Next => {
+                self.dynamics_params.select_next();
+            }
+            Action::Prev => {
+                self.dynamics_params.select_prev();
+            }
+            Action::Decrease => {
+                dynamics.change(param, false);
+            }
+            Action::Increase => {
+                dynamics.change(param, true);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_delay_mode(&mut self, action: Action) {
+        let param = self.delay_params.selected_param();
+        let effects = &mut self.player.effect_manager;
+
+        match action {
+            Action::Next => {
+                self.delay_params.select_next();
+            }
+            Action::Prev => {
+                self.delay_params.select_prev();
+            }
+            Action::Decrease => {
+                effects.delay.change(param, false);
+            }
+            Action::Increase => {
+                effects.delay.change(param, true);
+            }
+            Action::Toggle => {
+                effects.toggle_effect(EffectKind::Delay);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_modulation_mode(&mut self, action: Action) {
+        let kind = self.modulation_effects.selected_param();
+        let param = self.modulation_params.selected_param();
+        let effects = &mut self.player.effect_manager;
+
+        match action {
+            Action::Next => {
+                self.modulation_effects.select_next();
+            }
+            Action::Prev => {
+                self.modulation_effects.select_prev();
+            }
+            Action::NextField => {
+                self.modulation_params.select_next();
+            }
+            Action::PrevField => {
+                self.modulation_params.select_prev();
+            }
+            Action::Decrease => {
+                if let Some(params) = effects.modulation.get_mut(kind) {
+                    params.change(param, false);
                 }
             }
-            KeyCode::Char('e') => {
-                self.player.effect_manager.toggle_reverb();
+            Action::Increase => {
+                if let Some(params) = effects.modulation.get_mut(kind) {
+                    params.change(param, true);
+                }
+            }
+            Action::Toggle => {
+                effects.toggle_effect(kind);
             }
             _ => {}
         }
-        Ok(())
     }
 
-    fn handle_volume_mode(&mut self, key_code: KeyCode) {
-        match key_code {
-            KeyCode::Char('j') => {
-                self.player.effect_manager.change_volume(false);
+    fn handle_distortion_mode(&mut self, action: Action) {
+        let param = self.distortion_params.selected_param();
+        let effects = &mut self.player.effect_manager;
+
+        match action {
+            Action::Next => {
+                self.distortion_params.select_next();
+            }
+            Action::Prev => {
+                self.distortion_params.select_prev();
             }
-            KeyCode::Char('k') => {
-                self.player.effect_manager.change_volume(true);
+            Action::Decrease => {
+                effects.distortion.change(param, false);
             }
-            KeyCode::Down => {
-                self.player.effect_manager.change_volume(false);
+            Action::Increase => {
+                effects.distortion.change(param, true);
             }
-            KeyCode::Up => {
-                self.player.effect_manager.change_volume(true);
+            Action::Toggle => {
+                // Saturation or bitcrusher, whichever the parameter belongs to
+                effects.toggle_effect(param.effect());
             }
             _ => {}
         }
     }
 
-    fn handle_pitch_mode(&mut self, key_code: KeyCode) {
-        match key_code {
-            KeyCode::Char('j') => {
-                self.player.effect_manager.change_pitch(false);
+    fn handle_synth_filter_mode(&mut self, action: Action) {
+        let param = self.filter_params.selected_param();
+        let effects = &mut self.player.effect_manager;
+
+        match action {
+            Action::Next => {
+                self.filter_params.select_next();
+            }
+            Action::Prev => {
+                self.filter_params.select_prev();
             }
-            KeyCode::Char('k') => {
-                self.player.effect_manager.change_pitch(true);
+            Action::Decrease => {
+                effects.filter.change(param, false);
             }
-            KeyCode::Down => {
-                self.player.effect_manager.change_pitch(false);
+            Action::Increase => {
+                effects.filter.change(param, true);
             }
-            KeyCode::Up => {
-                self.player.effect_manager.change_pitch(true);
+            Action::Toggle => {
+                effects.toggle_effect(EffectKind::Filter);
             }
             _ => {}
         }
     }
 
-    fn handle_filter_mode(&mut self, key_code: KeyCode) {
-        match key_code {
-            KeyCode::Char('j') => {
-                self.player.effect_manager.change_lowpass(false);
+    fn handle_messages_mode(&mut self, action: Action) {
+        let last = self.player.messages.len().saturating_sub(1);
+        match action {
+            Action::Next => self.message_selected = (self.message_selected + 1).min(last),
+            Action::Prev => self.message_selected = self.message_selected.saturating_sub(1),
+            Action::ClearMessages => {
+                self.player.messages.clear();
+                self.message_selected = 0;
             }
-            KeyCode::Char('k') => {
-                self.player.effect_manager.change_lowpass(true);
+            _ => {}
+        }
+    }
+
+    fn handle_envelope_mode(&mut self, action: Action) {
+        let param = self.envelope_params.selected_param();
+        let envelope = &mut self.player.effect_manager.amp_envelope;
+
+        match action {
+            Action::Next => {
+                self.envelope_params.select_next();
+            }
+            Action::Prev => {
+                self.envelope_params.select_prev();
+            }
+            Action::Decrease => {
+                envelope.change(param, false);
             }
-            KeyCode::Down => {
-                self.player.effect_manager.change_lowpass(false);
+            Action::Increase => {
+                envelope.change(param, true);
             }
-            KeyCode::Up => {
-                self.player.effect_manager.change_lowpass(true);
+            Action::Stop => {
+                self.player.stop_all();
             }
             _ => {}
         }
     }
 
-    fn handle_file_browser_mode(&mut self, key_code: KeyCode) -> io::Result<()> {
+    fn handle_presets_mode(&mut self, action: Action) {
+        let store = &self.player.preset_store;
+
+        // Deleting needs an explicit yes
+        if self.preset_browser.confirm_delete {
+            self.preset_browser.confirm_delete = false;
+            if action == Action::Confirm
+                && let Some(name) = self.preset_browser.selected_name(store)
+            {
+                match self.player.preset_store.delete(&name) {
+                    Ok(()) => {
+                        if self.preset_browser.current.as_deref() == Some(name.as_str()) {
+                            self.preset_browser.current = None;
+                        }
+                        self.preset_browser.selected = self
+                            .preset_browser
+                            .selected
+                            .min(self.player.preset_store.len().saturating_sub(1));
+                        self.player
+                            .messages
+                            .info(&format!("Deleted preset {}", name));
+                    }
+                    Err(e) => self
+                        .player
+                        .messages
+                        .error(&format!("Error saving presets: {}", e)),
+                }
+            } else {
+                self.player.messages.info("Delete cancelled");
+            }
+            return;
+        }
+
+        match action {
+            Action::Next => {
+                self.preset_browser.select_next(store);
+            }
+            Action::Prev => {
+                self.preset_browser.select_prev(store);
+            }
+            Action::Select => {
+                if let Some(name) = self.preset_browser.selected_name(store) {
+                    self.load_preset(&name);
+                }
+            }
+            Action::Add => {
+                self.preset_browser.start_prompt(PresetPrompt::SaveAs, "");
+            }
+            Action::Rename => {
+                if let Some(name) = self.preset_browser.selected_name(store) {
+                    self.preset_browser
+                        .start_prompt(PresetPrompt::Rename, &name);
+                }
+            }
+            Action::Remove if store.len() > 0 => {
+                self.preset_browser.confirm_delete = true;
+            }
+            Action::NextPreset => {
+                self.cycle_preset(true);
+            }
+            Action::PrevPreset => {
+                self.cycle_preset(false);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_preset_prompt(&mut self, key_code: KeyCode) {
         match key_code {
-            KeyCode::Char('j') | KeyCode::Down => {
+            KeyCode::Char(c) => {
+                self.preset_browser.input.push(c);
+            }
+            KeyCode::Backspace => {
+                self.preset_browser.input.pop();
+            }
+            KeyCode::Esc => {
+                self.preset_browser.cancel_prompt();
+            }
+            KeyCode::Enter => {
+                let name = self.preset_browser.input.trim().to_string();
+                let prompt = self.preset_browser.prompt;
+                self.preset_browser.cancel_prompt();
+                if name.is_empty() {
+                    self.player.messages.warn("Preset names can't be empty");
+                    return;
+                }
+
+                match prompt {
+                    Some(PresetPrompt::SaveAs) => self.save_preset(&name),
+                    Some(PresetPrompt::Rename) => self.rename_preset(&name),
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_command_line(&mut self, key: KeyEvent) -> io::Result<()> {
+        let line = &mut self.command_line;
+        match key.code {
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => line.clear(),
+            KeyCode::Char(c) => line.push(c),
+            // Deleting past the start closes the line, as in Vim
+            KeyCode::Backspace if line.input.is_empty() => line.close(),
+            KeyCode::Backspace => line.pop(),
+            KeyCode::Esc => line.close(),
+            KeyCode::Up => line.history_prev(),
+            KeyCode::Down => line.history_next(),
+            KeyCode::Tab | KeyCode::BackTab => {
+                let presets = self.player.preset_store.names();
+                let themes = self.config.theme_names();
+                line.complete(
+                    key.code == KeyCode::Tab,
+                    &self.file_manager,
+                    &presets,
+                    &themes,
+                );
+            }
+            KeyCode::Enter => {
+                let entered = line.submit();
+                if !entered.is_empty() {
+                    self.run_command(&entered);
+                    self.history.record(&self.player.effect_manager);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn run_command(&mut self, line: &str) {
+        let result =
+            Command::parse(line, &self.config).and_then(|command| self.execute_command(command));
+
+        if let Err(e) = &result {
+            self.player.messages.error(e);
+        }
+        self.command_line.result = Some(result);
+    }
+
+    // What the command did, or why it failed, to be shown on the command
+    // line
+    fn execute_command(&mut self, command: Command) -> Result<String, String> {
+        let effects = &mut self.player.effect_manager;
+        let dir = self.file_manager.current_dir.clone();
+
+        match command {
+            Command::Volume(volume) => {
+                effects.volume = volume;
+                Ok(format!("Volume {:.0}%", volume * 100.0))
+            }
+            Command::Speed(speed) => {
+                effects.playback_speed = speed;
+                Ok(format!("Speed {:.2}x", speed))
+            }
+            Command::Lowpass(cutoff) => {
+                effects.lowpass_cutoff = cutoff.round() as u32;
+                Ok(format!("Low-pass cutoff {} Hz", effects.lowpass_cutoff))
+            }
+            Command::Balance(balance) => {
+                effects.balance = balance;
+                Ok(format!("Balance {:+.2}", balance))
+            }
+            Command::Width(width) => {
+                effects.stereo_width = width;
+                Ok(format!("Stereo width {:.0}%", width * 100.0))
+            }
+            Command::Reverb(enable) => {
+                if enable != Some(effects.is_reverb_enabled()) {
+                    effects.toggle_reverb();
+                }
+                Ok(if effects.is_reverb_enabled() {
+                    "Reverb on".to_string()
+                } else {
+                    "Reverb off".to_string()
+                })
+            }
+            Command::Cd(path) => {
+                let path = command::resolve_path(&dir, &path);
+                if path.is_dir() {
+                    // Keep the path tidy, instead of piling up "../.."
+                    let path = path.canonicalize().unwrap_or(path);
+                    self.file_manager.change_directory(path.clone());
+                    Ok(path.display().to_string())
+                } else {
+                    Err(format!("Not a directory: {}", path.display()))
+                }
+            }
+            Command::Play(path) => self.play_path(&command::resolve_path(&dir, &path), false),
+            Command::Loop(path) => self.play_path(&command::resolve_path(&dir, &path), true),
+            Command::Stop => {
+                self.player.stop_all();
+                Ok("Stopped".to_string())
+            }
+            Command::LoopStart(secs) => self.place_loop_marker(LoopMarker::Start, secs),
+            Command::LoopEnd(secs) => self.place_loop_marker(LoopMarker::End, secs),
+            Command::Export(path, overwrite) => {
+                self.export(&command::resolve_path(&dir, &path), overwrite)
+            }
+            Command::Preset(name) => {
+                if self.player.preset_store.get(&name).is_some() {
+                    self.load_preset(&name);
+                    Ok(format!("Loaded preset {}", name))
+                } else {
+                    Err(format!("No preset named {}", name))
+                }
+            }
+            Command::Theme(name) => match self.config.theme_named(&name) {
+                Some(theme) => {
+                    self.config.theme = theme;
+                    Ok(format!("Theme {}", name))
+                }
+                None => Err(format!(
+                    "No theme named {} (known: {})",
+                    name,
+                    self.config.theme_names().join(", ")
+                )),
+            },
+            Command::Quit => {
+                self.should_quit = true;
+                Ok(String::new())
+            }
+        }
+    }
+
+    // Play a file named on the command line
+    fn play_path(&mut self, path: &Path, is_looping: bool) -> Result<String, String> {
+        if !path.is_file() {
+            return Err(format!("No such file: {}", path.display()));
+        }
+        if !self.file_manager.is_audio_file(path) {
+            return Err(format!("Not an audio file: {}", path.display()));
+        }
+
+        self.set_current_file(path);
+        self.player
+            .play_sound(path, is_looping)
+            .map(|()| format!("Playing {}", path.display()))
+            .map_err(|e| e.to_string())
+    }
+
+    // Save the current file (or the selected one) with the effects applied.
+    // An existing file is only replaced with `:export!`
+    fn export(&mut self, target: &Path, overwrite: bool) -> Result<String, String> {
+        let source = self.current_audio_path.clone().or_else(|| {
+            self.file_manager
+                .get_selected_file()
+                .filter(|path| !path.is_dir() && self.file_manager.is_audio_file(path))
+        });
+        let Some(source) = source else {
+            return Err("Play or select an audio file to export".to_string());
+        };
+
+        let target = match target.extension() {
+            None => target.with_extension("wav"),
+            Some(ext) if ext.eq_ignore_ascii_case("wav") => target.to_path_buf(),
+            Some(_) => return Err("Only WAV files can be exported".to_string()),
+        };
+        if target.exists() && !overwrite {
+            return Err(format!(
+                "{} already exists, use :export! to overwrite it",
+                target.display()
+            ));
+        }
+
+        let audio = self
+            .player
+            .load_decoded(&source)
+            .map_err(|e| e.to_string())?;
+        // Exports of a WAV keep its format, anything else becomes 16-bit
+        let format = WavFormat::of(&source).unwrap_or(WavFormat::PCM16);
+        self.player
+            .render(audio)
+            .write_wav(&target, format)
+            .map_err(|e| format!("Error saving {}: {}", target.display(), e))?;
+
+        // We may have just replaced a file we have in memory
+        self.player.forget_decoded(&target);
+        self.file_manager.refresh_files();
+        Ok(format!("Exported {}", target.display()))
+    }
+
+    fn save_preset(&mut self, name: &str) {
+        let store = &mut self.player.preset_store;
+        match store.save_as(name, &self.player.effect_manager) {
+            Ok(()) => {
+                self.preset_browser.follow(store, name);
+                self.player.messages.info(&format!("Saved preset {}", name));
+            }
+            Err(e) => self
+                .player
+                .messages
+                .error(&format!("Error saving presets: {}", e)),
+        }
+    }
+
+    fn rename_preset(&mut self, to: &str) {
+        let store = &mut self.player.preset_store;
+        let Some(from) = self.preset_browser.selected_name(store) else {
+            return;
+        };
+
+        match store.rename(&from, to) {
+            Ok(true) => {
+                let was_current = self.preset_browser.current.as_deref() == Some(from.as_str());
+                self.preset_browser.follow(store, to);
+                if !was_current {
+                    self.preset_browser.current = None;
+                }
+                self.player
+                    .messages
+                    .info(&format!("Renamed preset {} to {}", from, to));
+            }
+            Ok(false) => self
+                .player
+                .messages
+                .warn(&format!("A preset named {} already exists", to)),
+            Err(e) => self
+                .player
+                .messages
+                .error(&format!("Error saving presets: {}", e)),
+        }
+    }
+
+    fn load_preset(&mut self, name: &str) {
+        let store = &self.player.preset_store;
+        if let Some(mut effects) = store.get(name).cloned() {
+            self.preset_browser.follow(store, name);
+            // The file may have been edited by hand
+            self.config.clamp_effects(&mut effects);
+            self.player.effect_manager = effects;
+            self.clamp_chain_selection();
+            self.player
+                .messages
+                .info(&format!("Loaded preset {}", name));
+        }
+    }
+
+    // Load the next/previous preset after the current one
+    fn cycle_preset(&mut self, forward: bool) {
+        match self
+            .preset_browser
+            .cycle(&self.player.preset_store, forward)
+        {
+            Some(name) => self.load_preset(&name),
+            None => self
+                .player
+                .messages
+                .warn("No presets yet, save one with Space P then a"),
+        }
+    }
+
+    fn handle_file_browser_mode(&mut self, action: Action) -> io::Result<()> {
+        match action {
+            Action::Next => {
                 self.file_manager.select_next();
             }
-            KeyCode::Char('k') | KeyCode::Up => {
+            Action::Prev => {
                 self.file_manager.select_prev();
             }
-            KeyCode::Char('h') | KeyCode::Left => {
+            Action::Parent => {
                 self.file_manager.go_to_parent_dir();
             }
-            KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
+            Action::Select => {
                 if let Some(selected) = self.file_manager.get_selected_file() {
                     if selected.is_dir() {
                         self.file_manager.change_directory(selected);
                     } else if self.file_manager.is_audio_file(&selected) {
-                        // Set current audio file name
-                        self.current_audio_file = selected
-                            .file_name()
-                            .map(|name| name.to_string_lossy().to_string());
-
-                        // Play the selected file
-                        self.player
-                            .play_sound(selected.to_str().unwrap_or("example.wav"), false)?;
-                        // Return to normal mode
+                        // Play the selected file and return to normal mode
+                        self.play_file(&selected, false);
                         self.mode = AppMode::Normal;
                     }
                 }
             }
-            KeyCode::Char('p') => {
-                // Instead of hardcoded file, use selected file
-                if let Some(file_path) = self.file_manager.get_selected_file() {
-                    if !file_path.is_dir() && self.file_manager.is_audio_file(&file_path) {
-                        // Set current audio file name
-                        self.current_audio_file = file_path
-                            .file_name()
-                            .map(|name| name.to_string_lossy().to_string());
+            Action::Play => self.play_selected(false),
+            Action::Loop => self.play_selected(true),
+            _ => {}
+        }
+        Ok(())
+    }
 
-                        self.player
-                            .play_sound(file_path.to_str().unwrap_or("example.wav"), false)?;
-                    } else {
-                        // Fallback to example.wav if selected file is not playable
-                        self.current_audio_file = Some("example.wav".to_string());
-                        self.player.play_sound("example.wav", false)?;
-                    }
-                } else {
-                    // Fallback to example.wav if no file selected
-                    self.current_audio_file = Some("example.wav".to_string());
-                    self.player.play_sound("example.wav", false)?;
+    fn handle_loop_mode(&mut self, action: Action) -> io::Result<()> {
+        match action {
+            Action::NextField => {
+                self.loop_editor.next_marker();
+            }
+            Action::MoveDown => {
+                self.edit_loop_region(|editor, region, audio| editor.nudge(region, audio, false));
+            }
+            Action::MoveUp => {
+                self.edit_loop_region(|editor, region, audio| editor.nudge(region, audio, true));
+            }
+            Action::Decrease => {
+                self.loop_editor.change_step(false);
+            }
+            Action::Increase => {
+                self.loop_editor.change_step(true);
+            }
+            Action::SnapToZero => {
+                self.edit_loop_region(|editor, region, audio| editor.snap(region, audio));
+            }
+            Action::ClearLoop => {
+                // Back to looping the whole file
+                if let Some(path) = &self.current_audio_path
+                    && let Err(e) = self.player.loop_store.remove(path)
+                {
+                    self.player
+                        .messages
+                        .error(&format!("Could not save loop points: {}", e));
                 }
             }
-            KeyCode::Char('r') => {
-                // Loop selected file
-                if let Some(file_path) = self.file_manager.get_selected_file() {
-                    if !file_path.is_dir() && self.file_manager.is_audio_file(&file_path) {
-                        // Set current audio file name
-                        self.current_audio_file = file_path
-                            .file_name()
-                            .map(|name| name.to_string_lossy().to_string());
+            Action::Audition => {
+                // Audition the loop
+                if let Some(path) = self.current_audio_path.clone() {
+                    self.player.stop_all();
+                    self.play_file(&path, true);
+                }
+            }
+            Action::Stop => {
+                self.player.stop_all();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 
-                        self.player
-                            .play_sound(file_path.to_str().unwrap_or("example.wav"), true)?;
-                    } else {
-                        // Fallback to example.wav if selected file is not playable
-                        self.current_audio_file = Some("example.wav".to_string());
-                        self.player.play_sound("example.wav", true)?;
+    // Put a loop marker of the current file at a time typed in
+    fn place_loop_marker(&mut self, marker: LoopMarker, secs: f32) -> Result<String, String> {
+        let Some(path) = self.current_audio_path.clone() else {
+            return Err("Play or select an audio file to set its loop".to_string());
+        };
+        let audio = self.player.load_decoded(&path).map_err(|e| e.to_string())?;
+        let length = audio.frames_to_secs(audio.frames());
+        if secs > length {
+            return Err(format!("The file is only {:.3}s long", length));
+        }
+
+        self.edit_loop_region(|_, region, audio| {
+            region.with_marker(marker, audio.secs_to_frames(secs), audio.frames())
+        });
+        let name = match marker {
+            LoopMarker::End => "end",
+            _ => "start",
+        };
+        Ok(format!("Loop {} at {:.3}s", name, secs))
+    }
+
+    // Apply an edit to the current file's loop region and save it
+    fn edit_loop_region(
+        &mut self,
+        edit: impl FnOnce(&LoopEditor, LoopRegion, &DecodedAudio) -> LoopRegion,
+    ) {
+        let Some(path) = self.current_audio_path.clone() else {
+            return;
+        };
+
+        let audio = match self.player.load_decoded(&path) {
+            Ok(audio) => audio,
+            Err(e) => {
+                self.player.messages.error(&e.to_string());
+                return;
+            }
+        };
+
+        let region = self
+            .player
+            .loop_store
+            .get(&path)
+            .unwrap_or(LoopRegion::whole(audio.frames()));
+        let region = edit(&self.loop_editor, region, &audio);
+
+        if let Err(e) = self.player.loop_store.set(&path, region) {
+            self.player
+                .messages
+                .error(&format!("Could not save loop points: {}", e));
+        }
+    }
+
+    fn handle_edit_mode(&mut self, action: Action) {
+        // Overwriting the original needs an explicit yes
+        if self.sample_editor.confirm_overwrite {
+            self.sample_editor.confirm_overwrite = false;
+            if action == Action::Confirm {
+                self.overwrite_original();
+            } else {
+                self.player.messages.info("Overwrite cancelled");
+            }
+            return;
+        }
+
+        match action {
+            Action::Next => {
+                self.sample_editor.select_next();
+            }
+            Action::Prev => {
+                self.sample_editor.select_prev();
+            }
+            Action::Decrease => {
+                self.change_edit_param(false);
+            }
+            Action::Increase => {
+                self.change_edit_param(true);
+            }
+            Action::Preview => {
+                // Preview the edit
+                if let Some(audio) = self.edited_audio() {
+                    self.player.stop_all();
+                    if let Err(e) = self.player.play_buffer(audio) {
+                        self.player.messages.error(&e.to_string());
+                    }
+                }
+            }
+            Action::Stop => {
+                self.player.stop_all();
+            }
+            Action::SaveCopy => {
+                // Save as a new file next to the original
+                if let (Some(path), Some(audio)) =
+                    (self.sample_editor.file.clone(), self.edited_audio())
+                {
+                    let target = editor::save_as_path(&path);
+                    // A copy of a WAV keeps its format, anything else becomes 16-bit
+                    let format = WavFormat::of(&path).unwrap_or(WavFormat::PCM16);
+                    match audio.write_wav(&target, format) {
+                        Ok(()) => {
+                            self.player
+                                .messages
+                                .info(&format!("Saved {}", target.display()));
+                            self.file_manager.refresh_files();
+                        }
+                        Err(e) => self.player.messages.error(&format!(
+                            "Error saving {}: {}",
+                            target.display(),
+                            e
+                        )),
                     }
+                }
+            }
+            Action::Overwrite => {
+                let is_wav = self.sample_editor.file.as_ref().is_some_and(|path| {
+                    path.extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"))
+                });
+                if is_wav {
+                    self.sample_editor.confirm_overwrite = true;
                 } else {
-                    // Fallback to example.wav if no file selected
-                    self.current_audio_file = Some("example.wav".to_string());
-                    self.player.play_sound("example.wav", true)?;
+                    self.player
+                        .messages
+                        .warn("Only WAV files can be overwritten, use w to save a copy");
                 }
             }
             _ => {}
         }
-        Ok(())
+    }
+
+    fn change_edit_param(&mut self, increase: bool) {
+        let Some(path) = self.sample_editor.file.clone() else {
+            return;
+        };
+
+        match self.player.load_decoded(&path) {
+            Ok(audio) => {
+                let duration = audio.frames_to_secs(audio.frames());
+                let param = self.sample_editor.selected_param();
+                self.sample_editor
+                    .settings
+                    .change(param, increase, duration);
+            }
+            Err(e) => self.player.messages.error(&e.to_string()),
+        }
+    }
+
+    // The file being edited with the current edit settings applied
+    fn edited_audio(&mut self) -> Option<DecodedAudio> {
+        let path = self.sample_editor.file.clone()?;
+
+        match self.player.load_decoded(&path) {
+            Ok(audio) => Some(self.sample_editor.settings.render(&audio)),
+            Err(e) => {
+                self.player.messages.error(&e.to_string());
+                None
+            }
+        }
+    }
+
+    fn overwrite_original(&mut self) {
+        let Some(path) = self.sample_editor.file.clone() else {
+            return;
+        };
+        let Some(audio) = self.edited_audio() else {
+            return;
+        };
+
+        // Written in the original's own format, so nothing is lost
+        let written = WavFormat::of(&path).and_then(|format| audio.write_wav(&path, format));
+        match written {
+            Ok(()) => {
+                self.player.forget_decoded(&path);
+                // The old loop points may no longer fit the edited file
+                if let Err(e) = self.player.loop_store.remove(&path) {
+                    self.player
+                        .messages
+                        .error(&format!("Could not save loop points: {}", e));
+                }
+                self.sample_editor.settings = editor::EditSettings::new();
+                self.player
+                    .messages
+                    .info(&format!("Overwrote {}", path.display()));
+            }
+            Err(e) => {
+                self.player
+                    .messages
+                    .error(&format!("Error saving {}: {}", path.display(), e))
+            }
+        }
+    }
+
+    fn handle_chain_mode(&mut self, action: Action) {
+        let effects = &mut self.player.effect_manager;
+        let editor = &mut self.chain_editor;
+
+        match action {
+            Action::Next => editor.select_next(effects),
+            Action::Prev => editor.select_prev(effects),
+            Action::MoveDown => editor.move_selected(effects, false),
+            Action::MoveUp => editor.move_selected(effects, true),
+            Action::Decrease => editor.change_candidate(effects, false),
+            Action::Increase => editor.change_candidate(effects, true),
+            Action::Add => editor.add(effects),
+            Action::Remove => editor.remove(effects),
+            Action::Toggle => effects.toggle_bypass(editor.selected),
+            _ => {}
+        }
+    }
+
+    fn handle_mixer_mode(&mut self, action: Action) {
+        let count = self.player.voices.len();
+        if count == 0 {
+            return;
+        }
+        let index = self.mixer_selected_index().unwrap_or(0);
+
+        match action {
+            Action::Next => {
+                self.mixer_selected = Some(self.player.voices[(index + 1) % count].id);
+            }
+            Action::Prev => {
+                self.mixer_selected = Some(self.player.voices[(index + count - 1) % count].id);
+            }
+            Action::Decrease => {
+                self.player.voices[index].change_pan(false);
+            }
+            Action::Increase => {
+                self.player.voices[index].change_pan(true);
+            }
+            Action::GainUp => {
+                self.player.voices[index].change_gain(true);
+            }
+            Action::GainDown => {
+                self.player.voices[index].change_gain(false);
+            }
+            Action::Mute => {
+                let voice = &mut self.player.voices[index];
+                voice.muted = !voice.muted;
+            }
+            Action::Solo => {
+                let voice = &mut self.player.voices[index];
+                voice.solo = !voice.solo;
+            }
+            Action::Remove => {
+                self.player.stop_voice(index);
+            }
+            _ => {}
+        }
+    }
+
+    // Index of the selected mixer voice, falling back to the first one
+    pub fn mixer_selected_index(&self) -> Option<usize> {
+        let voices = &self.player.voices;
+        self.mixer_selected
+            .and_then(|id| voices.iter().position(|voice| voice.id == id))
+            .or(if voices.is_empty() { None } else { Some(0) })
+    }
+
+    fn handle_record_mode(&mut self, action: Action) {
+        let result = match action {
+            Action::CycleInput => self.player.recorder.next_input(),
+            Action::Arm => {
+                // Toggle metering the input without writing anything
+                if self.player.recorder.state == RecorderState::Idle {
+                    let fake_file = self.fake_input_file();
+                    self.player
+                        .recorder
+                        .arm(&self.player.tap, fake_file.as_deref())
+                } else {
+                    self.player.recorder.disarm()
+                }
+            }
+            Action::Record => {
+                let fake_file = self.fake_input_file();
+                self.player.recorder.record(
+                    &self.file_manager.current_dir,
+                    &self.player.tap,
+                    fake_file.as_deref(),
+                )
+            }
+            Action::Stop => match self.player.recorder.stop() {
+                Ok(Some(path)) => {
+                    self.player
+                        .messages
+                        .info(&format!("Recorded {}", path.display()));
+                    self.file_manager.refresh_files();
+                    Ok(())
+                }
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            },
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            self.player
+                .messages
+                .error(&format!("Recording error: {}", e));
+        }
+    }
+
+    // The fake input plays $AUDIRUST_FAKE_INPUT, or else the current file
+    fn fake_input_file(&self) -> Option<PathBuf> {
+        std::env::var_os("AUDIRUST_FAKE_INPUT")
+            .map(PathBuf::from)
+            .or_else(|| self.current_audio_path.clone())
     }
 
     pub fn update(&mut self) {
         self.player.cleanup_finished();
-        self.player.update_looping_sounds();
         self.player.update();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::parse_keys;
+
+    fn app_in(mode: AppMode) -> App {
+        let mut app = App::new(None, Config::new(), Vec::new());
+        app.mode = mode;
+        app
+    }
+
+    fn press(app: &mut App, keys: &str) {
+        for chord in parse_keys(keys).unwrap() {
+            app.handle_key_events(KeyEvent::new(chord.code, chord.modifiers))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn counts_repeat_actions() {
+        let mut app = app_in(AppMode::Dynamics);
+        press(&mut app, "3j");
+        assert_eq!(app.dynamics_params.selected, 3);
+        assert_eq!(app.count, None);
+
+        // Counts are capped, and an unbound key throws them away
+        press(&mut app, "123456");
+        assert_eq!(app.count, Some(MAX_COUNT));
+        press(&mut app, "!");
+        assert_eq!(app.count, None);
+
+        let mut app = app_in(AppMode::Volume);
+        let volume = app.player.effect_manager.volume;
+        press(&mut app, "3j");
+        let step = app.config.volume.step;
+        assert!((app.player.effect_manager.volume - (volume - 3.0 * step)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn jumps_to_lines() {
+        let mut app = app_in(AppMode::Dynamics);
+        let last = DYNAMICS_PARAMS.len() - 1;
+        press(&mut app, "G");
+        assert_eq!(app.dynamics_params.selected, last);
+        press(&mut app, "gg");
+        assert_eq!(app.dynamics_params.selected, 0);
+        // A count is the line to go to, past the end is the last one
+        press(&mut app, "4gg");
+        assert_eq!(app.dynamics_params.selected, 3);
+        press(&mut app, "2G");
+        assert_eq!(app.dynamics_params.selected, 1);
+        press(&mut app, "99G");
+        assert_eq!(app.dynamics_params.selected, last);
+    }
+
+    #[test]
+    fn pages_by_half_the_panel() {
+        let mut app = app_in(AppMode::Dynamics);
+        app.screen = Rect::new(0, 0, 80, 40);
+        let page = app.page_size();
+        assert!(page > 1 && page < DYNAMICS_PARAMS.len());
+
+        press(&mut app, "<C-d>");
+        assert_eq!(app.dynamics_params.selected, page);
+        press(&mut app, "<C-u>");
+        assert_eq!(app.dynamics_params.selected, 0);
+        press(&mut app, "2<C-d>");
+        assert_eq!(app.dynamics_params.selected, DYNAMICS_PARAMS.len() - 1);
+    }
+
+    #[test]
+    fn exports_over_a_file_only_when_told_to() {
+        let dir = std::env::temp_dir().join(format!("audirust-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("out.wav");
+        std::fs::write(&target, "not a wav").unwrap();
+
+        let mut app = app_in(AppMode::Normal);
+        let example = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/example.wav");
+        app.set_current_file(&example);
+        let dir = dir.display().to_string();
+
+        let result = app.execute_command(Command::Export(format!("{}/out", dir), false));
+        assert!(result.unwrap_err().contains("already exists"));
+        assert_eq!(std::fs::read(&target).unwrap(), b"not a wav");
+
+        let result = app.execute_command(Command::Export(format!("{}/out", dir), true));
+        assert!(result.is_ok(), "{:?}", result);
+        // In the format of the file it came from
+        assert_eq!(
+            WavFormat::of(&target).unwrap(),
+            WavFormat::of(&example).unwrap()
+        );
+    }
+
+    #[test]
+    fn repeats_the_last_action() {
+        let mut app = app_in(AppMode::Dynamics);
+        press(&mut app, "2j");
+        press(&mut app, ".");
+        assert_eq!(app.dynamics_params.selected, 4);
+        // A new count replaces the old one
+        press(&mut app, "3.");
+        assert_eq!(app.dynamics_params.selected, 7);
+
+        // Getting around isn't repeated
+        press(&mut app, "<Esc>");
+        assert_eq!(app.mode, AppMode::Normal);
+        app.mode = AppMode::Dynamics;
+        press(&mut app, "k.");
+        assert_eq!(app.dynamics_params.selected, 5);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::audio_player::chain::SharedEffects;
+use crate::audio_player::dynamics::{Compressor, DynamicsMeter, Gate, Limiter};
+use crate::audio_player::tap::{OutputTap, SharedTap};
+use rodio::{
+    OutputStreamHandle, PlayError, Sink, Source,
+    dynamic_mixer::{DynamicMixerController, mixer},
+    source::Zero,
+};
+use std::sync::Arc;
+
+// Everything that plays is mixed here, then gated, compressed and limited
+// on its way to the output
+pub struct MasterBus {
+    controller: Arc<DynamicMixerController<f32>>,
+    pub meter: Arc<DynamicsMeter>,
+    // Dropping the sink would stop the output
+    _sink: Sink,
+}
+
+impl MasterBus {
+    pub fn new(
+        stream_handle: &OutputStreamHandle,
+        shared: Arc<SharedEffects>,
+        tap: SharedTap,
+    ) -> Result<Self, PlayError> {
+        let sink = Sink::try_new(stream_handle)?;
+        let (controller, mixer) = mixer::<f32>(2, 44100);
+
+        // The mixer ends once it runs out of sources, so keep silence in it
+        controller.add(Zero::<f32>::new(2, 44100));
+
+        let meter = DynamicsMeter::new();
+        let gate = Gate::new(mixer, Arc::clone(&shared), Arc::clone(&meter));
+        let compressor = Compressor::new(gate, Arc::clone(&shared), Arc::clone(&meter));
+        let limiter = Limiter::new(compressor, shared, Arc::clone(&meter));
+        sink.append(OutputTap::new(limiter, tap));
+
+        Ok(Self {
+            controller,
+            meter,
+            _sink: sink,
+        })
+    }
+
+    pub fn add<S>(&self, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        self.controller.add(source);
+    }
+}
@@ -0,0 +1,384 @@
+use crate::audio_player::effects::{Effect, EffectKind, EffectManager};
+use rodio::Source;
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+// How often (in frames) a playing chain looks for new settings, ~6ms
+const CHECK_INTERVAL: usize = 256;
+
+// Effect settings shared with every playing sound, so changes are heard
+// right away instead of on the next play
+pub struct SharedEffects {
+    settings: Mutex<EffectManager>,
+    generation: AtomicU64,
+}
+
+impl SharedEffects {
+    pub fn new(settings: &EffectManager) -> Arc<Self> {
+        Arc::new(Self {
+            settings: Mutex::new(settings.clone()),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    pub fn publish(&self, settings: &EffectManager) {
+        if let Ok(mut current) = self.settings.lock() {
+            *current = settings.clone();
+            self.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    // Look at the settings if they changed since `seen`. Never blocks the
+    // audio thread, if they're being written we'll just try again next time
+    pub fn read_if_changed<R>(
+        &self,
+        seen: &mut Option<u64>,
+        read: impl FnOnce(&EffectManager) -> R,
+    ) -> Option<R> {
+        let generation = self.generation.load(Ordering::Acquire);
+        if *seen == Some(generation) {
+            return None;
+        }
+
+        let settings = self.settings.try_lock().ok()?;
+        *seen = Some(generation);
+        Some(read(&settings))
+    }
+}
+
+struct ChainUnit {
+    kind: EffectKind,
+    bypassed: bool,
+    effect: Box<dyn Effect>,
+}
+
+// Runs a source through the effect chain of the EffectManager
+pub struct ChainSource<S> {
+    input: S,
+    shared: Arc<SharedEffects>,
+    generation: Option<u64>,
+    units: Vec<ChainUnit>,
+    channels: u16,
+    sample_rate: u32,
+    frame: Vec<f32>,
+    position: usize,
+    frames_until_check: usize,
+    // Frames of silence still to feed through after the input ended
+    tail_left: Option<usize>,
+}
+
+impl<S> ChainSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, shared: Arc<SharedEffects>) -> Self {
+        let channels = input.channels().max(1);
+        let sample_rate = input.sample_rate();
+
+        let mut chain = Self {
+            input,
+            shared,
+            generation: None,
+            units: Vec::new(),
+            channels,
+            sample_rate,
+            frame: Vec::with_capacity(channels as usize),
+            position: 0,
+            frames_until_check: 0,
+            tail_left: None,
+        };
+        chain.sync_settings();
+        chain
+    }
+
+    // Rebuild/reconfigure the units if the settings changed since last time
+    fn sync_settings(&mut self) {
+        self.shared
+            .read_if_changed(&mut self.generation, |settings| {
+                // Keep existing units (and their state) for effects still in the chain
+                let mut old_units = std::mem::take(&mut self.units);
+                for slot in &settings.chain {
+                    let mut effect = match old_units.iter().position(|unit| unit.kind == slot.kind)
+                    {
+                        Some(index) => old_units.remove(index).effect,
+                        None => slot.kind.build(self.channels, self.sample_rate),
+                    };
+                    effect.configure(settings);
+
+                    self.units.push(ChainUnit {
+                        kind: slot.kind,
+                        bypassed: slot.bypassed,
+                        effect,
+                    });
+                }
+            });
+    }
+
+    // Move the envelopes in the chain on to their release
+    pub fn release(&mut self) {
+        for unit in &mut self.units {
+            unit.effect.release();
+        }
+    }
+
+    // Pull the next frame from the input and run it through the chain
+    fn next_frame(&mut self) -> bool {
+        if self.frames_until_check == 0 {
+            self.sync_settings();
+            self.frames_until_check = CHECK_INTERVAL;
+        }
+        self.frames_until_check -= 1;
+
+        self.frame.clear();
+        if self.tail_left.is_none() {
+            for _ in 0..self.channels {
+                match self.input.next() {
+                    Some(sample) => self.frame.push(sample),
+                    None => break,
+                }
+            }
+
+            if self.frame.len() < self.channels as usize {
+                // Input is done, let delays and the like ring out
+                self.release();
+                let tail = self
+                    .units
+                    .iter()
+                    .filter(|unit| !unit.bypassed)
+                    .map(|unit| unit.effect.tail())
+                    .max()
+                    .unwrap_or(0);
+                self.tail_left = Some(tail);
+            }
+        }
+
+        if let Some(tail_left) = &mut self.tail_left {
+            if *tail_left == 0 {
+                return false;
+            }
+            *tail_left -= 1;
+            self.frame.resize(self.channels as usize, 0.0);
+        }
+
+        for unit in self.units.iter_mut().filter(|unit| !unit.bypassed) {
+            unit.effect.process(&mut self.frame);
+        }
+        self.position = 0;
+        true
+    }
+}
+
+impl<S> Iterator for ChainSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.frame.len() && !self.next_frame() {
+            return None;
+        }
+
+        let sample = self.frame[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for ChainSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// Chain editor state: the selected slot and which effect `a` would add
+pub struct ChainEditor {
+    pub selected: usize,
+    pub candidate: usize,
+}
+
+impl ChainEditor {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            candidate: 0,
+        }
+    }
+
+    pub fn select_next(&mut self, effects: &EffectManager) {
+        if !effects.chain.is_empty() {
+            self.selected = (self.selected + 1) % effects.chain.len();
+        }
+    }
+
+    pub fn select_prev(&mut self, effects: &EffectManager) {
+        if !effects.chain.is_empty() {
+            self.selected = (self.selected + effects.chain.len() - 1) % effects.chain.len();
+        }
+    }
+
+    pub fn change_candidate(&mut self, effects: &EffectManager, forward: bool) {
+        let available = effects.available_effects().len();
+        if available == 0 {
+            return;
+        }
+        self.candidate = if forward {
+            (self.candidate + 1) % available
+        } else {
+            (self.candidate + available - 1) % available
+        };
+    }
+
+    pub fn candidate(&self, effects: &EffectManager) -> Option<EffectKind> {
+        let available = effects.available_effects();
+        available
+            .get(self.candidate.min(available.len().saturating_sub(1)))
+            .copied()
+    }
+
+    // Insert the candidate right after the selected slot
+    pub fn add(&mut self, effects: &mut EffectManager) {
+        if let Some(kind) = self.candidate(effects) {
+            let index = if effects.chain.is_empty() {
+                0
+            } else {
+                self.selected + 1
+            };
+            effects.add_effect(kind, index);
+            self.selected = index.min(effects.chain.len().saturating_sub(1));
+            self.candidate = 0;
+        }
+    }
+
+    pub fn remove(&mut self, effects: &mut EffectManager) {
+        effects.remove_effect(self.selected);
+        self.selected = self.selected.min(effects.chain.len().saturating_sub(1));
+    }
+
+    pub fn move_selected(&mut self, effects: &mut EffectManager, up: bool) {
+        self.selected = effects.move_effect(self.selected, up);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_player::effects::ChainSlot;
+    use rodio::buffer::SamplesBuffer;
+
+    fn gain_only(volume: f32, bypassed: bool) -> EffectManager {
+        let mut effects = EffectManager::new();
+        effects.volume = volume;
+        effects.chain = vec![ChainSlot {
+            kind: EffectKind::Gain,
+            bypassed,
+        }];
+        effects
+    }
+
+    fn ones(frames: usize) -> SamplesBuffer<f32> {
+        SamplesBuffer::new(2, 44100, vec![1.0; frames * 2])
+    }
+
+    #[test]
+    fn reads_settings_once_per_change() {
+        let shared = SharedEffects::new(&gain_only(0.5, false));
+        let mut seen = None;
+
+        assert_eq!(shared.read_if_changed(&mut seen, |e| e.volume), Some(0.5));
+        assert_eq!(shared.read_if_changed(&mut seen, |e| e.volume), None);
+
+        shared.publish(&gain_only(0.25, false));
+        assert_eq!(shared.read_if_changed(&mut seen, |e| e.volume), Some(0.25));
+        assert_eq!(shared.read_if_changed(&mut seen, |e| e.volume), None);
+    }
+
+    #[test]
+    fn runs_the_chain_and_skips_bypassed_effects() {
+        let shared = SharedEffects::new(&gain_only(0.5, false));
+        let output: Vec<f32> = ChainSource::new(ones(100), shared).collect();
+        assert_eq!(output.len(), 200);
+        assert!(output.iter().all(|&s| s == 0.5));
+
+        let shared = SharedEffects::new(&gain_only(0.5, true));
+        let output: Vec<f32> = ChainSource::new(ones(100), shared).collect();
+        assert!(output.iter().all(|&s| s == 1.0));
+    }
+
+    #[test]
+    fn picks_up_published_changes_while_playing() {
+        let shared = SharedEffects::new(&gain_only(0.5, false));
+        let mut chain = ChainSource::new(ones(4 * CHECK_INTERVAL), shared.clone());
+        assert_eq!(chain.next(), Some(0.5));
+
+        shared.publish(&gain_only(0.25, false));
+        // Heard within one check interval
+        let rest: Vec<f32> = chain.by_ref().collect();
+        let frames = CHECK_INTERVAL * 2;
+        assert!(rest[frames..].iter().all(|&s| s == 0.25));
+    }
+
+    #[test]
+    fn edits_the_chain() {
+        let mut effects = EffectManager::new();
+        let mut editor = ChainEditor::new();
+        let kinds = |effects: &EffectManager| -> Vec<EffectKind> {
+            effects.chain.iter().map(|slot| slot.kind).collect()
+        };
+        let before = kinds(&effects);
+
+        // The candidate cycles through what isn't in the chain yet
+        let first = editor.candidate(&effects).unwrap();
+        assert!(!before.contains(&first));
+        editor.change_candidate(&effects, false);
+        let last = *effects.available_effects().last().unwrap();
+        assert_eq!(editor.candidate(&effects), Some(last));
+        editor.change_candidate(&effects, true);
+
+        // Added right after the selected slot, which follows it
+        editor.add(&mut effects);
+        assert_eq!(effects.chain[1].kind, first);
+        assert_eq!(editor.selected, 1);
+        assert!(!effects.available_effects().contains(&first));
+
+        editor.move_selected(&mut effects, true);
+        assert_eq!(effects.chain[0].kind, first);
+        assert_eq!(editor.selected, 0);
+        // Nowhere further up to go
+        editor.move_selected(&mut effects, true);
+        assert_eq!(editor.selected, 0);
+
+        editor.remove(&mut effects);
+        assert_eq!(kinds(&effects), before);
+
+        // Removing the last slot keeps the selection in range
+        editor.selected = effects.chain.len() - 1;
+        editor.remove(&mut effects);
+        assert_eq!(editor.selected, effects.chain.len() - 1);
+
+        editor.select_next(&effects);
+        assert_eq!(editor.selected, 0);
+        editor.select_prev(&effects);
+        assert_eq!(editor.selected, effects.chain.len() - 1);
+    }
+}
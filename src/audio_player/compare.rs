@@ -0,0 +1,136 @@
+use crate::audio_player::effects::EffectManager;
+use crate::audio_player::tap::SharedTap;
+
+// Output below this (about -60 dB) doesn't count towards a slot's loudness
+const MIN_RMS: f32 = 0.001;
+// Weight of the newest block in the running loudness of a slot
+const SMOOTHING: f32 = 0.05;
+// Updates to ignore after switching, while the old slot's sound (and its
+// reverb or delay tail) is still in the output
+const SETTLE_UPDATES: u32 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbSlot {
+    A,
+    B,
+}
+
+impl AbSlot {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AbSlot::A => "A",
+            AbSlot::B => "B",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            AbSlot::A => 0,
+            AbSlot::B => 1,
+        }
+    }
+
+    pub fn other(&self) -> Self {
+        match self {
+            AbSlot::A => AbSlot::B,
+            AbSlot::B => AbSlot::A,
+        }
+    }
+}
+
+// Two complete effect settings to flip between. The active one lives in
+// the player's EffectManager, the other one is kept here
+pub struct AbCompare {
+    pub active: AbSlot,
+    inactive: Option<EffectManager>,
+    // Turn the louder slot down to the level of the quieter one
+    pub loudness_match: bool,
+    // Running RMS of each slot's output, with our own trim taken out
+    levels: [Option<f32>; 2],
+    last_written: u64,
+    settle: u32,
+}
+
+impl AbCompare {
+    pub fn new() -> Self {
+        Self {
+            active: AbSlot::A,
+            inactive: None,
+            loudness_match: true,
+            levels: [None, None],
+            last_written: 0,
+            settle: 0,
+        }
+    }
+
+    // Comparing starts with the first switch
+    pub fn is_active(&self) -> bool {
+        self.inactive.is_some()
+    }
+
+    // Swap the settings with the other slot. The first time round, B starts
+    // out as a copy of A
+    pub fn switch(&mut self, effects: &mut EffectManager) {
+        let other = self.inactive.take().unwrap_or_else(|| effects.clone());
+        self.inactive = Some(std::mem::replace(effects, other));
+        self.active = self.active.other();
+        self.settle = SETTLE_UPDATES;
+    }
+
+    // Make the other slot a copy of the active one
+    pub fn copy_to_other(&mut self, effects: &EffectManager) {
+        self.inactive = Some(effects.clone());
+        self.levels[self.active.other().index()] = self.levels[self.active.index()];
+    }
+
+    // Gain for the active slot, so it's no louder than the other one
+    pub fn trim(&self) -> f32 {
+        if !self.loudness_match || !self.is_active() {
+            return 1.0;
+        }
+
+        match (
+            self.levels[self.active.index()],
+            self.levels[self.active.other().index()],
+        ) {
+            (Some(active), Some(other)) if active > other => other / active,
+            _ => 1.0,
+        }
+    }
+
+    pub fn trim_db(&self) -> f32 {
+        20.0 * self.trim().log10()
+    }
+
+    // Follow the loudness of whatever slot is playing
+    pub fn update(&mut self, tap: &SharedTap, is_playing: bool) {
+        let samples = match tap.lock() {
+            Ok(buffer) => {
+                let (samples, written) = buffer.since(self.last_written);
+                self.last_written = written;
+                samples
+            }
+            Err(_) => return,
+        };
+
+        if !self.is_active() || !is_playing || samples.is_empty() {
+            return;
+        }
+        if self.settle > 0 {
+            self.settle -= 1;
+            return;
+        }
+
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        if rms < MIN_RMS {
+            return;
+        }
+
+        let level = rms / self.trim();
+        let slot = &mut self.levels[self.active.index()];
+        *slot = Some(match *slot {
+            Some(previous) => previous + (level - previous) * SMOOTHING,
+            None => level,
+        });
+    }
+}
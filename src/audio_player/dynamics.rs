@@ -0,0 +1,767 @@
+use crate::audio_player::chain::SharedEffects;
+use crate::audio_player::effects::limit;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+// How often (in frames) a stage looks for new settings, ~6ms
+const CHECK_INTERVAL: usize = 256;
+// How far the limiter looks ahead, so it's already down when a peak arrives
+const LIMITER_LOOKAHEAD: f32 = 0.0015;
+const LIMITER_RELEASE: f32 = 0.05;
+const GATE_ATTACK: f32 = 0.001;
+const GATE_HOLD: f32 = 0.05;
+const GATE_RELEASE: f32 = 0.08;
+// Gain reduction meter falloff per update (~60 per second)
+const METER_DECAY: f32 = 0.9;
+
+// The parameters, in the order they're listed in Dynamics mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicsParam {
+    Limiter,
+    Ceiling,
+    Compressor,
+    Threshold,
+    Ratio,
+    Attack,
+    Release,
+    Makeup,
+    Gate,
+    GateThreshold,
+}
+
+pub const DYNAMICS_PARAMS: [DynamicsParam; 10] = [
+    DynamicsParam::Limiter,
+    DynamicsParam::Ceiling,
+    DynamicsParam::Compressor,
+    DynamicsParam::Threshold,
+    DynamicsParam::Ratio,
+    DynamicsParam::Attack,
+    DynamicsParam::Release,
+    DynamicsParam::Makeup,
+    DynamicsParam::Gate,
+    DynamicsParam::GateThreshold,
+];
+
+// Master bus dynamics. Levels are in dB, times in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DynamicsSettings {
+    pub limiter: bool,
+    pub ceiling: f32,
+    pub compressor: bool,
+    pub threshold: f32,
+    pub ratio: f32,
+    pub attack: f32,
+    pub release: f32,
+    pub makeup: f32,
+    pub gate: bool,
+    pub gate_threshold: f32,
+}
+
+impl DynamicsSettings {
+    pub fn new() -> Self {
+        Self {
+            // The limiter is what keeps several sounds at 2x from clipping
+            limiter: true,
+            ceiling: -0.3,
+            compressor: false,
+            threshold: -18.0,
+            ratio: 4.0,
+            attack: 10.0,
+            release: 100.0,
+            makeup: 0.0,
+            gate: false,
+            gate_threshold: -50.0,
+        }
+    }
+
+    pub fn describe(&self, param: DynamicsParam) -> (&'static str, String) {
+        match param {
+            DynamicsParam::Limiter => ("Limiter", on_off(self.limiter)),
+            DynamicsParam::Ceiling => ("  Ceiling", format!("{:.1} dB", self.ceiling)),
+            DynamicsParam::Compressor => ("Compressor", on_off(self.compressor)),
+            DynamicsParam::Threshold => ("  Threshold", format!("{:.0} dB", self.threshold)),
+            DynamicsParam::Ratio => ("  Ratio", format!("{:.1}:1", self.ratio)),
+            DynamicsParam::Attack => ("  Attack", format!("{:.1} ms", self.attack)),
+            DynamicsParam::Release => ("  Release", format!("{:.0} ms", self.release)),
+            DynamicsParam::Makeup => ("  Makeup", format!("{:+.1} dB", self.makeup)),
+            DynamicsParam::Gate => ("Noise gate", on_off(self.gate)),
+            DynamicsParam::GateThreshold => {
+                ("  Threshold", format!("{:.0} dB", self.gate_threshold))
+            }
+        }
+    }
+
+    pub fn change(&mut self, param: DynamicsParam, increase: bool) {
+        let step = |value: f32, step: f32, min: f32, max: f32| {
+            if increase {
+                (value + step).min(max)
+            } else {
+                (value - step).max(min)
+            }
+        };
+        // Times move in ratios so both short and long ones are reachable
+        let scale = |value: f32, min: f32, max: f32| {
+            if increase {
+                (value * 1.25).min(max)
+            } else {
+                (value / 1.25).max(min)
+            }
+        };
+
+        match param {
+            DynamicsParam::Limiter => self.limiter = !self.limiter,
+            DynamicsParam::Ceiling => self.ceiling = step(self.ceiling, 0.1, -12.0, 0.0),
+            DynamicsParam::Compressor => self.compressor = !self.compressor,
+            DynamicsParam::Threshold => self.threshold = step(self.threshold, 1.0, -60.0, 0.0),
+            DynamicsParam::Ratio => self.ratio = step(self.ratio, 0.5, 1.0, 20.0),
+            DynamicsParam::Attack => self.attack = scale(self.attack, 0.1, 200.0),
+            DynamicsParam::Release => self.release = scale(self.release, 5.0, 2000.0),
+            DynamicsParam::Makeup => self.makeup = step(self.makeup, 0.5, 0.0, 24.0),
+            DynamicsParam::Gate => self.gate = !self.gate,
+            DynamicsParam::GateThreshold => {
+                self.gate_threshold = step(self.gate_threshold, 1.0, -90.0, -10.0)
+            }
+        }
+    }
+
+    pub fn clamp(&mut self) {
+        self.ceiling = limit(self.ceiling, -12.0, 0.0);
+        self.threshold = limit(self.threshold, -60.0, 0.0);
+        self.ratio = limit(self.ratio, 1.0, 20.0);
+        self.attack = limit(self.attack, 0.1, 200.0);
+        self.release = limit(self.release, 5.0, 2000.0);
+        self.makeup = limit(self.makeup, 0.0, 24.0);
+        self.gate_threshold = limit(self.gate_threshold, -90.0, -10.0);
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value { "On" } else { "Off" }.to_string()
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(0.000_001).log10()
+}
+
+// One-pole smoothing coefficient for a time constant in seconds
+fn smoothing(seconds: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (seconds.max(0.000_01) * sample_rate as f32)).exp()
+}
+
+// Gain reduction (in dB) written by the audio thread. Each stage keeps the
+// highest value until the UI takes it
+pub struct DynamicsMeter {
+    gate: AtomicU32,
+    compressor: AtomicU32,
+    limiter: AtomicU32,
+}
+
+impl DynamicsMeter {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            gate: AtomicU32::new(0),
+            compressor: AtomicU32::new(0),
+            limiter: AtomicU32::new(0),
+        })
+    }
+
+    // Bits of positive floats sort like the floats, so fetch_max works
+    fn record(value: &AtomicU32, reduction: f32) {
+        value.fetch_max(reduction.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn take(value: &AtomicU32) -> f32 {
+        f32::from_bits(value.swap(0, Ordering::Relaxed))
+    }
+}
+
+// Gain reduction as shown on the meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GainReduction {
+    pub gate: f32,
+    pub compressor: f32,
+    pub limiter: f32,
+}
+
+impl GainReduction {
+    pub fn new() -> Self {
+        Self {
+            gate: 0.0,
+            compressor: 0.0,
+            limiter: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, meter: &DynamicsMeter) {
+        let follow =
+            |shown: f32, value: &AtomicU32| (shown * METER_DECAY).max(DynamicsMeter::take(value));
+        self.gate = follow(self.gate, &meter.gate);
+        self.compressor = follow(self.compressor, &meter.compressor);
+        self.limiter = follow(self.limiter, &meter.limiter);
+    }
+}
+
+// Hands out a source one frame at a time so the stages can link their
+// detectors across channels, and picks up changed settings on the way
+struct FrameReader<S> {
+    input: S,
+    shared: Arc<SharedEffects>,
+    generation: Option<u64>,
+    frames_until_check: usize,
+    frame: Vec<f32>,
+    position: usize,
+}
+
+impl<S> FrameReader<S>
+where
+    S: Source<Item = f32>,
+{
+    fn new(input: S, shared: Arc<SharedEffects>) -> Self {
+        Self {
+            input,
+            shared,
+            generation: None,
+            frames_until_check: 0,
+            frame: Vec::new(),
+            position: 0,
+        }
+    }
+
+    // `process` gets each new frame, plus the settings if they changed
+    fn next_sample(
+        &mut self,
+        mut process: impl FnMut(&mut [f32], Option<DynamicsSettings>),
+    ) -> Option<f32> {
+        if self.position >= self.frame.len() {
+            let mut changed = None;
+            if self.frames_until_check == 0 {
+                changed = self
+                    .shared
+                    .read_if_changed(&mut self.generation, |effects| effects.dynamics);
+                self.frames_until_check = CHECK_INTERVAL;
+            }
+            self.frames_until_check -= 1;
+
+            self.frame.clear();
+            for _ in 0..self.input.channels().max(1) {
+                self.frame.push(self.input.next()?);
+            }
+            process(&mut self.frame, changed);
+            self.position = 0;
+        }
+
+        let sample = self.frame[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+// Noise gate: closes when the level stays under the threshold
+pub struct Gate<S> {
+    reader: FrameReader<S>,
+    meter: Arc<DynamicsMeter>,
+    enabled: bool,
+    threshold: f32,
+    gain: f32,
+    hold_left: usize,
+}
+
+impl<S> Gate<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, shared: Arc<SharedEffects>, meter: Arc<DynamicsMeter>) -> Self {
+        Self {
+            reader: FrameReader::new(input, shared),
+            meter,
+            enabled: false,
+            threshold: 0.0,
+            gain: 1.0,
+            hold_left: 0,
+        }
+    }
+}
+
+impl<S> Iterator for Gate<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample_rate = self.reader.input.sample_rate();
+        let Self {
+            reader,
+            meter,
+            enabled,
+            threshold,
+            gain,
+            hold_left,
+        } = self;
+
+        reader.next_sample(|frame, changed| {
+            if let Some(settings) = changed {
+                *enabled = settings.gate;
+                *threshold = db_to_gain(settings.gate_threshold);
+            }
+            if !*enabled {
+                *gain = 1.0;
+                return;
+            }
+
+            let peak = frame.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+            if peak >= *threshold {
+                *hold_left = (GATE_HOLD * sample_rate as f32) as usize;
+            } else {
+                *hold_left = hold_left.saturating_sub(1);
+            }
+
+            let (target, time) = if *hold_left > 0 {
+                (1.0, GATE_ATTACK)
+            } else {
+                (0.0, GATE_RELEASE)
+            };
+            let coeff = smoothing(time, sample_rate);
+            *gain = target + (*gain - target) * coeff;
+
+            for sample in frame.iter_mut() {
+                *sample *= *gain;
+            }
+            DynamicsMeter::record(&meter.gate, -gain_to_db(*gain));
+        })
+    }
+}
+
+impl<S> Source for Gate<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.reader.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.reader.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.reader.input.total_duration()
+    }
+}
+
+// Feed-forward compressor with a peak detector, linked across channels
+pub struct Compressor<S> {
+    reader: FrameReader<S>,
+    meter: Arc<DynamicsMeter>,
+    settings: DynamicsSettings,
+    // Current gain reduction in dB
+    reduction: f32,
+}
+
+impl<S> Compressor<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, shared: Arc<SharedEffects>, meter: Arc<DynamicsMeter>) -> Self {
+        Self {
+            reader: FrameReader::new(input, shared),
+            meter,
+            settings: DynamicsSettings::new(),
+            reduction: 0.0,
+        }
+    }
+}
+
+impl<S> Iterator for Compressor<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample_rate = self.reader.input.sample_rate();
+        let Self {
+            reader,
+            meter,
+            settings,
+            reduction,
+        } = self;
+
+        reader.next_sample(|frame, changed| {
+            if let Some(changed) = changed {
+                *settings = changed;
+            }
+            if !settings.compressor {
+                *reduction = 0.0;
+                return;
+            }
+
+            let peak = frame.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+            let over = gain_to_db(peak) - settings.threshold;
+            let target = if over > 0.0 {
+                over * (1.0 - 1.0 / settings.ratio)
+            } else {
+                0.0
+            };
+
+            let time = if target > *reduction {
+                settings.attack
+            } else {
+                settings.release
+            };
+            let coeff = smoothing(time / 1000.0, sample_rate);
+            *reduction = target + (*reduction - target) * coeff;
+
+            let gain = db_to_gain(settings.makeup - *reduction);
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+            DynamicsMeter::record(&meter.compressor, *reduction);
+        })
+    }
+}
+
+impl<S> Source for Compressor<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.reader.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.reader.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.reader.input.total_duration()
+    }
+}
+
+// Brickwall limiter. The signal is delayed by the lookahead so the gain is
+// already down by the time a peak comes out, anything left is clipped
+pub struct Limiter<S> {
+    reader: FrameReader<S>,
+    meter: Arc<DynamicsMeter>,
+    enabled: bool,
+    ceiling: f32,
+    gain: f32,
+    // Delayed frames and the gain each of them needs
+    delayed: VecDeque<f32>,
+    needed: VecDeque<f32>,
+}
+
+impl<S> Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, shared: Arc<SharedEffects>, meter: Arc<DynamicsMeter>) -> Self {
+        Self {
+            reader: FrameReader::new(input, shared),
+            meter,
+            enabled: false,
+            ceiling: 1.0,
+            gain: 1.0,
+            delayed: VecDeque::new(),
+            needed: VecDeque::new(),
+        }
+    }
+
+    // Samples of silence the lookahead puts in front of the audio, for
+    // offline renders to skip
+    pub fn latency(&self) -> usize {
+        lookahead_frames(self.reader.input.sample_rate()) * self.reader.input.channels() as usize
+    }
+}
+
+fn lookahead_frames(sample_rate: u32) -> usize {
+    ((LIMITER_LOOKAHEAD * sample_rate as f32) as usize).max(1)
+}
+
+// Move the gain towards the lowest any frame in the lookahead needs, quickly
+// down and slowly back up
+fn follow_needed(gain: &mut f32, needed: &VecDeque<f32>, sample_rate: u32) {
+    let lowest = needed.iter().fold(1.0f32, |min, g| min.min(*g));
+    if lowest < *gain {
+        *gain = lowest;
+    } else {
+        let coeff = smoothing(LIMITER_RELEASE, sample_rate);
+        *gain = lowest + (*gain - lowest) * coeff;
+    }
+}
+
+impl<S> Iterator for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample_rate = self.reader.input.sample_rate();
+        let lookahead = lookahead_frames(sample_rate);
+        let Self {
+            reader,
+            meter,
+            enabled,
+            ceiling,
+            gain,
+            delayed,
+            needed,
+        } = self;
+
+        let sample = reader.next_sample(|frame, changed| {
+            if let Some(settings) = changed {
+                *enabled = settings.limiter;
+                *ceiling = db_to_gain(settings.ceiling);
+            }
+            // The delay stays in when disabled, so toggling doesn't click
+            let peak = frame.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+            let required = if *enabled && peak > *ceiling {
+                *ceiling / peak
+            } else {
+                1.0
+            };
+            delayed.extend(frame.iter().copied());
+            needed.push_back(required);
+
+            // Not enough delay built up yet
+            if needed.len() <= lookahead {
+                frame.iter_mut().for_each(|sample| *sample = 0.0);
+                return;
+            }
+
+            follow_needed(gain, needed, sample_rate);
+            needed.pop_front();
+
+            for sample in frame.iter_mut() {
+                let out = delayed.pop_front().unwrap_or(0.0) * *gain;
+                *sample = if *enabled {
+                    out.clamp(-*ceiling, *ceiling)
+                } else {
+                    out
+                };
+            }
+            DynamicsMeter::record(&meter.limiter, -gain_to_db(*gain));
+        });
+        if sample.is_some() {
+            return sample;
+        }
+
+        // The input has ended, play out the frames still in the lookahead
+        if self.delayed.is_empty() {
+            return None;
+        }
+        let channels = self.reader.input.channels().max(1) as usize;
+        if self.delayed.len().is_multiple_of(channels) {
+            follow_needed(&mut self.gain, &self.needed, sample_rate);
+            self.needed.pop_front();
+        }
+        let out = self.delayed.pop_front()? * self.gain;
+        Some(if self.enabled {
+            out.clamp(-self.ceiling, self.ceiling)
+        } else {
+            out
+        })
+    }
+}
+
+impl<S> Source for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.reader.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.reader.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.reader.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_player::effects::EffectManager;
+    use rodio::buffer::SamplesBuffer;
+    use std::f32::consts::TAU;
+
+    const RATE: u32 = 44100;
+
+    fn shared(settings: DynamicsSettings) -> Arc<SharedEffects> {
+        let mut effects = EffectManager::new();
+        effects.dynamics = settings;
+        SharedEffects::new(&effects)
+    }
+
+    // Stereo sine, the level changing to `levels[i]` every `frames` frames
+    fn sine(levels: &[f32], frames: usize) -> SamplesBuffer<f32> {
+        let samples = (0..levels.len() * frames)
+            .flat_map(|i| {
+                let s = levels[i / frames] * (TAU * 440.0 * i as f32 / RATE as f32).sin();
+                [s, s]
+            })
+            .collect::<Vec<f32>>();
+        SamplesBuffer::new(2, RATE, samples)
+    }
+
+    fn constant(level: f32, frames: usize) -> SamplesBuffer<f32> {
+        SamplesBuffer::new(2, RATE, vec![level; frames * 2])
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |max, s| max.max(s.abs()))
+    }
+
+    #[test]
+    fn limiter_holds_the_ceiling() {
+        let settings = DynamicsSettings::new();
+        let meter = DynamicsMeter::new();
+        let output: Vec<f32> =
+            Limiter::new(sine(&[2.0], 44100), shared(settings), meter.clone()).collect();
+
+        assert!(peak(&output) <= db_to_gain(settings.ceiling) + 1e-6);
+        // Clamped only as a last resort, the gain does the work
+        let mut reduction = GainReduction::new();
+        reduction.update(&meter);
+        assert!((reduction.limiter - gain_to_db(2.0 / db_to_gain(settings.ceiling))).abs() < 0.1);
+    }
+
+    #[test]
+    fn limiter_delays_by_the_lookahead() {
+        let input: Vec<f32> = sine(&[0.5], 1000).collect();
+        let output: Vec<f32> = Limiter::new(
+            sine(&[0.5], 1000),
+            shared(DynamicsSettings::new()),
+            DynamicsMeter::new(),
+        )
+        .collect();
+        let lookahead = (LIMITER_LOOKAHEAD * RATE as f32) as usize * 2;
+
+        assert!(output[..lookahead].iter().all(|&s| s == 0.0));
+        // Under the ceiling it's left alone, and nothing is lost at the end
+        assert_eq!(output[lookahead..], input);
+    }
+
+    #[test]
+    fn limiter_plays_out_the_lookahead() {
+        let limiter = Limiter::new(
+            sine(&[2.0], 1000),
+            shared(DynamicsSettings::new()),
+            DynamicsMeter::new(),
+        );
+        let latency = limiter.latency();
+        assert_eq!(latency, (LIMITER_LOOKAHEAD * RATE as f32) as usize * 2);
+
+        let output: Vec<f32> = limiter.skip(latency).collect();
+        assert_eq!(output.len(), 2000);
+        // The frames let out at the end are still limited
+        let tail = &output[output.len() - latency..];
+        assert!(peak(tail) <= db_to_gain(DynamicsSettings::new().ceiling) + 1e-6);
+        assert!(peak(tail) > 0.5);
+    }
+
+    #[test]
+    fn compressor_reduces_by_the_ratio() {
+        let mut settings = DynamicsSettings::new();
+        settings.compressor = true;
+        settings.makeup = 3.0;
+        let meter = DynamicsMeter::new();
+        let output: Vec<f32> =
+            Compressor::new(constant(0.5, 44100), shared(settings), meter.clone()).collect();
+
+        // 0.5 is 12 dB over -18, at 4:1 that's 9 dB down
+        let expected = db_to_gain(gain_to_db(0.5) - 0.75 * (gain_to_db(0.5) + 18.0) + 3.0);
+        let settled = peak(&output[output.len() / 2..]);
+        assert!(
+            (settled - expected).abs() < 0.01,
+            "{} vs {}",
+            settled,
+            expected
+        );
+        assert!((DynamicsMeter::take(&meter.compressor) - 9.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn gate_closes_on_quiet_passages() {
+        let mut settings = DynamicsSettings::new();
+        settings.gate = true;
+        let second = RATE as usize;
+        let output: Vec<f32> = Gate::new(
+            sine(&[0.5, 0.001], second),
+            shared(settings),
+            DynamicsMeter::new(),
+        )
+        .collect();
+
+        assert!((peak(&output[..2 * second]) - 0.5).abs() < 0.01);
+        // Held open for a moment, then faded out
+        let hold = (GATE_HOLD * RATE as f32) as usize * 2;
+        assert!(peak(&output[2 * second..2 * second + hold / 2]) > 0.0009);
+        assert!(peak(&output[3 * second..]) < 1e-5);
+    }
+
+    #[test]
+    fn disabled_stages_pass_the_signal() {
+        let mut settings = DynamicsSettings::new();
+        settings.limiter = false;
+        let input: Vec<f32> = sine(&[2.0], 100).collect();
+        let meter = DynamicsMeter::new();
+        let shared = shared(settings);
+
+        let gated: Vec<f32> = Gate::new(sine(&[2.0], 100), shared.clone(), meter.clone()).collect();
+        let compressed: Vec<f32> = Compressor::new(sine(&[2.0], 100), shared, meter).collect();
+        assert_eq!(gated, input);
+        assert_eq!(compressed, input);
+    }
+
+    #[test]
+    fn changes_stay_in_range() {
+        let mut settings = DynamicsSettings::new();
+        for _ in 0..10 {
+            settings.change(DynamicsParam::Ceiling, true);
+            settings.change(DynamicsParam::Ratio, false);
+        }
+        assert_eq!(settings.ceiling, 0.0);
+        assert_eq!(settings.ratio, 1.0);
+
+        for _ in 0..100 {
+            settings.change(DynamicsParam::Attack, false);
+            settings.change(DynamicsParam::Release, true);
+            settings.change(DynamicsParam::GateThreshold, true);
+        }
+        assert_eq!(settings.attack, 0.1);
+        assert_eq!(settings.release, 2000.0);
+        assert_eq!(settings.gate_threshold, -10.0);
+
+        settings.change(DynamicsParam::Limiter, true);
+        assert!(!settings.limiter);
+    }
+}
@@ -0,0 +1,415 @@
+use crate::audio_player::sample::DecodedAudio;
+use std::{
+    f32::consts::PI,
+    path::{Path, PathBuf},
+};
+
+// Peak normalization target (-1 dBFS)
+const PEAK_TARGET: f32 = 0.891;
+// RMS loudness normalization target (-16 dBFS)
+const LOUDNESS_TARGET: f32 = 0.158;
+// Trim and fade lengths move in 10ms steps
+const TIME_STEP: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FadeCurve {
+    Linear,
+    Exponential,
+    Logarithmic,
+    SCurve,
+}
+
+impl FadeCurve {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "Linear",
+            FadeCurve::Exponential => "Exponential",
+            FadeCurve::Logarithmic => "Logarithmic",
+            FadeCurve::SCurve => "S-Curve",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            FadeCurve::Linear => FadeCurve::Exponential,
+            FadeCurve::Exponential => FadeCurve::Logarithmic,
+            FadeCurve::Logarithmic => FadeCurve::SCurve,
+            FadeCurve::SCurve => FadeCurve::Linear,
+        }
+    }
+
+    // Gain for a fade in at position t (0.0 - 1.0)
+    fn gain(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::Exponential => t * t,
+            FadeCurve::Logarithmic => 1.0 - (1.0 - t) * (1.0 - t),
+            FadeCurve::SCurve => 0.5 - 0.5 * (t * PI).cos(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalize {
+    Off,
+    Peak,
+    Loudness,
+}
+
+impl Normalize {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Normalize::Off => "Off",
+            Normalize::Peak => "Peak (-1 dBFS)",
+            Normalize::Loudness => "Loudness (-16 dBFS RMS)",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Normalize::Off => Normalize::Peak,
+            Normalize::Peak => Normalize::Loudness,
+            Normalize::Loudness => Normalize::Off,
+        }
+    }
+}
+
+// The editable parameters, in the order they're listed in Edit mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditParam {
+    TrimStart,
+    TrimEnd,
+    FadeIn,
+    FadeOut,
+    FadeCurve,
+    Normalize,
+    Reverse,
+    RemoveDc,
+}
+
+pub const EDIT_PARAMS: [EditParam; 8] = [
+    EditParam::TrimStart,
+    EditParam::TrimEnd,
+    EditParam::FadeIn,
+    EditParam::FadeOut,
+    EditParam::FadeCurve,
+    EditParam::Normalize,
+    EditParam::Reverse,
+    EditParam::RemoveDc,
+];
+
+// Edits applied on top of the original file, which itself is never touched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EditSettings {
+    pub trim_start: f32,
+    pub trim_end: f32,
+    pub fade_in: f32,
+    pub fade_out: f32,
+    pub fade_curve: FadeCurve,
+    pub normalize: Normalize,
+    pub reverse: bool,
+    pub remove_dc: bool,
+}
+
+impl EditSettings {
+    pub fn new() -> Self {
+        Self {
+            trim_start: 0.0,
+            trim_end: 0.0,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            fade_curve: FadeCurve::Linear,
+            normalize: Normalize::Off,
+            reverse: false,
+            remove_dc: false,
+        }
+    }
+
+    pub fn describe(&self, param: EditParam) -> (&'static str, String) {
+        match param {
+            EditParam::TrimStart => ("Trim start", format!("{:.2}s", self.trim_start)),
+            EditParam::TrimEnd => ("Trim end", format!("{:.2}s", self.trim_end)),
+            EditParam::FadeIn => ("Fade in", format!("{:.2}s", self.fade_in)),
+            EditParam::FadeOut => ("Fade out", format!("{:.2}s", self.fade_out)),
+            EditParam::FadeCurve => ("Fade curve", self.fade_curve.name().to_string()),
+            EditParam::Normalize => ("Normalize", self.normalize.name().to_string()),
+            EditParam::Reverse => ("Reverse", on_off(self.reverse)),
+            EditParam::RemoveDc => ("Remove DC offset", on_off(self.remove_dc)),
+        }
+    }
+
+    // Step a parameter, capping times at the length of the file
+    pub fn change(&mut self, param: EditParam, increase: bool, duration: f32) {
+        let step = |value: f32| {
+            let value = if increase {
+                value + TIME_STEP
+            } else {
+                value - TIME_STEP
+            };
+            value.clamp(0.0, duration)
+        };
+
+        match param {
+            EditParam::TrimStart => self.trim_start = step(self.trim_start),
+            EditParam::TrimEnd => self.trim_end = step(self.trim_end),
+            EditParam::FadeIn => self.fade_in = step(self.fade_in),
+            EditParam::FadeOut => self.fade_out = step(self.fade_out),
+            EditParam::FadeCurve => self.fade_curve = self.fade_curve.next(),
+            EditParam::Normalize => self.normalize = self.normalize.next(),
+            EditParam::Reverse => self.reverse = !self.reverse,
+            EditParam::RemoveDc => self.remove_dc = !self.remove_dc,
+        }
+    }
+
+    // Produce the edited audio as a new buffer
+    pub fn render(&self, audio: &DecodedAudio) -> DecodedAudio {
+        let channels = audio.channels as usize;
+        let mut samples = audio.samples.clone();
+
+        if self.remove_dc {
+            for channel in 0..channels {
+                let count = (samples.len() / channels).max(1) as f32;
+                let mean = samples.iter().skip(channel).step_by(channels).sum::<f32>() / count;
+                for sample in samples.iter_mut().skip(channel).step_by(channels) {
+                    *sample -= mean;
+                }
+            }
+        }
+
+        // Trim
+        let frames = audio.frames();
+        let start = audio.secs_to_frames(self.trim_start).min(frames);
+        let end = frames
+            .saturating_sub(audio.secs_to_frames(self.trim_end))
+            .max(start);
+        let mut samples = samples[start * channels..end * channels].to_vec();
+
+        if self.reverse {
+            let reversed: Vec<f32> = samples
+                .chunks_exact(channels)
+                .rev()
+                .flatten()
+                .copied()
+                .collect();
+            samples = reversed;
+        }
+
+        // Fades
+        let frames = samples.len() / channels;
+        let fade_in = audio.secs_to_frames(self.fade_in).min(frames);
+        let fade_out = audio.secs_to_frames(self.fade_out).min(frames);
+        for (frame, chunk) in samples.chunks_exact_mut(channels).enumerate() {
+            let mut gain = 1.0;
+            if frame < fade_in {
+                gain *= self.fade_curve.gain(frame as f32 / fade_in as f32);
+            }
+            if frame + fade_out >= frames && fade_out > 0 {
+                gain *= self
+                    .fade_curve
+                    .gain((frames - 1 - frame) as f32 / fade_out as f32);
+            }
+            for sample in chunk {
+                *sample *= gain;
+            }
+        }
+
+        let peak = samples.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        let gain = match self.normalize {
+            Normalize::Off => 1.0,
+            Normalize::Peak if peak > 0.0 => PEAK_TARGET / peak,
+            Normalize::Loudness if peak > 0.0 => {
+                let rms =
+                    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+                // Never push the peaks past full scale to get there
+                (LOUDNESS_TARGET / rms).min(1.0 / peak)
+            }
+            _ => 1.0,
+        };
+        if gain != 1.0 {
+            for sample in &mut samples {
+                *sample *= gain;
+            }
+        }
+
+        DecodedAudio {
+            samples,
+            channels: audio.channels,
+            sample_rate: audio.sample_rate,
+        }
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value { "On" } else { "Off" }.to_string()
+}
+
+// Edit mode state: which file we're editing and what's selected
+pub struct SampleEditor {
+    pub file: Option<PathBuf>,
+    pub settings: EditSettings,
+    pub selected: usize,
+    // Set while we wait for the user to confirm overwriting the original
+    pub confirm_overwrite: bool,
+}
+
+impl SampleEditor {
+    pub fn new() -> Self {
+        Self {
+            file: None,
+            settings: EditSettings::new(),
+            selected: 0,
+            confirm_overwrite: false,
+        }
+    }
+
+    // Start editing a file, keeping the settings if it's the same one
+    pub fn open(&mut self, file: &Path) {
+        if self.file.as_deref() != Some(file) {
+            self.file = Some(file.to_path_buf());
+            self.settings = EditSettings::new();
+            self.selected = 0;
+        }
+        self.confirm_overwrite = false;
+    }
+
+    pub fn selected_param(&self) -> EditParam {
+        EDIT_PARAMS[self.selected]
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % EDIT_PARAMS.len();
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = (self.selected + EDIT_PARAMS.len() - 1) % EDIT_PARAMS.len();
+    }
+}
+
+// First free "<name>_edit.wav", "<name>_edit_2.wav", ... next to the original
+pub fn save_as_path(original: &Path) -> PathBuf {
+    let stem = original
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "sample".to_string());
+    let dir = original.parent().unwrap_or(Path::new("."));
+
+    let mut candidate = dir.join(format!("{}_edit.wav", stem));
+    let mut n = 2;
+    while candidate.exists() {
+        candidate = dir.join(format!("{}_edit_{}.wav", stem, n));
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stereo audio at 100Hz, so 0.01s is one frame
+    fn stereo(frames: &[f32]) -> DecodedAudio {
+        DecodedAudio {
+            samples: frames.iter().flat_map(|&s| [s, -s]).collect(),
+            channels: 2,
+            sample_rate: 100,
+        }
+    }
+
+    fn left(audio: &DecodedAudio) -> Vec<f32> {
+        audio.samples.iter().step_by(2).copied().collect()
+    }
+
+    #[test]
+    fn leaves_audio_alone_by_default() {
+        let audio = stereo(&[0.1, 0.2, 0.3]);
+        assert_eq!(EditSettings::new().render(&audio).samples, audio.samples);
+    }
+
+    #[test]
+    fn trims_and_reverses_whole_frames() {
+        let mut settings = EditSettings::new();
+        settings.trim_start = 0.02;
+        settings.trim_end = 0.01;
+        settings.reverse = true;
+        let edited = settings.render(&stereo(&[0.0, 0.1, 0.2, 0.3, 0.4, 0.5]));
+        assert_eq!(edited.samples, vec![0.4, -0.4, 0.3, -0.3, 0.2, -0.2]);
+
+        // Trimming more than there is leaves nothing, not a panic
+        settings.trim_start = 10.0;
+        assert!(settings.render(&stereo(&[0.1; 4])).samples.is_empty());
+    }
+
+    #[test]
+    fn fades_in_and_out_along_the_curve() {
+        let mut settings = EditSettings::new();
+        settings.fade_in = 0.04;
+        settings.fade_out = 0.04;
+        let edited = left(&settings.render(&stereo(&[1.0; 10])));
+        assert_eq!(
+            edited,
+            vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0, 0.75, 0.5, 0.25, 0.0]
+        );
+
+        settings.fade_curve = FadeCurve::Exponential;
+        let edited = left(&settings.render(&stereo(&[1.0; 10])));
+        assert_eq!(edited[1], 0.0625);
+        assert_eq!(edited[8], 0.0625);
+    }
+
+    #[test]
+    fn normalizes_peak_and_loudness() {
+        let mut settings = EditSettings::new();
+        settings.normalize = Normalize::Peak;
+        let edited = settings.render(&stereo(&[0.1, -0.5, 0.25]));
+        let peak = edited
+            .samples
+            .iter()
+            .fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!((peak - PEAK_TARGET).abs() < 1e-6);
+
+        settings.normalize = Normalize::Loudness;
+        let edited = settings.render(&stereo(&[0.01; 8]));
+        assert!(
+            edited
+                .samples
+                .iter()
+                .all(|s| (s.abs() - LOUDNESS_TARGET).abs() < 1e-5)
+        );
+
+        // Loudness never clips the peaks
+        let mut spike = vec![0.0; 64];
+        spike[0] = 0.5;
+        let edited = settings.render(&stereo(&spike));
+        assert!((edited.samples[0] - 1.0).abs() < 1e-6);
+
+        // Silence stays silent
+        let edited = settings.render(&stereo(&[0.0; 4]));
+        assert!(edited.samples.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn removes_dc_per_channel() {
+        let mut settings = EditSettings::new();
+        settings.remove_dc = true;
+        let audio = DecodedAudio {
+            samples: vec![0.5, -0.1, 0.7, -0.3],
+            channels: 2,
+            sample_rate: 100,
+        };
+        let edited = settings.render(&audio);
+        for (a, b) in edited.samples.iter().zip([-0.1, 0.1, 0.1, -0.1]) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn caps_times_at_the_file_length() {
+        let mut settings = EditSettings::new();
+        settings.change(EditParam::FadeIn, false, 1.0);
+        assert_eq!(settings.fade_in, 0.0);
+        settings.trim_end = 0.995;
+        settings.change(EditParam::TrimEnd, true, 1.0);
+        assert_eq!(settings.trim_end, 1.0);
+    }
+}
@@ -1,10 +1,827 @@
+use crate::audio_player::dynamics::DynamicsSettings;
+use crate::audio_player::envelope::{AdsrParam, AdsrSettings, Envelope};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_PI_4, PI, SQRT_2};
+
+// A DSP unit in the effect chain. Units are built per sound and work on one
+// interleaved frame at a time
+pub trait Effect: Send {
+    // Pick up parameter changes without losing internal state (filter
+    // memory, delay lines, ...)
+    fn configure(&mut self, effects: &EffectManager);
+
+    fn process(&mut self, frame: &mut [f32]);
+
+    // The sound has ended, envelopes move on to their release
+    fn release(&mut self) {}
+
+    // How many frames the effect keeps ringing after its input has ended
+    fn tail(&self) -> usize {
+        0
+    }
+}
+
+// Every effect that can be put in the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectKind {
+    Gain,
+    Reverb,
+    LowPass,
+    Stereo,
+    Delay,
+    Chorus,
+    Flanger,
+    Phaser,
+    Tremolo,
+    AutoPan,
+    Saturation,
+    Bitcrusher,
+    Filter,
+}
+
+pub const ALL_EFFECTS: [EffectKind; 13] = [
+    EffectKind::Gain,
+    EffectKind::Reverb,
+    EffectKind::LowPass,
+    EffectKind::Stereo,
+    EffectKind::Delay,
+    EffectKind::Chorus,
+    EffectKind::Flanger,
+    EffectKind::Phaser,
+    EffectKind::Tremolo,
+    EffectKind::AutoPan,
+    EffectKind::Saturation,
+    EffectKind::Bitcrusher,
+    EffectKind::Filter,
+];
+
+// The LFO based effects, as listed in Modulation mode
+pub const MODULATION_EFFECTS: [EffectKind; 5] = [
+    EffectKind::Chorus,
+    EffectKind::Flanger,
+    EffectKind::Phaser,
+    EffectKind::Tremolo,
+    EffectKind::AutoPan,
+];
+
+impl EffectKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectKind::Gain => "Volume",
+            EffectKind::Reverb => "Reverb",
+            EffectKind::LowPass => "Low-Pass Filter",
+            EffectKind::Stereo => "Stereo",
+            EffectKind::Delay => "Delay",
+            EffectKind::Chorus => "Chorus",
+            EffectKind::Flanger => "Flanger",
+            EffectKind::Phaser => "Phaser",
+            EffectKind::Tremolo => "Tremolo",
+            EffectKind::AutoPan => "Auto-Pan",
+            EffectKind::Saturation => "Saturation",
+            EffectKind::Bitcrusher => "Bitcrusher",
+            EffectKind::Filter => "Multimode Filter",
+        }
+    }
+
+    pub fn build(&self, channels: u16, sample_rate: u32) -> Box<dyn Effect> {
+        match self {
+            EffectKind::Gain => Box::new(Gain::new()),
+            EffectKind::Reverb => Box::new(Reverb::new(channels, sample_rate)),
+            EffectKind::LowPass => Box::new(LowPass::new(channels, sample_rate)),
+            EffectKind::Stereo => Box::new(Stereo::new()),
+            EffectKind::Delay => Box::new(Delay::new(channels, sample_rate)),
+            EffectKind::Chorus | EffectKind::Flanger => {
+                Box::new(ModulatedDelay::new(*self, channels, sample_rate))
+            }
+            EffectKind::Phaser => Box::new(Phaser::new(channels, sample_rate)),
+            EffectKind::Tremolo => Box::new(Tremolo::new(sample_rate)),
+            EffectKind::AutoPan => Box::new(AutoPan::new(sample_rate)),
+            EffectKind::Saturation => Box::new(Saturation::new(channels, sample_rate)),
+            EffectKind::Bitcrusher => Box::new(Bitcrusher::new(channels)),
+            EffectKind::Filter => Box::new(Filter::new(channels, sample_rate)),
+        }
+    }
+}
+
+// Note lengths a synced delay can be set to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoteValue {
+    Whole,
+    Half,
+    Quarter,
+    DottedEighth,
+    Eighth,
+    EighthTriplet,
+    Sixteenth,
+}
+
+const NOTE_VALUES: [NoteValue; 7] = [
+    NoteValue::Whole,
+    NoteValue::Half,
+    NoteValue::Quarter,
+    NoteValue::DottedEighth,
+    NoteValue::Eighth,
+    NoteValue::EighthTriplet,
+    NoteValue::Sixteenth,
+];
+
+impl NoteValue {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoteValue::Whole => "1/1",
+            NoteValue::Half => "1/2",
+            NoteValue::Quarter => "1/4",
+            NoteValue::DottedEighth => "1/8 dotted",
+            NoteValue::Eighth => "1/8",
+            NoteValue::EighthTriplet => "1/8 triplet",
+            NoteValue::Sixteenth => "1/16",
+        }
+    }
+
+    // Length in quarter notes
+    fn beats(&self) -> f32 {
+        match self {
+            NoteValue::Whole => 4.0,
+            NoteValue::Half => 2.0,
+            NoteValue::Quarter => 1.0,
+            NoteValue::DottedEighth => 0.75,
+            NoteValue::Eighth => 0.5,
+            NoteValue::EighthTriplet => 1.0 / 3.0,
+            NoteValue::Sixteenth => 0.25,
+        }
+    }
+
+    // Longer/shorter note
+    fn step(self, longer: bool) -> Self {
+        let index = NOTE_VALUES.iter().position(|n| *n == self).unwrap_or(0);
+        let index = if longer {
+            index.saturating_sub(1)
+        } else {
+            (index + 1).min(NOTE_VALUES.len() - 1)
+        };
+        NOTE_VALUES[index]
+    }
+}
+
+// The delay parameters, in the order they're listed in Delay mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelayParam {
+    Time,
+    Sync,
+    Bpm,
+    Note,
+    Feedback,
+    Mix,
+    PingPong,
+    HighCut,
+}
+
+pub const DELAY_PARAMS: [DelayParam; 8] = [
+    DelayParam::Time,
+    DelayParam::Sync,
+    DelayParam::Bpm,
+    DelayParam::Note,
+    DelayParam::Feedback,
+    DelayParam::Mix,
+    DelayParam::PingPong,
+    DelayParam::HighCut,
+];
+
+// Longest delay the line is sized for
+const MAX_DELAY_SECS: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DelaySettings {
+    // Free running time, used when not synced
+    pub time_ms: f32,
+    pub sync: bool,
+    pub bpm: f32,
+    pub note: NoteValue,
+    pub feedback: f32,
+    // 0.0 is only the dry sound, 1.0 only the repeats
+    pub mix: f32,
+    pub ping_pong: bool,
+    // Low-pass on the repeats, each one gets a little darker
+    pub high_cut: u32,
+}
+
+impl DelaySettings {
+    pub fn new() -> Self {
+        Self {
+            time_ms: 375.0,
+            sync: false,
+            bpm: 120.0,
+            note: NoteValue::DottedEighth,
+            feedback: 0.4,
+            mix: 0.3,
+            ping_pong: false,
+            high_cut: 8000,
+        }
+    }
+
+    // The delay time in seconds, whichever way it's set
+    pub fn delay_secs(&self) -> f32 {
+        let secs = if self.sync {
+            60.0 / self.bpm * self.note.beats()
+        } else {
+            self.time_ms / 1000.0
+        };
+        secs.min(MAX_DELAY_SECS)
+    }
+
+    pub fn describe(&self, param: DelayParam) -> (&'static str, String) {
+        match param {
+            DelayParam::Time if self.sync => (
+                "Time",
+                format!("{:.0} ms (synced)", self.delay_secs() * 1000.0),
+            ),
+            DelayParam::Time => ("Time", format!("{:.0} ms", self.time_ms)),
+            DelayParam::Sync => (
+                "Sync to BPM",
+                if self.sync { "On" } else { "Off" }.to_string(),
+            ),
+            DelayParam::Bpm => ("BPM", format!("{:.0}", self.bpm)),
+            DelayParam::Note => ("Note", self.note.name().to_string()),
+            DelayParam::Feedback => ("Feedback", format!("{:.0}%", self.feedback * 100.0)),
+            DelayParam::Mix => ("Wet/dry", format!("{:.0}% wet", self.mix * 100.0)),
+            DelayParam::PingPong => (
+                "Ping-pong",
+                if self.ping_pong { "On" } else { "Off" }.to_string(),
+            ),
+            DelayParam::HighCut => ("High-cut", format!("{}Hz", self.high_cut)),
+        }
+    }
+
+    pub fn change(&mut self, param: DelayParam, increase: bool) {
+        match param {
+            DelayParam::Time => {
+                // Finer steps for short, slapback-ish times
+                let step = if self.time_ms < 100.0 { 5.0 } else { 25.0 };
+                self.time_ms = if increase {
+                    (self.time_ms + step).min(MAX_DELAY_SECS * 1000.0)
+                } else {
+                    (self.time_ms - step).max(5.0)
+                };
+            }
+            DelayParam::Sync => self.sync = !self.sync,
+            DelayParam::Bpm => {
+                self.bpm = if increase {
+                    (self.bpm + 1.0).min(300.0)
+                } else {
+                    (self.bpm - 1.0).max(30.0)
+                };
+            }
+            DelayParam::Note => self.note = self.note.step(increase),
+            DelayParam::Feedback => {
+                self.feedback = if increase {
+                    (self.feedback + 0.05).min(0.95)
+                } else {
+                    (self.feedback - 0.05).max(0.0)
+                };
+            }
+            DelayParam::Mix => {
+                self.mix = if increase {
+                    (self.mix + 0.05).min(1.0)
+                } else {
+                    (self.mix - 0.05).max(0.0)
+                };
+            }
+            DelayParam::PingPong => self.ping_pong = !self.ping_pong,
+            DelayParam::HighCut => {
+                self.high_cut = if increase {
+                    (self.high_cut + 500).min(20000)
+                } else {
+                    self.high_cut.saturating_sub(500).max(500)
+                };
+            }
+        }
+    }
+
+    // Back into the ranges `change` keeps to
+    pub fn clamp(&mut self) {
+        self.time_ms = limit(self.time_ms, 5.0, MAX_DELAY_SECS * 1000.0);
+        self.bpm = limit(self.bpm, 30.0, 300.0);
+        self.feedback = limit(self.feedback, 0.0, 0.95);
+        self.mix = limit(self.mix, 0.0, 1.0);
+        self.high_cut = self.high_cut.clamp(500, 20000);
+    }
+}
+
+// What can be set on each modulation effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModulationParam {
+    Rate,
+    Depth,
+    Mix,
+}
+
+pub const MODULATION_PARAMS: [ModulationParam; 3] = [
+    ModulationParam::Rate,
+    ModulationParam::Depth,
+    ModulationParam::Mix,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModulationParams {
+    // LFO speed in Hz
+    pub rate: f32,
+    // 0.0 - 1.0, how far the LFO sweeps
+    pub depth: f32,
+    // 0.0 is only the dry sound, 1.0 only the effect
+    pub mix: f32,
+}
+
+impl ModulationParams {
+    fn new(rate: f32, depth: f32, mix: f32) -> Self {
+        Self { rate, depth, mix }
+    }
+
+    pub fn describe(&self, param: ModulationParam) -> String {
+        match param {
+            ModulationParam::Rate => format!("Rate {:.2}Hz", self.rate),
+            ModulationParam::Depth => format!("Depth {:.0}%", self.depth * 100.0),
+            ModulationParam::Mix => format!("Mix {:.0}%", self.mix * 100.0),
+        }
+    }
+
+    pub fn change(&mut self, param: ModulationParam, increase: bool) {
+        match param {
+            // Rates move in ratios, from slow sweeps to fast wobbles
+            ModulationParam::Rate => {
+                self.rate = if increase {
+                    (self.rate * 1.2).min(20.0)
+                } else {
+                    (self.rate / 1.2).max(0.05)
+                };
+            }
+            ModulationParam::Depth => {
+                self.depth = if increase {
+                    (self.depth + 0.05).min(1.0)
+                } else {
+                    (self.depth - 0.05).max(0.0)
+                };
+            }
+            ModulationParam::Mix => {
+                self.mix = if increase {
+                    (self.mix + 0.05).min(1.0)
+                } else {
+                    (self.mix - 0.05).max(0.0)
+                };
+            }
+        }
+    }
+
+    pub fn clamp(&mut self) {
+        self.rate = limit(self.rate, 0.05, 20.0);
+        self.depth = limit(self.depth, 0.0, 1.0);
+        self.mix = limit(self.mix, 0.0, 1.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModulationSettings {
+    pub chorus: ModulationParams,
+    pub flanger: ModulationParams,
+    pub phaser: ModulationParams,
+    pub tremolo: ModulationParams,
+    pub auto_pan: ModulationParams,
+}
+
+impl ModulationSettings {
+    pub fn new() -> Self {
+        Self {
+            chorus: ModulationParams::new(0.8, 0.5, 0.5),
+            flanger: ModulationParams::new(0.25, 0.7, 0.5),
+            phaser: ModulationParams::new(0.5, 0.7, 0.5),
+            tremolo: ModulationParams::new(5.0, 0.5, 1.0),
+            auto_pan: ModulationParams::new(1.0, 0.8, 1.0),
+        }
+    }
+
+    pub fn get(&self, kind: EffectKind) -> Option<&ModulationParams> {
+        match kind {
+            EffectKind::Chorus => Some(&self.chorus),
+            EffectKind::Flanger => Some(&self.flanger),
+            EffectKind::Phaser => Some(&self.phaser),
+            EffectKind::Tremolo => Some(&self.tremolo),
+            EffectKind::AutoPan => Some(&self.auto_pan),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, kind: EffectKind) -> Option<&mut ModulationParams> {
+        match kind {
+            EffectKind::Chorus => Some(&mut self.chorus),
+            EffectKind::Flanger => Some(&mut self.flanger),
+            EffectKind::Phaser => Some(&mut self.phaser),
+            EffectKind::Tremolo => Some(&mut self.tremolo),
+            EffectKind::AutoPan => Some(&mut self.auto_pan),
+            _ => None,
+        }
+    }
+
+    pub fn clamp(&mut self) {
+        for kind in MODULATION_EFFECTS {
+            if let Some(params) = self.get_mut(kind) {
+                params.clamp();
+            }
+        }
+    }
+}
+
+// Waveshaper curves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SaturationMode {
+    SoftClip,
+    Tube,
+    HardClip,
+}
+
+impl SaturationMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SaturationMode::SoftClip => "Soft clip",
+            SaturationMode::Tube => "Tube",
+            SaturationMode::HardClip => "Hard clip",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            SaturationMode::SoftClip => SaturationMode::Tube,
+            SaturationMode::Tube => SaturationMode::HardClip,
+            SaturationMode::HardClip => SaturationMode::SoftClip,
+        }
+    }
+
+    fn shape(&self, x: f32) -> f32 {
+        match self {
+            SaturationMode::SoftClip => x.tanh(),
+            // The bias makes it lopsided, which brings in even harmonics.
+            // Scaled back up so quiet sounds keep their level
+            SaturationMode::Tube => {
+                let bias = 0.2f32.tanh();
+                ((x + 0.2).tanh() - bias) / (1.0 - bias * bias)
+            }
+            SaturationMode::HardClip => x.clamp(-1.0, 1.0),
+        }
+    }
+}
+
+// The distortion parameters, in the order they're listed in Distortion mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistortionParam {
+    Mode,
+    Drive,
+    Tone,
+    Bits,
+    Downsample,
+}
+
+pub const DISTORTION_PARAMS: [DistortionParam; 5] = [
+    DistortionParam::Mode,
+    DistortionParam::Drive,
+    DistortionParam::Tone,
+    DistortionParam::Bits,
+    DistortionParam::Downsample,
+];
+
+impl DistortionParam {
+    // The chain effect a parameter belongs to
+    pub fn effect(&self) -> EffectKind {
+        match self {
+            DistortionParam::Bits | DistortionParam::Downsample => EffectKind::Bitcrusher,
+            _ => EffectKind::Saturation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DistortionSettings {
+    pub mode: SaturationMode,
+    // Gain into the waveshaper, in dB
+    pub drive: f32,
+    // Low-pass after the waveshaper to tame the fizz
+    pub tone: u32,
+    pub bits: u32,
+    // Hold every sample this many times
+    pub downsample: u32,
+}
+
+impl DistortionSettings {
+    pub fn new() -> Self {
+        Self {
+            mode: SaturationMode::SoftClip,
+            drive: 12.0,
+            tone: 8000,
+            bits: 8,
+            downsample: 4,
+        }
+    }
+
+    pub fn describe(&self, param: DistortionParam) -> (&'static str, String) {
+        match param {
+            DistortionParam::Mode => ("Curve", self.mode.name().to_string()),
+            DistortionParam::Drive => ("Drive", format!("{:+.0} dB", self.drive)),
+            DistortionParam::Tone => ("Tone", format!("{}Hz", self.tone)),
+            DistortionParam::Bits => ("Bit depth", format!("{} bit", self.bits)),
+            DistortionParam::Downsample => ("Sample rate", format!("1/{}", self.downsample)),
+        }
+    }
+
+    pub fn change(&mut self, param: DistortionParam, increase: bool) {
+        match param {
+            DistortionParam::Mode => self.mode = self.mode.next(),
+            DistortionParam::Drive => {
+                self.drive = if increase {
+                    (self.drive + 1.0).min(36.0)
+                } else {
+                    (self.drive - 1.0).max(0.0)
+                };
+            }
+            DistortionParam::Tone => {
+                self.tone = if increase {
+                    (self.tone + 500).min(20000)
+                } else {
+                    self.tone.saturating_sub(500).max(500)
+                };
+            }
+            DistortionParam::Bits => {
+                self.bits = if increase {
+                    (self.bits + 1).min(16)
+                } else {
+                    self.bits.saturating_sub(1).max(1)
+                };
+            }
+            DistortionParam::Downsample => {
+                self.downsample = if increase {
+                    (self.downsample + 1).min(32)
+                } else {
+                    self.downsample.saturating_sub(1).max(1)
+                };
+            }
+        }
+    }
+
+    pub fn clamp(&mut self) {
+        self.drive = limit(self.drive, 0.0, 36.0);
+        self.tone = self.tone.clamp(500, 20000);
+        self.bits = self.bits.clamp(1, 16);
+        self.downsample = self.downsample.clamp(1, 32);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+impl FilterType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterType::LowPass => "Low-pass",
+            FilterType::HighPass => "High-pass",
+            FilterType::BandPass => "Band-pass",
+            FilterType::Notch => "Notch",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            FilterType::LowPass => FilterType::HighPass,
+            FilterType::HighPass => FilterType::BandPass,
+            FilterType::BandPass => FilterType::Notch,
+            FilterType::Notch => FilterType::LowPass,
+        }
+    }
+}
+
+// The filter parameters, in the order they're listed in Synth Filter mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterParam {
+    Type,
+    Slope,
+    Cutoff,
+    Resonance,
+    EnvAmount,
+    Envelope(AdsrParam),
+    LfoRate,
+    LfoDepth,
+}
+
+pub const FILTER_PARAMS: [FilterParam; 11] = [
+    FilterParam::Type,
+    FilterParam::Slope,
+    FilterParam::Cutoff,
+    FilterParam::Resonance,
+    FilterParam::EnvAmount,
+    FilterParam::Envelope(AdsrParam::Attack),
+    FilterParam::Envelope(AdsrParam::Decay),
+    FilterParam::Envelope(AdsrParam::Sustain),
+    FilterParam::Envelope(AdsrParam::Release),
+    FilterParam::LfoRate,
+    FilterParam::LfoDepth,
+];
+
+// Synth style state-variable filter. Envelope and LFO amounts are in
+// octaves the cutoff is moved by
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FilterSettings {
+    pub filter_type: FilterType,
+    // 24 dB/octave instead of 12
+    pub steep: bool,
+    pub cutoff: f32,
+    // 0.0 - 1.0, self-oscillation is kept just out of reach
+    pub resonance: f32,
+    pub envelope: AdsrSettings,
+    pub env_amount: f32,
+    pub lfo_rate: f32,
+    pub lfo_depth: f32,
+}
+
+impl FilterSettings {
+    pub fn new() -> Self {
+        Self {
+            filter_type: FilterType::LowPass,
+            steep: false,
+            cutoff: 800.0,
+            resonance: 0.3,
+            envelope: AdsrSettings::new(5.0, 300.0, 0.2, 300.0),
+            env_amount: 3.0,
+            lfo_rate: 0.5,
+            lfo_depth: 0.0,
+        }
+    }
+
+    pub fn describe(&self, param: FilterParam) -> (&'static str, String) {
+        match param {
+            FilterParam::Type => ("Type", self.filter_type.name().to_string()),
+            FilterParam::Slope => (
+                "Slope",
+                if self.steep { "24 dB/oct" } else { "12 dB/oct" }.to_string(),
+            ),
+            FilterParam::Cutoff => ("Cutoff", format!("{:.0}Hz", self.cutoff)),
+            FilterParam::Resonance => ("Resonance", format!("{:.0}%", self.resonance * 100.0)),
+            FilterParam::EnvAmount => ("Env amount", format!("{:+.1} oct", self.env_amount)),
+            FilterParam::Envelope(adsr) => (
+                match adsr {
+                    AdsrParam::Attack => "Env attack",
+                    AdsrParam::Decay => "Env decay",
+                    AdsrParam::Sustain => "Env sustain",
+                    AdsrParam::Release => "Env release",
+                },
+                self.envelope.describe(adsr),
+            ),
+            FilterParam::LfoRate => ("LFO rate", format!("{:.2}Hz", self.lfo_rate)),
+            FilterParam::LfoDepth => ("LFO depth", format!("{:.1} oct", self.lfo_depth)),
+        }
+    }
+
+    pub fn change(&mut self, param: FilterParam, increase: bool) {
+        match param {
+            FilterParam::Type => self.filter_type = self.filter_type.next(),
+            FilterParam::Slope => self.steep = !self.steep,
+            // A twelfth of an octave per step
+            FilterParam::Cutoff => {
+                let ratio = 2.0f32.powf(1.0 / 12.0);
+                self.cutoff = if increase {
+                    (self.cutoff * ratio).min(20000.0)
+                } else {
+                    (self.cutoff / ratio).max(20.0)
+                };
+            }
+            FilterParam::Resonance => {
+                self.resonance = if increase {
+                    (self.resonance + 0.05).min(1.0)
+                } else {
+                    (self.resonance - 0.05).max(0.0)
+                };
+            }
+            FilterParam::EnvAmount => {
+                self.env_amount = if increase {
+                    (self.env_amount + 0.5).min(8.0)
+                } else {
+                    (self.env_amount - 0.5).max(-8.0)
+                };
+            }
+            FilterParam::Envelope(adsr) => self.envelope.change(adsr, increase),
+            FilterParam::LfoRate => {
+                self.lfo_rate = if increase {
+                    (self.lfo_rate * 1.2).min(20.0)
+                } else {
+                    (self.lfo_rate / 1.2).max(0.05)
+                };
+            }
+            FilterParam::LfoDepth => {
+                self.lfo_depth = if increase {
+                    (self.lfo_depth + 0.1).min(4.0)
+                } else {
+                    (self.lfo_depth - 0.1).max(0.0)
+                };
+            }
+        }
+    }
+
+    pub fn clamp(&mut self) {
+        self.cutoff = limit(self.cutoff, 20.0, 20000.0);
+        self.resonance = limit(self.resonance, 0.0, 1.0);
+        self.envelope.clamp();
+        self.env_amount = limit(self.env_amount, -8.0, 8.0);
+        self.lfo_rate = limit(self.lfo_rate, 0.05, 20.0);
+        self.lfo_depth = limit(self.lfo_depth, 0.0, 4.0);
+    }
+}
+
+// Like `clamp`, but NaN ends up at the bottom of the range instead of
+// staying NaN
+pub fn limit(value: f32, min: f32, max: f32) -> f32 {
+    value.max(min).min(max)
+}
+
+// Where a setting starts, how far one key press moves it and how far it can
+// go. Built in for every simple setting, and overridable from the config file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustable {
+    pub default: f32,
+    pub step: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Adjustable {
+    pub const fn new(default: f32, step: f32, min: f32, max: f32) -> Self {
+        Self {
+            default,
+            step,
+            min,
+            max,
+        }
+    }
+
+    pub fn apply(&self, value: f32, increase: bool) -> f32 {
+        if increase {
+            (value + self.step).min(self.max)
+        } else {
+            (value - self.step).max(self.min)
+        }
+    }
+
+    pub fn clamp(&self, value: f32) -> f32 {
+        limit(value, self.min, self.max)
+    }
+
+    // Position of a value in the range, 0.0 - 1.0, for gauges
+    pub fn fraction(&self, value: f32) -> f32 {
+        ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+    }
+
+    // The other way round, for clicks on a gauge. Lands on a whole number
+    // of steps, like the keys would
+    pub fn at_fraction(&self, fraction: f32) -> f32 {
+        let steps = (fraction.clamp(0.0, 1.0) * (self.max - self.min) / self.step).round();
+        (self.min + steps * self.step).clamp(self.min, self.max)
+    }
+}
+
+pub const VOLUME: Adjustable = Adjustable::new(1.0, 0.1, 0.0, 2.0);
+pub const SPEED: Adjustable = Adjustable::new(1.0, 0.1, 0.1, 3.0);
+pub const LOWPASS: Adjustable = Adjustable::new(20000.0, 500.0, 500.0, 20000.0);
+pub const BALANCE: Adjustable = Adjustable::new(0.0, 0.1, -1.0, 1.0);
+pub const STEREO_WIDTH: Adjustable = Adjustable::new(1.0, 0.1, 0.0, 2.0);
+
+// A position in the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainSlot {
+    pub kind: EffectKind,
+    pub bypassed: bool,
+}
+
 // Main effect manager to handle all audio effects
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EffectManager {
     pub playback_speed: f32,
     pub volume: f32,
     pub lowpass_cutoff: u32,
-    pub reverb_enabled: bool,
     pub reverb_delay: f32,
+    // -1.0 (left) to 1.0 (right)
+    pub balance: f32,
+    // Side level: 0.0 is mono, 1.0 unchanged, 2.0 extra wide
+    pub stereo_width: f32,
+    pub mono: bool,
+    pub swap_channels: bool,
+    // Gate, compressor and limiter on the master bus
+    pub dynamics: DynamicsSettings,
+    pub delay: DelaySettings,
+    pub modulation: ModulationSettings,
+    pub distortion: DistortionSettings,
+    pub filter: FilterSettings,
+    // Volume envelope of every sound, released when it's stopped
+    pub amp_envelope: AdsrSettings,
+    // Effects in the order they're applied
+    pub chain: Vec<ChainSlot>,
 }
 
 impl EffectManager {
@@ -13,8 +830,41 @@ impl EffectManager {
             playback_speed: 1.0,
             volume: 1.0,
             lowpass_cutoff: 20000,
-            reverb_enabled: false,
             reverb_delay: 0.06,
+            balance: 0.0,
+            stereo_width: 1.0,
+            mono: false,
+            swap_channels: false,
+            dynamics: DynamicsSettings::new(),
+            delay: DelaySettings::new(),
+            modulation: ModulationSettings::new(),
+            distortion: DistortionSettings::new(),
+            filter: FilterSettings::new(),
+            // Short enough to sound like no envelope, long enough not to click
+            amp_envelope: AdsrSettings::new(2.0, 0.0, 1.0, 30.0),
+            // Same order the effects were always applied in
+            chain: vec![
+                ChainSlot {
+                    kind: EffectKind::Gain,
+                    bypassed: false,
+                },
+                ChainSlot {
+                    kind: EffectKind::Reverb,
+                    bypassed: true,
+                },
+                ChainSlot {
+                    kind: EffectKind::Delay,
+                    bypassed: true,
+                },
+                ChainSlot {
+                    kind: EffectKind::LowPass,
+                    bypassed: false,
+                },
+                ChainSlot {
+                    kind: EffectKind::Stereo,
+                    bypassed: false,
+                },
+            ],
         }
     }
 
@@ -23,12 +873,8 @@ impl EffectManager {
         self.volume
     }
 
-    pub fn change_volume(&mut self, increase: bool) {
-        if increase {
-            self.volume = (self.volume + 0.1).min(2.0);
-        } else {
-            self.volume = (self.volume - 0.1).max(0.0);
-        }
+    pub fn change_volume(&mut self, increase: bool, range: &Adjustable) {
+        self.volume = range.apply(self.volume, increase);
     }
 
     // Pitch/speed methods
@@ -36,12 +882,8 @@ impl EffectManager {
         self.playback_speed
     }
 
-    pub fn change_pitch(&mut self, increase: bool) {
-        if increase {
-            self.playback_speed = (self.playback_speed + 0.1).min(3.0);
-        } else {
-            self.playback_speed = (self.playback_speed - 0.1).max(0.1);
-        }
+    pub fn change_pitch(&mut self, increase: bool, range: &Adjustable) {
+        self.playback_speed = range.apply(self.playback_speed, increase);
     }
 
     // Lowpass filter methods
@@ -49,17 +891,13 @@ impl EffectManager {
         self.lowpass_cutoff
     }
 
-    pub fn change_lowpass(&mut self, increase: bool) {
-        if increase {
-            self.lowpass_cutoff = (self.lowpass_cutoff + 500).min(20000);
-        } else {
-            self.lowpass_cutoff = (self.lowpass_cutoff - 500).max(500);
-        }
+    pub fn change_lowpass(&mut self, increase: bool, range: &Adjustable) {
+        self.lowpass_cutoff = range.apply(self.lowpass_cutoff as f32, increase).round() as u32;
     }
 
     // Reverb methods
     pub fn is_reverb_enabled(&self) -> bool {
-        self.reverb_enabled
+        self.is_active(EffectKind::Reverb)
     }
 
     pub fn get_reverb_delay(&self) -> f32 {
@@ -67,6 +905,1408 @@ impl EffectManager {
     }
 
     pub fn toggle_reverb(&mut self) {
-        self.reverb_enabled = !self.reverb_enabled;
+        self.toggle_effect(EffectKind::Reverb);
+    }
+
+    // Stereo methods
+    pub fn get_balance(&self) -> f32 {
+        self.balance
+    }
+
+    pub fn change_balance(&mut self, right: bool, range: &Adjustable) {
+        self.balance = range.apply(self.balance, right);
+        // Keep "center" exactly at zero after a few steps
+        if self.balance.abs() < 0.001 {
+            self.balance = 0.0;
+        }
+    }
+
+    pub fn center_balance(&mut self) {
+        self.balance = 0.0;
+    }
+
+    pub fn get_stereo_width(&self) -> f32 {
+        self.stereo_width
+    }
+
+    pub fn change_stereo_width(&mut self, increase: bool, range: &Adjustable) {
+        self.stereo_width = range.apply(self.stereo_width, increase);
+    }
+
+    pub fn toggle_mono(&mut self) {
+        self.mono = !self.mono;
+    }
+
+    pub fn toggle_swap_channels(&mut self) {
+        self.swap_channels = !self.swap_channels;
+    }
+
+    // Bring settings from outside (a hand-edited preset) back into the
+    // ranges the keys keep them in. The simple settings have their ranges
+    // in the config, see `Config::clamp_effects`
+    pub fn clamp(&mut self) {
+        self.reverb_delay = limit(self.reverb_delay, 0.001, 2.0);
+        self.dynamics.clamp();
+        self.delay.clamp();
+        self.modulation.clamp();
+        self.distortion.clamp();
+        self.filter.clamp();
+        self.amp_envelope.clamp();
+
+        // Every effect is in the chain once at most
+        let mut seen = Vec::new();
+        self.chain.retain(|slot| {
+            let first = !seen.contains(&slot.kind);
+            seen.push(slot.kind);
+            first
+        });
+    }
+
+    // Chain methods
+    pub fn is_active(&self, kind: EffectKind) -> bool {
+        self.chain
+            .iter()
+            .any(|slot| slot.kind == kind && !slot.bypassed)
+    }
+
+    // Bypass/enable an effect, adding it to the end of the chain if needed
+    pub fn toggle_effect(&mut self, kind: EffectKind) {
+        match self.chain.iter_mut().find(|slot| slot.kind == kind) {
+            Some(slot) => slot.bypassed = !slot.bypassed,
+            None => self.chain.push(ChainSlot {
+                kind,
+                bypassed: false,
+            }),
+        }
+    }
+
+    pub fn toggle_bypass(&mut self, index: usize) {
+        if let Some(slot) = self.chain.get_mut(index) {
+            slot.bypassed = !slot.bypassed;
+        }
+    }
+
+    // Effects that aren't in the chain yet
+    pub fn available_effects(&self) -> Vec<EffectKind> {
+        ALL_EFFECTS
+            .iter()
+            .copied()
+            .filter(|kind| !self.chain.iter().any(|slot| slot.kind == *kind))
+            .collect()
+    }
+
+    pub fn add_effect(&mut self, kind: EffectKind, index: usize) {
+        if self.chain.iter().any(|slot| slot.kind == kind) {
+            return;
+        }
+        let index = index.min(self.chain.len());
+        self.chain.insert(
+            index,
+            ChainSlot {
+                kind,
+                bypassed: false,
+            },
+        );
+    }
+
+    pub fn remove_effect(&mut self, index: usize) {
+        if index < self.chain.len() {
+            self.chain.remove(index);
+        }
+    }
+
+    // Swap an effect with its neighbour, returns where it ended up
+    pub fn move_effect(&mut self, index: usize, up: bool) -> usize {
+        if up && index > 0 && index < self.chain.len() {
+            self.chain.swap(index, index - 1);
+            index - 1
+        } else if !up && index + 1 < self.chain.len() {
+            self.chain.swap(index, index + 1);
+            index + 1
+        } else {
+            index
+        }
+    }
+}
+
+// Volume
+struct Gain {
+    gain: f32,
+}
+
+impl Gain {
+    fn new() -> Self {
+        Self { gain: 1.0 }
+    }
+}
+
+impl Effect for Gain {
+    fn configure(&mut self, effects: &EffectManager) {
+        self.gain = effects.get_volume();
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        for sample in frame {
+            *sample *= self.gain;
+        }
+    }
+}
+
+// Single echo of the signal, mixed back in under the dry sound
+struct Reverb {
+    buffer: Vec<f32>,
+    position: usize,
+    channels: usize,
+    sample_rate: u32,
+    delay_frames: usize,
+}
+
+const REVERB_LEVEL: f32 = 0.4;
+
+impl Reverb {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            buffer: Vec::new(),
+            position: 0,
+            channels: channels as usize,
+            sample_rate,
+            delay_frames: 0,
+        }
+    }
+}
+
+impl Effect for Reverb {
+    fn configure(&mut self, effects: &EffectManager) {
+        let delay_frames = ((effects.get_reverb_delay() * self.sample_rate as f32) as usize).max(1);
+        if delay_frames != self.delay_frames {
+            self.delay_frames = delay_frames;
+            self.buffer = vec![0.0; delay_frames * self.channels];
+            self.position = 0;
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let start = self.position * self.channels;
+        for (sample, delayed) in frame
+            .iter_mut()
+            .zip(&mut self.buffer[start..start + self.channels])
+        {
+            let dry = *sample;
+            *sample += *delayed * REVERB_LEVEL;
+            *delayed = dry;
+        }
+        self.position = (self.position + 1) % self.delay_frames;
+    }
+
+    fn tail(&self) -> usize {
+        self.delay_frames
+    }
+}
+
+// Second order low-pass (RBJ cookbook, Q = 0.5 like rodio's low_pass)
+struct LowPass {
+    channels: usize,
+    sample_rate: u32,
+    cutoff: u32,
+    coefficients: [f32; 5],
+    // x1, x2, y1, y2 per channel
+    state: Vec<[f32; 4]>,
+}
+
+impl LowPass {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels: channels as usize,
+            sample_rate,
+            cutoff: 0,
+            coefficients: [1.0, 0.0, 0.0, 0.0, 0.0],
+            state: vec![[0.0; 4]; channels as usize],
+        }
+    }
+}
+
+impl Effect for LowPass {
+    fn configure(&mut self, effects: &EffectManager) {
+        let cutoff = effects.get_lowpass_cutoff();
+        if cutoff == self.cutoff {
+            return;
+        }
+        self.cutoff = cutoff;
+
+        let w0 = 2.0 * PI * cutoff as f32 / self.sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * 0.5);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha;
+
+        let b1 = (1.0 - cos_w0) / a0;
+        let b0 = b1 / 2.0;
+        self.coefficients = [b0, b1, b0, -2.0 * cos_w0 / a0, (1.0 - alpha) / a0];
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        // 20kHz is the "off" position of the knob
+        if self.cutoff >= 20000 {
+            return;
+        }
+
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        for (sample, state) in frame.iter_mut().zip(&mut self.state).take(self.channels) {
+            let [x1, x2, y1, y2] = *state;
+            let x0 = *sample;
+            let y0 = b0 * x0 + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+            *state = [x0, x1, y0, y1];
+            *sample = y0;
+        }
+    }
+}
+
+// L/R swap, mid/side width, mono sum and balance, in that order.
+// Only does anything on stereo material
+struct Stereo {
+    balance: f32,
+    width: f32,
+    mono: bool,
+    swap: bool,
+}
+
+impl Stereo {
+    fn new() -> Self {
+        Self {
+            balance: 0.0,
+            width: 1.0,
+            mono: false,
+            swap: false,
+        }
+    }
+}
+
+impl Effect for Stereo {
+    fn configure(&mut self, effects: &EffectManager) {
+        self.balance = effects.get_balance();
+        self.width = effects.get_stereo_width();
+        self.mono = effects.mono;
+        self.swap = effects.swap_channels;
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let [left, right] = frame else {
+            return;
+        };
+        if self.swap {
+            std::mem::swap(left, right);
+        }
+
+        let mid = (*left + *right) * 0.5;
+        let side = if self.mono {
+            0.0
+        } else {
+            (*left - *right) * 0.5 * self.width
+        };
+
+        // Balance only turns the opposite side down, center stays at unity
+        *left = (mid + side) * (1.0 - self.balance.max(0.0));
+        *right = (mid - side) * (1.0 + self.balance.min(0.0));
+    }
+}
+
+// Delay line with feedback. Repeats go through a high-cut, and in ping-pong
+// mode bounce between the left and right channel
+struct Delay {
+    channels: usize,
+    sample_rate: u32,
+    buffer: Vec<f32>,
+    // Frames in the buffer
+    length: usize,
+    position: usize,
+    // Current delay in frames, glides towards the target so changing the
+    // time doesn't click
+    delay: f32,
+    target: f32,
+    feedback: f32,
+    mix: f32,
+    ping_pong: bool,
+    cut_coeff: f32,
+    // High-cut state per channel
+    cut_state: Vec<f32>,
+    // Scratch space for the repeats of the current frame
+    repeats: Vec<f32>,
+}
+
+impl Delay {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels as usize;
+        let length = (MAX_DELAY_SECS * sample_rate as f32) as usize + 2;
+        Self {
+            channels,
+            sample_rate,
+            buffer: vec![0.0; length * channels],
+            length,
+            position: 0,
+            delay: 0.0,
+            target: 0.0,
+            feedback: 0.0,
+            mix: 0.0,
+            ping_pong: false,
+            cut_coeff: 0.0,
+            cut_state: vec![0.0; channels],
+            repeats: vec![0.0; channels],
+        }
+    }
+
+    // Linear interpolation between the two frames around `delay`
+    fn read(&self, channel: usize, delay: f32) -> f32 {
+        let whole = delay.floor() as usize;
+        let fraction = delay - whole as f32;
+        let index = |back: usize| {
+            let frame = (self.position + self.length - back) % self.length;
+            self.buffer[frame * self.channels + channel]
+        };
+        index(whole) * (1.0 - fraction) + index(whole + 1) * fraction
+    }
+}
+
+impl Effect for Delay {
+    fn configure(&mut self, effects: &EffectManager) {
+        let settings = &effects.delay;
+        self.target =
+            (settings.delay_secs() * self.sample_rate as f32).clamp(1.0, (self.length - 2) as f32);
+        // First time around, start right at the target
+        if self.delay == 0.0 {
+            self.delay = self.target;
+        }
+        self.feedback = settings.feedback;
+        self.mix = settings.mix;
+        self.ping_pong = settings.ping_pong && self.channels == 2;
+        self.cut_coeff = (-2.0 * PI * settings.high_cut as f32 / self.sample_rate as f32).exp();
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        self.delay += (self.target - self.delay) * 0.0005;
+
+        for channel in 0..self.channels {
+            let delayed = self.read(channel, self.delay);
+            let state = &mut self.cut_state[channel];
+            *state = delayed + (*state - delayed) * self.cut_coeff;
+            self.repeats[channel] = *state;
+        }
+
+        let start = self.position * self.channels;
+        if self.ping_pong {
+            // The dry sound goes in on the left, repeats swap sides
+            let input = (frame[0] + frame[1]) * 0.5;
+            self.buffer[start] = input + self.repeats[1] * self.feedback;
+            self.buffer[start + 1] = self.repeats[0] * self.feedback;
+        } else {
+            for (channel, sample) in frame.iter().enumerate().take(self.channels) {
+                self.buffer[start + channel] = sample + self.repeats[channel] * self.feedback;
+            }
+        }
+        self.position = (self.position + 1) % self.length;
+
+        for (sample, repeat) in frame.iter_mut().zip(&self.repeats) {
+            *sample = *sample * (1.0 - self.mix) + repeat * self.mix;
+        }
+    }
+
+    fn tail(&self) -> usize {
+        // Until the repeats have died down to -60dB
+        let repeats = if self.feedback > 0.0 {
+            (0.001f32.ln() / self.feedback.ln()).ceil() as usize
+        } else {
+            0
+        };
+        (self.target as usize * (repeats + 1)).min(self.length * 8)
+    }
+}
+
+// Sine LFO
+struct Lfo {
+    phase: f32,
+    increment: f32,
+    sample_rate: u32,
+}
+
+impl Lfo {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            phase: 0.0,
+            increment: 0.0,
+            sample_rate,
+        }
+    }
+
+    fn set_rate(&mut self, rate: f32) {
+        self.increment = rate / self.sample_rate as f32;
+    }
+
+    fn advance(&mut self) {
+        self.phase = (self.phase + self.increment).fract();
+    }
+
+    // -1.0 - 1.0, `offset` shifts the phase (in cycles)
+    fn value(&self, offset: f32) -> f32 {
+        ((self.phase + offset) * 2.0 * PI).sin()
+    }
+}
+
+// Blend of the dry and processed sample
+fn mix(dry: f32, wet: f32, mix: f32) -> f32 {
+    dry * (1.0 - mix) + wet * mix
+}
+
+// Chorus and flanger: a short delay line swept by the LFO. The flanger uses
+// shorter times and feeds back for the jet sound
+struct ModulatedDelay {
+    kind: EffectKind,
+    channels: usize,
+    sample_rate: u32,
+    lfo: Lfo,
+    buffer: Vec<f32>,
+    length: usize,
+    position: usize,
+    params: ModulationParams,
+}
+
+impl ModulatedDelay {
+    fn new(kind: EffectKind, channels: u16, sample_rate: u32) -> Self {
+        let channels = channels as usize;
+        // 50ms is plenty for both
+        let length = (0.05 * sample_rate as f32) as usize + 2;
+        Self {
+            kind,
+            channels,
+            sample_rate,
+            lfo: Lfo::new(sample_rate),
+            buffer: vec![0.0; length * channels],
+            length,
+            position: 0,
+            params: ModulationParams::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    // Shortest delay and how far it sweeps, in ms
+    fn range(&self) -> (f32, f32) {
+        match self.kind {
+            EffectKind::Flanger => (1.0, 5.0),
+            _ => (7.0, 15.0),
+        }
+    }
+
+    fn feedback(&self) -> f32 {
+        match self.kind {
+            EffectKind::Flanger => 0.6,
+            _ => 0.0,
+        }
+    }
+}
+
+impl Effect for ModulatedDelay {
+    fn configure(&mut self, effects: &EffectManager) {
+        if let Some(params) = effects.modulation.get(self.kind) {
+            self.params = *params;
+            self.lfo.set_rate(params.rate);
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let (min_ms, sweep_ms) = self.range();
+        let feedback = self.feedback();
+        let start = self.position * self.channels;
+
+        for (channel, sample) in frame.iter_mut().enumerate().take(self.channels) {
+            // Channels sweep a quarter cycle apart for some stereo movement
+            let sweep = 0.5 + 0.5 * self.lfo.value(channel as f32 * 0.25);
+            let delay_ms = min_ms + sweep_ms * self.params.depth * sweep;
+            let delay =
+                (delay_ms / 1000.0 * self.sample_rate as f32).clamp(1.0, (self.length - 2) as f32);
+
+            let whole = delay.floor() as usize;
+            let fraction = delay - whole as f32;
+            let read = |back: usize| {
+                let frame = (self.position + self.length - back) % self.length;
+                self.buffer[frame * self.channels + channel]
+            };
+            let wet = read(whole) * (1.0 - fraction) + read(whole + 1) * fraction;
+
+            self.buffer[start + channel] = *sample + wet * feedback;
+            *sample = mix(*sample, wet, self.params.mix);
+        }
+
+        self.position = (self.position + 1) % self.length;
+        self.lfo.advance();
+    }
+
+    fn tail(&self) -> usize {
+        self.length
+    }
+}
+
+// Four first order all-pass stages with a swept corner frequency
+struct Phaser {
+    channels: usize,
+    sample_rate: u32,
+    lfo: Lfo,
+    params: ModulationParams,
+    // Input and output memory of each stage, per channel
+    stages: Vec<[(f32, f32); 4]>,
+    last: Vec<f32>,
+}
+
+impl Phaser {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels: channels as usize,
+            sample_rate,
+            lfo: Lfo::new(sample_rate),
+            params: ModulationParams::new(1.0, 0.0, 0.0),
+            stages: vec![[(0.0, 0.0); 4]; channels as usize],
+            last: vec![0.0; channels as usize],
+        }
+    }
+}
+
+impl Effect for Phaser {
+    fn configure(&mut self, effects: &EffectManager) {
+        self.params = effects.modulation.phaser;
+        self.lfo.set_rate(self.params.rate);
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        // Sweep from 200Hz up to as far as 3.2kHz, evenly in octaves
+        let sweep = 0.5 + 0.5 * self.lfo.value(0.0);
+        let frequency = 200.0 * 16.0f32.powf(sweep * self.params.depth);
+        let t = (PI * frequency / self.sample_rate as f32).tan();
+        let coefficient = (t - 1.0) / (t + 1.0);
+
+        for ((sample, stages), last) in frame
+            .iter_mut()
+            .zip(&mut self.stages)
+            .zip(&mut self.last)
+            .take(self.channels)
+        {
+            let mut signal = *sample + *last * 0.3;
+            for (x1, y1) in stages.iter_mut() {
+                let y = coefficient * signal + *x1 - coefficient * *y1;
+                *x1 = signal;
+                *y1 = y;
+                signal = y;
+            }
+            *last = signal;
+            *sample = mix(*sample, signal, self.params.mix);
+        }
+        self.lfo.advance();
+    }
+}
+
+// Volume going up and down
+struct Tremolo {
+    lfo: Lfo,
+    params: ModulationParams,
+}
+
+impl Tremolo {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            lfo: Lfo::new(sample_rate),
+            params: ModulationParams::new(1.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl Effect for Tremolo {
+    fn configure(&mut self, effects: &EffectManager) {
+        self.params = effects.modulation.tremolo;
+        self.lfo.set_rate(self.params.rate);
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let gain = 1.0 - self.params.depth * (0.5 + 0.5 * self.lfo.value(0.0));
+        for sample in frame {
+            *sample = mix(*sample, *sample * gain, self.params.mix);
+        }
+        self.lfo.advance();
+    }
+}
+
+// Balance moving between left and right. Only does anything on stereo
+struct AutoPan {
+    lfo: Lfo,
+    params: ModulationParams,
+}
+
+impl AutoPan {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            lfo: Lfo::new(sample_rate),
+            params: ModulationParams::new(1.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl Effect for AutoPan {
+    fn configure(&mut self, effects: &EffectManager) {
+        self.params = effects.modulation.auto_pan;
+        self.lfo.set_rate(self.params.rate);
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let pan = self.params.depth * self.lfo.value(0.0);
+        self.lfo.advance();
+
+        if let [left, right] = frame {
+            // Equal-power, so the sound doesn't dip on its way across.
+            // Unity on both sides in the middle
+            let angle = (pan + 1.0) * FRAC_PI_4;
+            *left = mix(*left, *left * angle.cos() * SQRT_2, self.params.mix);
+            *right = mix(*right, *right * angle.sin() * SQRT_2, self.params.mix);
+        }
+    }
+}
+
+// Second order filter section (RBJ cookbook)
+#[derive(Clone, Copy)]
+struct Biquad {
+    coefficients: [f32; 5],
+    // x1, x2, y1, y2
+    state: [f32; 4],
+}
+
+impl Biquad {
+    fn low_pass(cutoff: f32, sample_rate: f32, q: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha;
+
+        let b1 = (1.0 - cos_w0) / a0;
+        let b0 = b1 / 2.0;
+        Self {
+            coefficients: [b0, b1, b0, -2.0 * cos_w0 / a0, (1.0 - alpha) / a0],
+            state: [0.0; 4],
+        }
+    }
+
+    fn process(&mut self, x0: f32) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let [x1, x2, y1, y2] = self.state;
+        let y0 = b0 * x0 + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+        self.state = [x0, x1, y0, y1];
+        y0
+    }
+}
+
+// How many times the saturation runs faster than the sound, so the
+// harmonics it adds don't fold back down as aliasing
+const OVERSAMPLING: usize = 4;
+
+// 8th order Butterworth low-pass, used on the way up and on the way down
+#[derive(Clone, Copy)]
+struct HalfBand {
+    sections: [Biquad; 4],
+}
+
+impl HalfBand {
+    fn new(sample_rate: u32) -> Self {
+        // Just under the original Nyquist frequency, at the oversampled rate
+        let cutoff = sample_rate as f32 * 0.45;
+        let rate = (sample_rate as usize * OVERSAMPLING) as f32;
+        Self {
+            sections: [
+                Biquad::low_pass(cutoff, rate, 0.510),
+                Biquad::low_pass(cutoff, rate, 0.601),
+                Biquad::low_pass(cutoff, rate, 0.900),
+                Biquad::low_pass(cutoff, rate, 2.563),
+            ],
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        self.sections
+            .iter_mut()
+            .fold(x, |signal, section| section.process(signal))
+    }
+}
+
+// Drive into a waveshaper, then a tone control
+struct Saturation {
+    sample_rate: u32,
+    mode: SaturationMode,
+    drive: f32,
+    tone: u32,
+    // Per channel: upsampling filter, downsampling filter, tone filter state
+    up: Vec<HalfBand>,
+    down: Vec<HalfBand>,
+    tone_state: Vec<f32>,
+    tone_coeff: f32,
+}
+
+impl Saturation {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        let filter = HalfBand::new(sample_rate);
+        Self {
+            sample_rate,
+            mode: SaturationMode::SoftClip,
+            drive: 1.0,
+            tone: 0,
+            up: vec![filter; channels as usize],
+            down: vec![filter; channels as usize],
+            tone_state: vec![0.0; channels as usize],
+            tone_coeff: 0.0,
+        }
+    }
+}
+
+impl Effect for Saturation {
+    fn configure(&mut self, effects: &EffectManager) {
+        let settings = &effects.distortion;
+        self.mode = settings.mode;
+        self.drive = 10.0f32.powf(settings.drive / 20.0);
+        if settings.tone != self.tone {
+            self.tone = settings.tone;
+            self.tone_coeff = (-2.0 * PI * self.tone as f32 / self.sample_rate as f32).exp();
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        for (channel, sample) in frame.iter_mut().enumerate().take(self.up.len()) {
+            let up = &mut self.up[channel];
+            let down = &mut self.down[channel];
+
+            // Zero stuffing, the filters fill in the gaps
+            let mut output = 0.0;
+            for step in 0..OVERSAMPLING {
+                let input = if step == 0 {
+                    *sample * OVERSAMPLING as f32
+                } else {
+                    0.0
+                };
+                let shaped = self.mode.shape(up.process(input) * self.drive);
+                let filtered = down.process(shaped);
+                if step == 0 {
+                    output = filtered;
+                }
+            }
+
+            let state = &mut self.tone_state[channel];
+            *state = output + (*state - output) * self.tone_coeff;
+            *sample = *state;
+        }
+    }
+}
+
+// Fewer bits and a lower sample rate. Aliasing is the point here, so no
+// oversampling
+struct Bitcrusher {
+    bits: u32,
+    downsample: u32,
+    counter: u32,
+    held: Vec<f32>,
+}
+
+impl Bitcrusher {
+    fn new(channels: u16) -> Self {
+        Self {
+            bits: 16,
+            downsample: 1,
+            counter: 0,
+            held: vec![0.0; channels as usize],
+        }
+    }
+}
+
+impl Effect for Bitcrusher {
+    fn configure(&mut self, effects: &EffectManager) {
+        self.bits = effects.distortion.bits.clamp(1, 16);
+        self.downsample = effects.distortion.downsample.max(1);
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        if self.counter == 0 {
+            let levels = (1u32 << (self.bits - 1)) as f32;
+            for (held, sample) in self.held.iter_mut().zip(frame.iter()) {
+                *held = (sample * levels).round() / levels;
+            }
+        }
+        self.counter = (self.counter + 1) % self.downsample;
+
+        for (sample, held) in frame.iter_mut().zip(&self.held) {
+            *sample = *held;
+        }
+    }
+}
+
+// One state-variable filter stage (Simper's trapezoidal SVF)
+#[derive(Clone, Copy)]
+struct SvfStage {
+    ic1: f32,
+    ic2: f32,
+}
+
+impl SvfStage {
+    // `g` and `k` set cutoff and damping
+    fn process(&mut self, input: f32, g: f32, k: f32, filter_type: FilterType) -> f32 {
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = input - self.ic2;
+        let v1 = a1 * self.ic1 + a2 * v3;
+        let v2 = self.ic2 + a2 * self.ic1 + a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+
+        match filter_type {
+            FilterType::LowPass => v2,
+            FilterType::HighPass => input - k * v1 - v2,
+            FilterType::BandPass => v1,
+            FilterType::Notch => input - k * v1,
+        }
+    }
+}
+
+// Resonant multimode filter whose cutoff follows an envelope started with
+// the sound, plus an LFO
+struct Filter {
+    sample_rate: u32,
+    settings: FilterSettings,
+    envelope: Envelope,
+    lfo: Lfo,
+    // Two stages per channel, the second one only for 24 dB/octave
+    stages: Vec<[SvfStage; 2]>,
+}
+
+impl Filter {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            settings: FilterSettings::new(),
+            envelope: Envelope::new(),
+            lfo: Lfo::new(sample_rate),
+            stages: vec![[SvfStage { ic1: 0.0, ic2: 0.0 }; 2]; channels as usize],
+        }
+    }
+}
+
+impl Effect for Filter {
+    fn configure(&mut self, effects: &EffectManager) {
+        self.settings = effects.filter;
+        self.lfo.set_rate(self.settings.lfo_rate);
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let settings = &self.settings;
+        let envelope = self.envelope.next(&settings.envelope, self.sample_rate);
+        let octaves = envelope * settings.env_amount + self.lfo.value(0.0) * settings.lfo_depth;
+        self.lfo.advance();
+
+        let cutoff =
+            (settings.cutoff * 2.0f32.powf(octaves)).clamp(20.0, self.sample_rate as f32 * 0.45);
+        let g = (PI * cutoff / self.sample_rate as f32).tan();
+        // Cascaded stages get less resonance each so the peak stays sane
+        let resonance = if settings.steep {
+            settings.resonance * 0.7
+        } else {
+            settings.resonance
+        };
+        let k = 2.0 - 1.95 * resonance;
+
+        for (sample, stages) in frame.iter_mut().zip(&mut self.stages) {
+            let mut signal = stages[0].process(*sample, g, k, settings.filter_type);
+            if settings.steep {
+                signal = stages[1].process(signal, g, k, settings.filter_type);
+            }
+            *sample = signal;
+        }
+    }
+
+    fn release(&mut self) {
+        self.envelope.release();
+    }
+
+    fn tail(&self) -> usize {
+        (self.settings.envelope.release / 1000.0 * self.sample_rate as f32) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // At 1 kHz every millisecond is one frame
+    const RATE: u32 = 1000;
+
+    // Run interleaved audio through a freshly built effect
+    fn run(
+        kind: EffectKind,
+        effects: &EffectManager,
+        channels: u16,
+        sample_rate: u32,
+        input: &[f32],
+    ) -> Vec<f32> {
+        let mut effect = kind.build(channels, sample_rate);
+        effect.configure(effects);
+        input
+            .chunks(channels as usize)
+            .flat_map(|frame| {
+                let mut frame = frame.to_vec();
+                effect.process(&mut frame);
+                frame
+            })
+            .collect()
+    }
+
+    // A single full-scale frame, then silence
+    fn impulse(frames: usize, channels: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; frames * channels];
+        samples[..channels].fill(1.0);
+        samples
+    }
+
+    fn left(samples: &[f32]) -> Vec<f32> {
+        samples.iter().step_by(2).copied().collect()
+    }
+
+    fn right(samples: &[f32]) -> Vec<f32> {
+        samples.iter().skip(1).step_by(2).copied().collect()
+    }
+
+    // Frames that aren't silent, with their level
+    fn hits(samples: &[f32]) -> Vec<(usize, f32)> {
+        samples
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.abs() > 1e-6)
+            .map(|(i, s)| (i, *s))
+            .collect()
+    }
+
+    // Only the repeats, with the high-cut out of the way
+    fn wet_delay() -> EffectManager {
+        let mut effects = EffectManager::new();
+        effects.delay.mix = 1.0;
+        effects.delay.feedback = 0.5;
+        effects.delay.high_cut = 20000;
+        effects
+    }
+
+    #[test]
+    fn clamps_settings_from_outside() {
+        let mut effects = EffectManager::new();
+        effects.reverb_delay = -1.0;
+        effects.delay.high_cut = 100;
+        effects.delay.feedback = f32::NAN;
+        effects.distortion.tone = 0;
+        effects.distortion.bits = 0;
+        effects.distortion.downsample = 0;
+        effects.dynamics.ratio = 0.0;
+        effects.modulation.flanger.rate = 1000.0;
+        effects.filter.envelope.sustain = 2.0;
+        effects.amp_envelope.attack = -5.0;
+        effects.chain.push(effects.chain[0]);
+        effects.clamp();
+
+        assert_eq!(effects.reverb_delay, 0.001);
+        assert_eq!(effects.delay.high_cut, 500);
+        assert_eq!(effects.delay.feedback, 0.0);
+        assert_eq!(effects.distortion.tone, 500);
+        assert_eq!(effects.distortion.bits, 1);
+        assert_eq!(effects.distortion.downsample, 1);
+        assert_eq!(effects.dynamics.ratio, 1.0);
+        assert_eq!(effects.modulation.flanger.rate, 20.0);
+        assert_eq!(effects.filter.envelope.sustain, 1.0);
+        assert_eq!(effects.amp_envelope.attack, 0.0);
+        assert_eq!(effects.chain, EffectManager::new().chain);
+    }
+
+    #[test]
+    fn steps_down_from_below_the_range() {
+        let mut delay = DelaySettings::new();
+        delay.high_cut = 100;
+        delay.change(DelayParam::HighCut, false);
+        assert_eq!(delay.high_cut, 500);
+
+        let mut distortion = DistortionSettings::new();
+        distortion.tone = 100;
+        distortion.bits = 0;
+        distortion.downsample = 0;
+        for param in [
+            DistortionParam::Tone,
+            DistortionParam::Bits,
+            DistortionParam::Downsample,
+        ] {
+            distortion.change(param, false);
+        }
+        assert_eq!(
+            (distortion.tone, distortion.bits, distortion.downsample),
+            (500, 1, 1)
+        );
+    }
+
+    #[test]
+    fn bitcrusher_takes_any_bit_depth() {
+        for bits in [0, 1, 16, 40] {
+            let mut effects = EffectManager::new();
+            effects.distortion.bits = bits;
+            effects.distortion.downsample = 0;
+            let mut crusher = EffectKind::Bitcrusher.build(2, 44100);
+            crusher.configure(&effects);
+
+            let mut frame = [0.3, -0.3];
+            crusher.process(&mut frame);
+            assert!(frame.iter().all(|s| s.abs() <= 1.0), "{} bits", bits);
+        }
+    }
+
+    #[test]
+    fn delay_repeats_after_the_delay_time() {
+        let mut effects = wet_delay();
+        effects.delay.time_ms = 100.0;
+        let output = run(EffectKind::Delay, &effects, 2, RATE, &impulse(400, 2));
+
+        assert_eq!(hits(&left(&output)), [(100, 1.0), (200, 0.5), (300, 0.25)]);
+        assert_eq!(left(&output), right(&output));
+    }
+
+    #[test]
+    fn delay_syncs_to_the_tempo() {
+        let mut effects = wet_delay();
+        effects.delay.feedback = 0.0;
+        effects.delay.sync = true;
+        effects.delay.bpm = 120.0;
+
+        // A quarter note at 120 BPM is half a second, the free time is ignored
+        for (note, frames) in [
+            (NoteValue::Quarter, 500),
+            (NoteValue::DottedEighth, 375),
+            (NoteValue::Sixteenth, 125),
+        ] {
+            effects.delay.note = note;
+            let output = run(EffectKind::Delay, &effects, 1, RATE, &impulse(600, 1));
+            assert_eq!(hits(&output), [(frames, 1.0)], "{}", note.name());
+        }
+
+        effects.delay.bpm = 60.0;
+        effects.delay.note = NoteValue::Quarter;
+        assert_eq!(effects.delay.delay_secs(), 1.0);
+        effects.delay.note = NoteValue::Whole;
+        assert_eq!(effects.delay.delay_secs(), MAX_DELAY_SECS);
+    }
+
+    #[test]
+    fn delay_feedback_dies_down() {
+        let mut effects = wet_delay();
+        for _ in 0..30 {
+            effects.delay.change(DelayParam::Feedback, true);
+        }
+        assert_eq!(effects.delay.feedback, 0.95);
+        effects.delay.feedback = 2.0;
+        effects.delay.clamp();
+        assert_eq!(effects.delay.feedback, 0.95);
+
+        effects.delay.time_ms = 10.0;
+        let output = run(EffectKind::Delay, &effects, 1, RATE, &impulse(1000, 1));
+        let repeats = hits(&output);
+        assert_eq!(repeats.len(), 99);
+        // Every repeat is the last one times the feedback
+        for pair in repeats.windows(2) {
+            assert_eq!(pair[1].0 - pair[0].0, 10);
+            assert!((pair[1].1 - pair[0].1 * 0.95).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn ping_pong_bounces_between_the_sides() {
+        let mut effects = wet_delay();
+        effects.delay.time_ms = 100.0;
+        effects.delay.ping_pong = true;
+        // Played on the left only
+        let mut input = vec![0.0; 800];
+        input[0] = 1.0;
+        let output = run(EffectKind::Delay, &effects, 2, RATE, &input);
+
+        // The dry sound goes in as mono on the left, then the sides take turns
+        assert_eq!(hits(&left(&output)), [(100, 0.5), (300, 0.125)]);
+        assert_eq!(hits(&right(&output)), [(200, 0.25)]);
+
+        // Mono has no sides to bounce between
+        let output = run(EffectKind::Delay, &effects, 1, RATE, &impulse(400, 1));
+        assert_eq!(hits(&output), [(100, 1.0), (200, 0.5), (300, 0.25)]);
+    }
+
+    #[test]
+    fn high_cut_darkens_the_repeats() {
+        let mut effects = wet_delay();
+        effects.delay.time_ms = 10.0;
+        effects.delay.feedback = 0.0;
+        let rate = 44100;
+        let first_repeat = |high_cut: u32| {
+            let mut effects = effects.clone();
+            effects.delay.high_cut = high_cut;
+            run(EffectKind::Delay, &effects, 1, rate, &impulse(4410, 1))
+        };
+
+        // Wide open, the click comes through about as it went in
+        let open = first_repeat(20000);
+        assert!(open[441] > 0.9);
+        // At 500 Hz it's smeared out, but nothing is lost at the bottom
+        let dark = first_repeat(500);
+        assert!(dark.iter().fold(0.0f32, |max, s| max.max(*s)) < 0.1);
+        let total: f32 = dark.iter().sum();
+        assert!((total - 1.0).abs() < 0.01, "{}", total);
+    }
+
+    // Mono ramp, so a delay line's output tells how far back it reads
+    fn ramp(frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| i as f32).collect()
+    }
+
+    fn with_modulation(kind: EffectKind, rate: f32, depth: f32) -> EffectManager {
+        let mut effects = EffectManager::new();
+        let params = effects.modulation.get_mut(kind).unwrap();
+        params.rate = rate;
+        params.depth = depth;
+        params.mix = 1.0;
+        effects
+    }
+
+    #[test]
+    fn lfo_runs_at_its_rate() {
+        let mut lfo = Lfo::new(RATE);
+        lfo.set_rate(2.0);
+        let values: Vec<f32> = (0..1000)
+            .map(|_| {
+                let value = lfo.value(0.0);
+                lfo.advance();
+                value
+            })
+            .collect();
+
+        // Two cycles a second, peaks a quarter cycle in
+        assert_eq!(values[0], 0.0);
+        assert!((values[125] - 1.0).abs() < 1e-4);
+        assert!((values[375] + 1.0).abs() < 1e-4);
+        assert!(values[500].abs() < 1e-3);
+        assert!((values[625] - 1.0).abs() < 1e-4);
+        // An offset shifts the phase
+        assert!((Lfo::new(RATE).value(0.25) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn chorus_sweeps_by_the_depth() {
+        // Shortest and longest delay the LFO reaches in a second, in frames,
+        // and where the longest is
+        let sweep = |depth: f32| {
+            let effects = with_modulation(EffectKind::Chorus, 1.0, depth);
+            let input = ramp(1100);
+            let output = run(EffectKind::Chorus, &effects, 1, RATE, &input);
+            let delays: Vec<f32> = (100..1100).map(|i| input[i] - output[i]).collect();
+            let shortest = delays.iter().fold(f32::MAX, |min, d| min.min(*d));
+            let longest = delays.iter().fold(0.0f32, |max, d| max.max(*d));
+            let peak = delays.iter().position(|d| *d == longest).unwrap() + 100;
+            (shortest, longest, peak)
+        };
+
+        let (shortest, longest, _) = sweep(0.0);
+        assert!((shortest - 7.0).abs() < 1e-3 && (longest - 7.0).abs() < 1e-3);
+        // Half depth covers half of the 15ms sweep, longest where the LFO
+        // peaks, a quarter into every cycle
+        let (shortest, longest, peak) = sweep(0.5);
+        assert!((shortest - 7.0).abs() < 0.01, "{}", shortest);
+        assert!((longest - 14.5).abs() < 0.01, "{}", longest);
+        assert_eq!(peak, 250);
+    }
+
+    #[test]
+    fn flanger_feeds_back() {
+        let effects = with_modulation(EffectKind::Flanger, 1.0, 0.0);
+        let output = run(EffectKind::Flanger, &effects, 1, RATE, &impulse(5, 1));
+        // 1ms apart at depth 0, each repeat 0.6 of the last
+        assert_eq!(output[0], 0.0);
+        for (i, expected) in [1.0, 0.6, 0.36, 0.216].into_iter().enumerate() {
+            assert!((output[i + 1] - expected).abs() < 1e-5, "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn phaser_sweeps_with_the_depth() {
+        // Loudest and quietest stretch of a steady tone, after it settles
+        let spread = |depth: f32| {
+            let mut effects = with_modulation(EffectKind::Phaser, 1.0, depth);
+            effects.modulation.phaser.mix = 0.5;
+            let input: Vec<f32> = (0..44100 * 2)
+                .map(|i| (2.0 * PI * 1000.0 * i as f32 / 44100.0).sin())
+                .collect();
+            let output = run(EffectKind::Phaser, &effects, 1, 44100, &input);
+            let peaks: Vec<f32> = output[44100..]
+                .chunks(441)
+                .map(|chunk| chunk.iter().fold(0.0f32, |max, s| max.max(s.abs())))
+                .collect();
+            let loudest = peaks.iter().fold(0.0f32, |max, p| max.max(*p));
+            let quietest = peaks.iter().fold(f32::MAX, |min, p| min.min(*p));
+            loudest / quietest
+        };
+
+        assert!(spread(0.0) < 1.01, "{}", spread(0.0));
+        assert!(spread(1.0) > 1.5, "{}", spread(1.0));
+    }
+
+    #[test]
+    fn tremolo_stays_in_its_gain_range() {
+        for depth in [0.0, 0.5, 1.0] {
+            let effects = with_modulation(EffectKind::Tremolo, 1.0, depth);
+            let output = run(EffectKind::Tremolo, &effects, 1, RATE, &vec![1.0; 1000]);
+
+            let quietest = output.iter().fold(f32::MAX, |min, s| min.min(*s));
+            let loudest = output.iter().fold(0.0f32, |max, s| max.max(*s));
+            assert!((quietest - (1.0 - depth)).abs() < 1e-4, "{}", quietest);
+            assert!((loudest - 1.0).abs() < 1e-4, "{}", loudest);
+            // Quietest where the LFO peaks
+            if depth > 0.0 {
+                assert_eq!(output.iter().position(|s| *s == quietest), Some(250));
+            }
+        }
+    }
+
+    #[test]
+    fn auto_pan_keeps_the_power() {
+        let effects = with_modulation(EffectKind::AutoPan, 1.0, 1.0);
+        let output = run(EffectKind::AutoPan, &effects, 2, RATE, &vec![1.0; 2000]);
+
+        for frame in output.chunks(2) {
+            let power = frame[0] * frame[0] + frame[1] * frame[1];
+            assert!((power - 2.0).abs() < 1e-4, "{:?}", frame);
+        }
+        // Unity in the middle, all the way over at the LFO's peaks
+        assert!(output[..2].iter().all(|s| (s - 1.0).abs() < 1e-6));
+        assert!(output[250 * 2].abs() < 1e-4);
+        assert!(output[750 * 2 + 1].abs() < 1e-4);
+    }
+
+    // Amplitude of one frequency in a stretch of audio
+    fn level_at(samples: &[f32], frequency: f32, sample_rate: u32) -> f32 {
+        let (re, im) = samples
+            .iter()
+            .enumerate()
+            .fold((0.0f64, 0.0f64), |(re, im), (i, s)| {
+                let phase =
+                    2.0 * std::f64::consts::PI * frequency as f64 * i as f64 / sample_rate as f64;
+                (re + *s as f64 * phase.cos(), im + *s as f64 * phase.sin())
+            });
+        (2.0 * (re * re + im * im).sqrt() / samples.len() as f64) as f32
+    }
+
+    fn sine_wave(frequency: f32, level: f32, frames: usize, sample_rate: u32) -> Vec<f32> {
+        (0..frames)
+            .map(|i| level * (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn saturation_is_unity_at_low_drive() {
+        let mut effects = EffectManager::new();
+        effects.distortion.drive = 0.0;
+        effects.distortion.tone = 20000;
+        for mode in [
+            SaturationMode::SoftClip,
+            SaturationMode::Tube,
+            SaturationMode::HardClip,
+        ] {
+            effects.distortion.mode = mode;
+            let input = sine_wave(1000.0, 0.01, 44100, 44100);
+            let output = run(EffectKind::Saturation, &effects, 1, 44100, &input);
+
+            // Past the filters settling in
+            let level = level_at(&output[4410..], 1000.0, 44100);
+            assert!((level - 0.01).abs() < 0.0002, "{}: {}", mode.name(), level);
+        }
+    }
+
+    #[test]
+    fn oversampling_keeps_aliasing_down() {
+        let mut effects = EffectManager::new();
+        effects.distortion.mode = SaturationMode::HardClip;
+        effects.distortion.drive = 24.0;
+        effects.distortion.tone = 20000;
+        let input = sine_wave(15000.0, 0.5, 44100 + 4410, 44100);
+        let output = run(EffectKind::Saturation, &effects, 1, 44100, &input);
+
+        // The 3rd harmonic, 45 kHz, folds back down to 900 Hz without it
+        let drive = 10.0f32.powf(24.0 / 20.0);
+        let naive: Vec<f32> = input[4410..]
+            .iter()
+            .map(|s| SaturationMode::HardClip.shape(s * drive))
+            .collect();
+        let naive_alias = level_at(&naive, 900.0, 44100) / level_at(&naive, 15000.0, 44100);
+        let output = &output[4410..];
+        let alias = level_at(output, 900.0, 44100) / level_at(output, 15000.0, 44100);
+        assert!(naive_alias > 0.3, "{}", naive_alias);
+        // At least 20 dB less of it, 30 dB under the tone itself
+        assert!(alias < naive_alias / 10.0, "{}", alias);
+        assert!(alias < 0.03, "{}", alias);
+    }
+
+    // One frame of a known L/R pair through the stereo effect
+    fn stereo(set: impl Fn(&mut EffectManager)) -> [f32; 2] {
+        let mut effects = EffectManager::new();
+        set(&mut effects);
+        let output = run(EffectKind::Stereo, &effects, 2, RATE, &[0.8, 0.2]);
+        [output[0], output[1]]
+    }
+
+    fn assert_frame(frame: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            frame
+                .iter()
+                .zip(expected)
+                .all(|(a, b)| (a - b).abs() < 1e-6),
+            "{:?} != {:?}",
+            frame,
+            expected
+        );
+    }
+
+    #[test]
+    fn stereo_is_untouched_by_default() {
+        assert_frame(stereo(|_| {}), [0.8, 0.2]);
+    }
+
+    #[test]
+    fn balance_turns_down_the_other_side() {
+        assert_frame(stereo(|effects| effects.balance = 0.5), [0.4, 0.2]);
+        assert_frame(stereo(|effects| effects.balance = -1.0), [0.8, 0.0]);
+    }
+
+    #[test]
+    fn width_scales_the_side() {
+        // Mid 0.5, side 0.3
+        assert_frame(stereo(|effects| effects.stereo_width = 0.0), [0.5, 0.5]);
+        assert_frame(stereo(|effects| effects.stereo_width = 0.5), [0.65, 0.35]);
+        assert_frame(stereo(|effects| effects.stereo_width = 2.0), [1.1, -0.1]);
+    }
+
+    #[test]
+    fn mono_sums_both_sides() {
+        assert_frame(stereo(|effects| effects.mono = true), [0.5, 0.5]);
+        // Whatever the width
+        assert_frame(
+            stereo(|effects| {
+                effects.mono = true;
+                effects.stereo_width = 2.0;
+            }),
+            [0.5, 0.5],
+        );
+    }
+
+    #[test]
+    fn swap_trades_the_sides() {
+        assert_frame(stereo(|effects| effects.swap_channels = true), [0.2, 0.8]);
+        // Before the balance, which still goes by the output side
+        assert_frame(
+            stereo(|effects| {
+                effects.swap_channels = true;
+                effects.balance = 0.5;
+            }),
+            [0.1, 0.8],
+        );
     }
 }
@@ -0,0 +1,241 @@
+// Attack/decay/sustain/release envelopes, run once per voice
+
+use crate::audio_player::effects::limit;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdsrParam {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+pub const ADSR_PARAMS: [AdsrParam; 4] = [
+    AdsrParam::Attack,
+    AdsrParam::Decay,
+    AdsrParam::Sustain,
+    AdsrParam::Release,
+];
+
+impl AdsrParam {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdsrParam::Attack => "Attack",
+            AdsrParam::Decay => "Decay",
+            AdsrParam::Sustain => "Sustain",
+            AdsrParam::Release => "Release",
+        }
+    }
+}
+
+// Times are in milliseconds, sustain is a level (0.0 - 1.0)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AdsrSettings {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl AdsrSettings {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Self {
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+
+    pub fn describe(&self, param: AdsrParam) -> String {
+        match param {
+            AdsrParam::Attack => format!("{:.0} ms", self.attack),
+            AdsrParam::Decay => format!("{:.0} ms", self.decay),
+            AdsrParam::Sustain => format!("{:.0}%", self.sustain * 100.0),
+            AdsrParam::Release => format!("{:.0} ms", self.release),
+        }
+    }
+
+    pub fn change(&mut self, param: AdsrParam, increase: bool) {
+        // Times move in ratios, with a way back down to zero
+        let time = |value: f32| {
+            if increase {
+                (value * 1.25).clamp(1.0, 10000.0)
+            } else if value <= 1.0 {
+                0.0
+            } else {
+                value / 1.25
+            }
+        };
+
+        match param {
+            AdsrParam::Attack => self.attack = time(self.attack),
+            AdsrParam::Decay => self.decay = time(self.decay),
+            AdsrParam::Sustain => {
+                self.sustain = if increase {
+                    (self.sustain + 0.05).min(1.0)
+                } else {
+                    (self.sustain - 0.05).max(0.0)
+                };
+            }
+            AdsrParam::Release => self.release = time(self.release),
+        }
+    }
+
+    pub fn clamp(&mut self) {
+        self.attack = limit(self.attack, 0.0, 10000.0);
+        self.decay = limit(self.decay, 0.0, 10000.0);
+        self.sustain = limit(self.sustain, 0.0, 1.0);
+        self.release = limit(self.release, 0.0, 10000.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Done,
+}
+
+// A running envelope, starting in the attack stage
+pub struct Envelope {
+    stage: Stage,
+    level: f32,
+    // Set once the release starts, so it takes `release` ms from any level
+    release_step: Option<f32>,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Self {
+            stage: Stage::Attack,
+            level: 0.0,
+            release_step: None,
+        }
+    }
+
+    pub fn release(&mut self) {
+        if self.stage != Stage::Done {
+            self.stage = Stage::Release;
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.stage == Stage::Done
+    }
+
+    // Advance by one frame and return the level
+    pub fn next(&mut self, settings: &AdsrSettings, sample_rate: u32) -> f32 {
+        let frames = |ms: f32| (ms / 1000.0 * sample_rate as f32).max(1.0);
+
+        match self.stage {
+            Stage::Attack => {
+                self.level += 1.0 / frames(settings.attack);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= (1.0 - settings.sustain) / frames(settings.decay);
+                if self.level <= settings.sustain {
+                    self.level = settings.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {
+                self.level = settings.sustain;
+            }
+            Stage::Release => {
+                let step = *self
+                    .release_step
+                    .get_or_insert(self.level / frames(settings.release));
+                self.level -= step;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Done;
+                }
+            }
+            Stage::Done => {}
+        }
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // At 1 kHz every millisecond is one frame
+    const RATE: u32 = 1000;
+
+    fn run(envelope: &mut Envelope, settings: &AdsrSettings, frames: usize) -> Vec<f32> {
+        (0..frames).map(|_| envelope.next(settings, RATE)).collect()
+    }
+
+    #[test]
+    fn runs_attack_decay_and_sustain() {
+        let settings = AdsrSettings::new(4.0, 4.0, 0.5, 10.0);
+        let mut envelope = Envelope::new();
+        let levels = run(&mut envelope, &settings, 12);
+
+        assert_eq!(levels[..4], [0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(levels[4..8], [0.875, 0.75, 0.625, 0.5]);
+        assert!(levels[8..].iter().all(|&level| level == 0.5));
+        assert!(!envelope.is_done());
+    }
+
+    #[test]
+    fn zero_times_jump_straight_there() {
+        let settings = AdsrSettings::new(0.0, 0.0, 0.3, 0.0);
+        let mut envelope = Envelope::new();
+        assert_eq!(run(&mut envelope, &settings, 3), [1.0, 0.3, 0.3]);
+    }
+
+    #[test]
+    fn releases_from_any_level() {
+        let settings = AdsrSettings::new(8.0, 0.0, 1.0, 4.0);
+        let mut envelope = Envelope::new();
+        run(&mut envelope, &settings, 2);
+
+        // Let go halfway up the attack, still takes the full release
+        envelope.release();
+        assert_eq!(
+            run(&mut envelope, &settings, 4),
+            [0.1875, 0.125, 0.0625, 0.0]
+        );
+        assert!(envelope.is_done());
+        assert_eq!(envelope.next(&settings, RATE), 0.0);
+
+        // Nothing to release once it's done
+        envelope.release();
+        assert!(envelope.is_done());
+    }
+
+    #[test]
+    fn changes_stay_in_range() {
+        let mut settings = AdsrSettings::new(1.0, 5000.0, 0.9, 100.0);
+
+        // Down from the shortest time is off, and back up starts at 1 ms
+        settings.change(AdsrParam::Attack, false);
+        assert_eq!(settings.attack, 0.0);
+        settings.change(AdsrParam::Attack, true);
+        assert_eq!(settings.attack, 1.0);
+
+        for _ in 0..10 {
+            settings.change(AdsrParam::Decay, true);
+            settings.change(AdsrParam::Sustain, true);
+        }
+        assert_eq!(settings.decay, 10000.0);
+        assert_eq!(settings.sustain, 1.0);
+
+        for _ in 0..30 {
+            settings.change(AdsrParam::Sustain, false);
+        }
+        assert_eq!(settings.sustain, 0.0);
+        assert_eq!(settings.describe(AdsrParam::Release), "100 ms");
+    }
+}
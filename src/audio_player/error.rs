@@ -0,0 +1,60 @@
+use rodio::decoder::DecoderError;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+// Why a sound couldn't be loaded or played
+#[derive(Debug)]
+pub enum AudioError {
+    // There's an output device, but we couldn't play through it
+    Device(String),
+    Open(PathBuf, io::Error),
+    Decode(PathBuf, String),
+    // Not a format any of the decoders know
+    Unsupported(PathBuf),
+    // The name can't be written down as text, e.g. in the loops file
+    PathEncoding(PathBuf),
+}
+
+impl AudioError {
+    pub fn decode(path: &Path, error: DecoderError) -> Self {
+        match error {
+            DecoderError::UnrecognizedFormat => AudioError::Unsupported(path.to_path_buf()),
+            error => AudioError::Decode(path.to_path_buf(), error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::Device(reason) => write!(f, "Audio output failed: {}", reason),
+            AudioError::Open(path, error) => {
+                write!(f, "Could not open {}: {}", path.display(), error)
+            }
+            AudioError::Decode(path, reason) => {
+                write!(f, "Could not decode {}: {}", path.display(), reason)
+            }
+            AudioError::Unsupported(path) => {
+                write!(f, "Unsupported audio format: {}", path.display())
+            }
+            AudioError::PathEncoding(path) => {
+                write!(f, "File name is not valid UTF-8: {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+// For the places that deal in io::Result, like the recorder's fake input
+impl From<AudioError> for io::Error {
+    fn from(error: AudioError) -> Self {
+        let kind = match &error {
+            AudioError::Open(_, error) => error.kind(),
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, error)
+    }
+}
@@ -0,0 +1,366 @@
+use crate::audio_player::effects::{EffectManager, MODULATION_EFFECTS};
+use crate::audio_player::envelope::AdsrSettings;
+use std::time::{Duration, Instant};
+
+// Presses of the same parameter closer together than this are one change
+const COALESCE_WINDOW: Duration = Duration::from_millis(800);
+const MAX_ENTRIES: usize = 200;
+
+struct Entry {
+    // The settings to go back to
+    effects: EffectManager,
+    label: String,
+}
+
+// Undo/redo of effect changes. The settings are small, so every change is a
+// full snapshot of what they were before
+pub struct History {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+    // The settings as of the last recorded change
+    last: EffectManager,
+    last_change: Option<Instant>,
+    // What the last change, undo or redo did
+    pub status: Option<String>,
+}
+
+impl History {
+    pub fn new(effects: &EffectManager) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last: effects.clone(),
+            last_change: None,
+            status: None,
+        }
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_count(&self) -> usize {
+        self.redo.len()
+    }
+
+    // Take note of any change since the last call. Repeated presses on the
+    // same parameter are merged, so one undo takes them all back
+    pub fn record(&mut self, effects: &EffectManager) {
+        if *effects == self.last {
+            return;
+        }
+
+        let (label, detail) = describe_change(&self.last, effects);
+        let coalesce = self.redo.is_empty()
+            && self.undo.last().is_some_and(|entry| entry.label == label)
+            && self
+                .last_change
+                .is_some_and(|time| time.elapsed() < COALESCE_WINDOW);
+
+        if !coalesce {
+            self.undo.push(Entry {
+                effects: self.last.clone(),
+                label,
+            });
+            if self.undo.len() > MAX_ENTRIES {
+                self.undo.remove(0);
+            }
+        }
+        self.redo.clear();
+
+        self.last = effects.clone();
+        self.last_change = Some(Instant::now());
+        self.status = Some(detail);
+    }
+
+    pub fn undo(&mut self, effects: &mut EffectManager) {
+        match self.undo.pop() {
+            Some(entry) => {
+                self.status = Some(format!("Undo: {}", entry.label));
+                self.redo.push(Entry {
+                    effects: std::mem::replace(effects, entry.effects),
+                    label: entry.label,
+                });
+                self.last = effects.clone();
+                self.last_change = None;
+            }
+            None => self.status = Some("Already at oldest change".to_string()),
+        }
+    }
+
+    pub fn redo(&mut self, effects: &mut EffectManager) {
+        match self.redo.pop() {
+            Some(entry) => {
+                self.status = Some(format!("Redo: {}", entry.label));
+                self.undo.push(Entry {
+                    effects: std::mem::replace(effects, entry.effects),
+                    label: entry.label,
+                });
+                self.last = effects.clone();
+                self.last_change = None;
+            }
+            None => self.status = Some("Already at newest change".to_string()),
+        }
+    }
+
+    // Start over from these settings, for when they're swapped out as a whole
+    pub fn reset(&mut self, effects: &EffectManager) {
+        self.undo.clear();
+        self.redo.clear();
+        self.last = effects.clone();
+        self.last_change = None;
+    }
+}
+
+// What changed between two settings, as a name to merge changes by and a
+// line for the status bar. Each parameter gets its own name, so changing the
+// delay time and then its feedback stays two steps
+fn describe_change(before: &EffectManager, after: &EffectManager) -> (String, String) {
+    let mut changed: Vec<String> = Vec::new();
+    let mut check = |differs: bool, name: &str| {
+        if differs {
+            changed.push(name.to_string());
+        }
+    };
+
+    check(before.volume != after.volume, "Volume");
+    check(before.playback_speed != after.playback_speed, "Speed");
+    check(
+        before.lowpass_cutoff != after.lowpass_cutoff,
+        "Low-pass cutoff",
+    );
+    check(before.reverb_delay != after.reverb_delay, "Reverb");
+    check(before.balance != after.balance, "Balance");
+    check(before.stereo_width != after.stereo_width, "Stereo width");
+    check(before.mono != after.mono, "Mono");
+    check(before.swap_channels != after.swap_channels, "Swap L/R");
+
+    let (was, now) = (&before.dynamics, &after.dynamics);
+    check(was.limiter != now.limiter, "Limiter");
+    check(was.ceiling != now.ceiling, "Limiter ceiling");
+    check(was.compressor != now.compressor, "Compressor");
+    check(was.threshold != now.threshold, "Compressor threshold");
+    check(was.ratio != now.ratio, "Compressor ratio");
+    check(was.attack != now.attack, "Compressor attack");
+    check(was.release != now.release, "Compressor release");
+    check(was.makeup != now.makeup, "Compressor makeup");
+    check(was.gate != now.gate, "Noise gate");
+    check(was.gate_threshold != now.gate_threshold, "Gate threshold");
+
+    let (was, now) = (&before.delay, &after.delay);
+    check(was.time_ms != now.time_ms, "Delay time");
+    check(was.sync != now.sync, "Delay sync");
+    check(was.bpm != now.bpm, "Delay BPM");
+    check(was.note != now.note, "Delay note");
+    check(was.feedback != now.feedback, "Delay feedback");
+    check(was.mix != now.mix, "Delay wet/dry");
+    check(was.ping_pong != now.ping_pong, "Delay ping-pong");
+    check(was.high_cut != now.high_cut, "Delay high-cut");
+
+    for kind in MODULATION_EFFECTS {
+        if let (Some(was), Some(now)) = (before.modulation.get(kind), after.modulation.get(kind)) {
+            check(was.rate != now.rate, &format!("{} rate", kind.name()));
+            check(was.depth != now.depth, &format!("{} depth", kind.name()));
+            check(was.mix != now.mix, &format!("{} mix", kind.name()));
+        }
+    }
+
+    let (was, now) = (&before.distortion, &after.distortion);
+    check(was.mode != now.mode, "Saturation curve");
+    check(was.drive != now.drive, "Drive");
+    check(was.tone != now.tone, "Saturation tone");
+    check(was.bits != now.bits, "Bit depth");
+    check(was.downsample != now.downsample, "Downsample");
+
+    let (was, now) = (&before.filter, &after.filter);
+    check(was.filter_type != now.filter_type, "Filter type");
+    check(was.steep != now.steep, "Filter slope");
+    check(was.cutoff != now.cutoff, "Filter cutoff");
+    check(was.resonance != now.resonance, "Filter resonance");
+    check(was.env_amount != now.env_amount, "Filter env amount");
+    check(was.lfo_rate != now.lfo_rate, "Filter LFO rate");
+    check(was.lfo_depth != now.lfo_depth, "Filter LFO depth");
+    check_envelope(&mut check, &was.envelope, &now.envelope, "Filter env");
+    check_envelope(&mut check, &before.amp_envelope, &after.amp_envelope, "Amp");
+
+    check(before.chain != after.chain, "Effect chain");
+
+    let label = match changed.as_slice() {
+        [label] => label.clone(),
+        _ => "Settings".to_string(),
+    };
+
+    // The simple values are worth showing
+    let detail = match label.as_str() {
+        "Volume" => format!(
+            "Volume {:.0}% -> {:.0}%",
+            before.volume * 100.0,
+            after.volume * 100.0
+        ),
+        "Speed" => format!(
+            "Speed {:.2}x -> {:.2}x",
+            before.playback_speed, after.playback_speed
+        ),
+        "Low-pass cutoff" => format!(
+            "Low-pass cutoff {} Hz -> {} Hz",
+            before.lowpass_cutoff, after.lowpass_cutoff
+        ),
+        "Balance" => format!("Balance {:+.1} -> {:+.1}", before.balance, after.balance),
+        "Stereo width" => format!(
+            "Stereo width {:.0}% -> {:.0}%",
+            before.stereo_width * 100.0,
+            after.stereo_width * 100.0
+        ),
+        "Settings" => format!("Changed {}", changed.join(", ")),
+        _ => format!("Changed {}", label),
+    };
+
+    (label, detail)
+}
+
+fn check_envelope(
+    check: &mut impl FnMut(bool, &str),
+    before: &AdsrSettings,
+    after: &AdsrSettings,
+    prefix: &str,
+) {
+    check(before.attack != after.attack, &format!("{} attack", prefix));
+    check(before.decay != after.decay, &format!("{} decay", prefix));
+    check(
+        before.sustain != after.sustain,
+        &format!("{} sustain", prefix),
+    );
+    check(
+        before.release != after.release,
+        &format!("{} release", prefix),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_volume(volume: f32) -> EffectManager {
+        let mut effects = EffectManager::new();
+        effects.volume = volume;
+        effects
+    }
+
+    #[test]
+    fn merges_repeated_presses() {
+        let mut effects = with_volume(1.0);
+        let mut history = History::new(&effects);
+        for volume in [0.9, 0.8, 0.7] {
+            effects.volume = volume;
+            history.record(&effects);
+        }
+        assert_eq!(history.undo_count(), 1);
+        assert_eq!(history.status.as_deref(), Some("Volume 80% -> 70%"));
+
+        history.undo(&mut effects);
+        assert_eq!(effects, with_volume(1.0));
+        assert_eq!(history.status.as_deref(), Some("Undo: Volume"));
+    }
+
+    #[test]
+    fn keeps_different_parameters_apart() {
+        let mut effects = with_volume(1.0);
+        let mut history = History::new(&effects);
+        effects.volume = 0.5;
+        history.record(&effects);
+        effects.mono = true;
+        history.record(&effects);
+        effects.volume = 0.4;
+        history.record(&effects);
+        assert_eq!(history.undo_count(), 3);
+
+        // Nothing changed, nothing recorded
+        history.record(&effects);
+        assert_eq!(history.undo_count(), 3);
+    }
+
+    #[test]
+    fn keeps_parameters_of_one_effect_apart() {
+        let mut effects = EffectManager::new();
+        let mut history = History::new(&effects);
+        effects.delay.time_ms += 25.0;
+        history.record(&effects);
+        effects.delay.feedback += 0.05;
+        history.record(&effects);
+        assert_eq!(history.undo_count(), 2);
+
+        history.undo(&mut effects);
+        assert_eq!(history.status.as_deref(), Some("Undo: Delay feedback"));
+        assert_eq!(effects.delay.feedback, EffectManager::new().delay.feedback);
+        assert_ne!(effects.delay.time_ms, EffectManager::new().delay.time_ms);
+
+        // The same goes for the LFOs of different effects
+        effects.modulation.chorus.rate *= 1.2;
+        history.record(&effects);
+        effects.modulation.chorus.depth += 0.05;
+        history.record(&effects);
+        effects.modulation.flanger.depth += 0.05;
+        history.record(&effects);
+        assert_eq!(history.undo_count(), 4);
+    }
+
+    #[test]
+    fn stops_merging_after_the_window() {
+        let mut effects = with_volume(1.0);
+        let mut history = History::new(&effects);
+        effects.volume = 0.9;
+        history.record(&effects);
+        history.last_change = Some(Instant::now() - COALESCE_WINDOW);
+        effects.volume = 0.8;
+        history.record(&effects);
+        assert_eq!(history.undo_count(), 2);
+    }
+
+    #[test]
+    fn undoes_and_redoes() {
+        let mut effects = with_volume(1.0);
+        let mut history = History::new(&effects);
+        effects.volume = 0.5;
+        history.record(&effects);
+
+        history.undo(&mut effects);
+        history.undo(&mut effects);
+        assert_eq!(history.status.as_deref(), Some("Already at oldest change"));
+        assert_eq!(history.redo_count(), 1);
+
+        history.redo(&mut effects);
+        assert_eq!(effects.volume, 0.5);
+        history.redo(&mut effects);
+        assert_eq!(history.status.as_deref(), Some("Already at newest change"));
+
+        // Changing after an undo throws the redo away
+        history.undo(&mut effects);
+        effects.volume = 0.7;
+        history.record(&effects);
+        assert_eq!(history.redo_count(), 0);
+        effects.volume = 0.6;
+        history.record(&effects);
+        assert_eq!(history.undo_count(), 1);
+    }
+
+    #[test]
+    fn caps_the_entries() {
+        let mut effects = EffectManager::new();
+        let mut history = History::new(&effects);
+        for _ in 0..MAX_ENTRIES + 10 {
+            effects.mono = !effects.mono;
+            history.record(&effects);
+            history.last_change = None;
+        }
+        assert_eq!(history.undo_count(), MAX_ENTRIES);
+    }
+
+    #[test]
+    fn labels_combined_changes() {
+        let mut after = with_volume(0.5);
+        after.mono = true;
+        let (label, detail) = describe_change(&with_volume(1.0), &after);
+        assert_eq!(label, "Settings");
+        assert_eq!(detail, "Changed Volume, Mono");
+    }
+}
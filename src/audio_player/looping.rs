@@ -0,0 +1,382 @@
+use crate::audio_player::error::AudioError;
+use crate::audio_player::sample::DecodedAudio;
+use crate::utils;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const LOOPS_FILE: &str = "loops.tsv";
+
+// Loop points of a file, all in frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopRegion {
+    pub start: usize,
+    pub end: usize,
+    pub crossfade: usize,
+}
+
+impl LoopRegion {
+    pub fn whole(frames: usize) -> Self {
+        Self {
+            start: 0,
+            end: frames,
+            crossfade: 0,
+        }
+    }
+
+    // Keep the region inside the file and the crossfade inside the region
+    // and the lead-in before it
+    pub fn clamped(self, frames: usize) -> Self {
+        let end = self.end.clamp(1.min(frames), frames);
+        let start = self.start.min(end.saturating_sub(1));
+        let crossfade = self.crossfade.min(start).min(end - start);
+
+        Self {
+            start,
+            end,
+            crossfade,
+        }
+    }
+
+    // Put a marker at a frame, keeping the region valid
+    pub fn with_marker(self, marker: LoopMarker, frame: usize, frames: usize) -> Self {
+        let mut region = self;
+        match marker {
+            LoopMarker::Start => region.start = frame,
+            LoopMarker::End => region.end = frame.max(region.start + 1),
+            LoopMarker::Crossfade => region.crossfade = frame,
+        }
+        region.clamped(frames)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMarker {
+    Start,
+    End,
+    Crossfade,
+}
+
+// Step sizes for nudging markers, from single samples up to whole seconds
+const STEPS: [(f32, &str); 5] = [
+    (0.0, "1 sample"),
+    (0.001, "1ms"),
+    (0.01, "10ms"),
+    (0.1, "100ms"),
+    (1.0, "1s"),
+];
+
+// Editing state of the Loop mode
+pub struct LoopEditor {
+    pub marker: LoopMarker,
+    step_index: usize,
+}
+
+impl LoopEditor {
+    pub fn new() -> Self {
+        Self {
+            marker: LoopMarker::Start,
+            step_index: 2,
+        }
+    }
+
+    pub fn next_marker(&mut self) {
+        self.marker = match self.marker {
+            LoopMarker::Start => LoopMarker::End,
+            LoopMarker::End => LoopMarker::Crossfade,
+            LoopMarker::Crossfade => LoopMarker::Start,
+        };
+    }
+
+    pub fn change_step(&mut self, increase: bool) {
+        if increase {
+            self.step_index = (self.step_index + 1).min(STEPS.len() - 1);
+        } else {
+            self.step_index = self.step_index.saturating_sub(1);
+        }
+    }
+
+    pub fn step_name(&self) -> &'static str {
+        STEPS[self.step_index].1
+    }
+
+    fn step_frames(&self, audio: &DecodedAudio) -> usize {
+        audio.secs_to_frames(STEPS[self.step_index].0).max(1)
+    }
+
+    // Move the selected marker by one step
+    pub fn nudge(&self, region: LoopRegion, audio: &DecodedAudio, increase: bool) -> LoopRegion {
+        let step = self.step_frames(audio);
+        let shift = |value: usize| {
+            if increase {
+                value + step
+            } else {
+                value.saturating_sub(step)
+            }
+        };
+
+        let frame = match self.marker {
+            LoopMarker::Start => region.start,
+            LoopMarker::End => region.end,
+            LoopMarker::Crossfade => region.crossfade,
+        };
+        region.with_marker(self.marker, shift(frame), audio.frames())
+    }
+
+    // Move the selected marker onto the nearest zero crossing
+    pub fn snap(&self, region: LoopRegion, audio: &DecodedAudio) -> LoopRegion {
+        let mut region = region;
+        match self.marker {
+            LoopMarker::Start => region.start = audio.nearest_zero_crossing(region.start),
+            LoopMarker::End => region.end = audio.nearest_zero_crossing(region.end),
+            LoopMarker::Crossfade => {}
+        }
+        region.clamped(audio.frames())
+    }
+}
+
+// Loop regions of every file we've edited, saved to the config directory
+pub struct LoopStore {
+    regions: HashMap<PathBuf, LoopRegion>,
+    path: Option<PathBuf>,
+}
+
+impl LoopStore {
+    pub fn load() -> Self {
+        Self::load_from(utils::config_file(LOOPS_FILE))
+    }
+
+    fn load_from(path: Option<PathBuf>) -> Self {
+        let mut regions = HashMap::new();
+
+        if let Some(path) = &path
+            && let Ok(contents) = fs::read_to_string(path)
+        {
+            for line in contents.lines() {
+                // start, end, crossfade, then the path (which may contain tabs)
+                let mut fields = line.splitn(4, '\t');
+                let parsed = (|| {
+                    let start = fields.next()?.parse().ok()?;
+                    let end = fields.next()?.parse().ok()?;
+                    let crossfade = fields.next()?.parse().ok()?;
+                    let file = decode_path(fields.next()?);
+                    Some((
+                        file,
+                        LoopRegion {
+                            start,
+                            end,
+                            crossfade,
+                        },
+                    ))
+                })();
+
+                if let Some((file, region)) = parsed {
+                    regions.insert(file, region);
+                }
+            }
+        }
+
+        Self { regions, path }
+    }
+
+    pub fn get(&self, file: &Path) -> Option<LoopRegion> {
+        self.regions.get(&Self::key(file)).copied()
+    }
+
+    pub fn set(&mut self, file: &Path, region: LoopRegion) -> io::Result<()> {
+        self.regions.insert(Self::key(file), region);
+        self.save()
+    }
+
+    pub fn remove(&mut self, file: &Path) -> io::Result<()> {
+        self.regions.remove(&Self::key(file));
+        self.save()
+    }
+
+    fn key(file: &Path) -> PathBuf {
+        fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut contents = String::new();
+        let mut unwritable = None;
+        for (file, region) in &self.regions {
+            let Some(file) = encode_path(file) else {
+                // The others are still saved
+                unwritable.get_or_insert(file);
+                continue;
+            };
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                region.start, region.end, region.crossfade, file
+            ));
+        }
+        utils::write_atomically(path, |temp| fs::write(temp, contents))?;
+
+        match unwritable {
+            Some(file) => Err(AudioError::PathEncoding(file.clone()).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+// Paths go in the loops file as they are. One that isn't UTF-8 (or that
+// happens to start with the prefix) goes in as hex bytes after the prefix
+const HEX_PREFIX: &str = "hex:";
+
+fn encode_path(file: &Path) -> Option<String> {
+    match file.to_str() {
+        Some(text) if !text.starts_with(HEX_PREFIX) => Some(text.to_string()),
+        _ => path_to_hex(file).map(|hex| format!("{}{}", HEX_PREFIX, hex)),
+    }
+}
+
+fn decode_path(field: &str) -> PathBuf {
+    field
+        .strip_prefix(HEX_PREFIX)
+        .and_then(path_from_hex)
+        .unwrap_or_else(|| PathBuf::from(field))
+}
+
+#[cfg(unix)]
+fn path_to_hex(file: &Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(
+        file.as_os_str()
+            .as_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    )
+}
+
+#[cfg(unix)]
+fn path_from_hex(hex: &str) -> Option<PathBuf> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(PathBuf::from(OsStr::from_bytes(&bytes)))
+}
+
+// Elsewhere a path that isn't UTF-8 takes unpaired UTF-16 surrogates, which
+// we don't try to write down. Saving says so, and its loop lasts until we
+// quit
+#[cfg(not(unix))]
+fn path_to_hex(_file: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+fn path_from_hex(_hex: &str) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(start: usize, end: usize, crossfade: usize) -> LoopRegion {
+        LoopRegion {
+            start,
+            end,
+            crossfade,
+        }
+    }
+
+    #[test]
+    fn clamps_regions_into_the_file() {
+        assert_eq!(region(0, 500, 0).clamped(100), region(0, 100, 0));
+        assert_eq!(region(80, 50, 0).clamped(100), region(49, 50, 0));
+        // The crossfade needs audio before the start, and fits in the loop
+        assert_eq!(region(10, 90, 30).clamped(100), region(10, 90, 10));
+        assert_eq!(region(60, 70, 30).clamped(100), region(60, 70, 10));
+        assert_eq!(region(5, 5, 5).clamped(0), region(0, 0, 0));
+    }
+
+    #[test]
+    fn places_markers() {
+        let whole = LoopRegion::whole(100);
+        assert_eq!(
+            whole.with_marker(LoopMarker::Start, 40, 100),
+            region(40, 100, 0)
+        );
+        assert_eq!(
+            whole.with_marker(LoopMarker::End, 250, 100),
+            region(0, 100, 0)
+        );
+        // The end can't go before the start
+        let late_start = region(40, 100, 0);
+        assert_eq!(
+            late_start.with_marker(LoopMarker::End, 10, 100),
+            region(40, 41, 0)
+        );
+        assert_eq!(
+            late_start.with_marker(LoopMarker::Crossfade, 20, 100),
+            region(40, 100, 20)
+        );
+    }
+
+    #[test]
+    fn nudges_by_the_step() {
+        let audio = DecodedAudio {
+            samples: vec![0.0; 1000],
+            channels: 1,
+            sample_rate: 1000,
+        };
+        let mut editor = LoopEditor::new();
+        // 10ms to start with, 10 frames at 1kHz
+        assert_eq!(editor.step_name(), "10ms");
+        let moved = editor.nudge(LoopRegion::whole(1000), &audio, true);
+        assert_eq!(moved, region(10, 1000, 0));
+        assert_eq!(editor.nudge(moved, &audio, false), region(0, 1000, 0));
+
+        editor.change_step(false);
+        editor.change_step(false);
+        editor.change_step(false);
+        assert_eq!(editor.step_name(), "1 sample");
+        editor.next_marker();
+        let moved = editor.nudge(LoopRegion::whole(1000), &audio, false);
+        assert_eq!(moved, region(0, 999, 0));
+    }
+
+    #[test]
+    fn saves_and_loads_every_path() {
+        let dir = std::env::temp_dir().join(format!("audirust-loops-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let file = dir.join(LOOPS_FILE);
+
+        let mut files = vec![
+            PathBuf::from("/samples/with\ttab.wav"),
+            PathBuf::from("hex:not really.wav"),
+        ];
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            files.push(PathBuf::from(OsStr::from_bytes(b"/samples/caf\xe9.wav")));
+        }
+
+        let mut store = LoopStore::load_from(Some(file.clone()));
+        for (i, path) in files.iter().enumerate() {
+            store.set(path, region(i, 100 + i, 0)).unwrap();
+        }
+
+        let store = LoopStore::load_from(Some(file.clone()));
+        for (i, path) in files.iter().enumerate() {
+            assert_eq!(store.get(path), Some(region(i, 100 + i, 0)), "{:?}", path);
+        }
+        let contents = fs::read_to_string(&file).unwrap();
+        assert!(contents.contains("\t/samples/with\ttab.wav\n"));
+        assert!(contents.contains(&format!("{}6865783a", HEX_PREFIX)));
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+const MAX_MESSAGES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "INFO",
+            Severity::Warning => "WARN",
+            Severity::Error => "ERROR",
+        }
+    }
+
+    // How long the toast stays up, longer for what matters more
+    fn toast_time(&self) -> Duration {
+        match self {
+            Severity::Info => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(5),
+            Severity::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+pub struct Message {
+    pub severity: Severity,
+    pub text: String,
+    // Time since the log was started
+    pub at: Duration,
+    // Still shown as a toast, until it expires or is dismissed
+    toast: bool,
+}
+
+impl Message {
+    // mm:ss into the session, with hours once there are any
+    pub fn timestamp(&self) -> String {
+        let secs = self.at.as_secs();
+        if secs >= 3600 {
+            format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        } else {
+            format!("{:02}:{:02}", secs / 60, secs % 60)
+        }
+    }
+}
+
+// Everything the player and the app had to tell, oldest first. Recent
+// messages also pop up as toasts
+pub struct MessageLog {
+    started: Instant,
+    entries: VecDeque<Message>,
+}
+
+impl MessageLog {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, severity: Severity, text: &str) {
+        self.entries.push_back(Message {
+            severity,
+            text: text.to_string(),
+            at: self.started.elapsed(),
+            toast: true,
+        });
+        if self.entries.len() > MAX_MESSAGES {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn info(&mut self, text: &str) {
+        self.push(Severity::Info, text);
+    }
+
+    pub fn warn(&mut self, text: &str) {
+        self.push(Severity::Warning, text);
+    }
+
+    pub fn error(&mut self, text: &str) {
+        self.push(Severity::Error, text);
+    }
+
+    pub fn entries(&self) -> &VecDeque<Message> {
+        &self.entries
+    }
+
+    pub fn last(&self) -> Option<&Message> {
+        self.entries.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // The newest messages that are still up as toasts, oldest first
+    pub fn toasts(&self, max: usize) -> Vec<&Message> {
+        let now = self.started.elapsed();
+        let mut toasts: Vec<&Message> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|message| message.toast && now < message.at + message.severity.toast_time())
+            .take(max)
+            .collect();
+        toasts.reverse();
+        toasts
+    }
+
+    // Everything has been seen in the log, so no more toasts
+    pub fn dismiss_toasts(&mut self) {
+        for message in &mut self.entries {
+            message.toast = false;
+        }
+    }
+}
@@ -1,141 +1,432 @@
+pub mod bus;
+pub mod chain;
+pub mod compare;
+pub mod dynamics;
+pub mod editor;
 pub mod effects;
+pub mod envelope;
+pub mod error;
+pub mod history;
+pub mod looping;
+pub mod messages;
+pub mod params;
+pub mod pitch;
+pub mod presets;
+pub mod recorder;
+pub mod sample;
+pub mod tap;
 pub mod visualization;
+pub mod voice;
 
+use crate::config::Config;
+use bus::MasterBus;
+use chain::{ChainSource, SharedEffects};
+use compare::AbCompare;
+use dynamics::{Compressor, DynamicsMeter, GainReduction, Gate, Limiter};
 use effects::EffectManager;
-use rodio::{Decoder, OutputStreamHandle, Sink, Source, dynamic_mixer::mixer};
+use error::AudioError;
+use looping::{LoopRegion, LoopStore};
+use messages::MessageLog;
+use pitch::Tuner;
+use presets::PresetStore;
+use recorder::Recorder;
+use rodio::{OutputStreamHandle, Source, dynamic_mixer::mixer, source::UniformSourceIterator};
+use sample::{BufferSource, DecodedAudio, PlayHead};
 use std::{
-    fs::File,
-    io::{self, BufReader},
+    path::{Path, PathBuf},
     sync::Arc,
-    time::{Duration, Instant},
+    time::Instant,
 };
+use tap::{SharedTap, TapBuffer};
 use visualization::WaveformVisualizer;
+use voice::Voice;
+
+// How finely the overview of a file is kept, more than any terminal is wide
+const OVERVIEW_POINTS: usize = 1024;
 
 pub struct AudioPlayer {
-    pub stream_handle: Option<OutputStreamHandle>,
-    pub active_sinks: Vec<(Arc<Sink>, bool)>,
-    pub messages: Vec<String>,
+    // Where all voices end up, None without an audio device
+    bus: Option<MasterBus>,
+    pub voices: Vec<Voice>,
+    next_voice_id: u64,
+    pub messages: MessageLog,
     pub last_played: Option<Instant>,
     pub visual_only_mode: bool,
 
     // Effect management
     pub effect_manager: EffectManager,
+    // What playing sounds currently use, updated whenever effect_manager changes
+    shared_effects: Arc<SharedEffects>,
+    published_effects: EffectManager,
+    // A/B comparison against a second set of effect settings
+    pub ab: AbCompare,
 
     // Visualization
     pub visualizer: WaveformVisualizer,
+
+    // Output of the master bus, analysed by the tuner
+    pub tap: SharedTap,
+    pub gain_reduction: GainReduction,
+    pub tuner: Tuner,
+
+    // Recording from an input device or our own output
+    pub recorder: Recorder,
+
+    // Loop points per file, and the last file we decoded
+    pub loop_store: LoopStore,
+    decoded: Option<(PathBuf, Arc<DecodedAudio>)>,
+    // Peaks of the decoded file, for the overview
+    overview: Vec<f32>,
+    // Where the file started last is playing, to show and seek
+    playhead: Option<(PathBuf, Arc<PlayHead>)>,
+
+    // Named effect settings
+    pub preset_store: PresetStore,
 }
 
 impl AudioPlayer {
-    pub fn new(stream_handle: Option<OutputStreamHandle>) -> Self {
+    pub fn new(stream_handle: Option<OutputStreamHandle>, config: &Config) -> Self {
         let visual_only_mode = stream_handle.is_none();
+        let effect_manager = config.effects();
+        let shared_effects = SharedEffects::new(&effect_manager);
+        let tap = TapBuffer::new_shared(1.0);
+        let bus = stream_handle.as_ref().map(|stream_handle| {
+            MasterBus::new(stream_handle, Arc::clone(&shared_effects), Arc::clone(&tap))
+        });
+        let mut messages = MessageLog::new();
+        let bus = match bus {
+            Some(Err(e)) => {
+                messages.error(&format!("Could not open the audio output: {}", e));
+                None
+            }
+            bus => bus.and_then(Result::ok),
+        };
 
-        AudioPlayer {
-            stream_handle,
-            active_sinks: Vec::new(),
-            messages: Vec::new(),
+        let mut player = AudioPlayer {
+            bus,
+            voices: Vec::new(),
+            next_voice_id: 1,
+            messages,
             last_played: None,
             visual_only_mode,
-            effect_manager: EffectManager::new(),
-            visualizer: WaveformVisualizer::new(100), // 100 points for waveform
-        }
-    }
+            shared_effects,
+            published_effects: effect_manager.clone(),
+            ab: AbCompare::new(),
+            effect_manager,
+            visualizer: WaveformVisualizer::new(config.waveform_points),
+            tap,
+            gain_reduction: GainReduction::new(),
+            tuner: Tuner::new(),
+            recorder: Recorder::new(),
+            loop_store: LoopStore::load(),
+            decoded: None,
+            overview: Vec::new(),
+            playhead: None,
+            preset_store: PresetStore::load(),
+        };
 
-    pub fn add_message(&mut self, message: &str) {
-        self.messages.push(message.to_string());
-        if self.messages.len() > 5 {
-            self.messages.remove(0);
+        let store = &player.preset_store;
+        let mut problems: Vec<String> = store
+            .skipped()
+            .iter()
+            .map(|name| format!("Could not read preset {}, it's kept as it is", name))
+            .collect();
+        if let Some(e) = &store.read_error {
+            problems.push(format!(
+                "Could not read the presets file, presets won't be saved: {}",
+                e
+            ));
         }
+        for problem in problems {
+            player.messages.error(&problem);
+        }
+        player
     }
 
-    pub fn play_sound(&mut self, file_path: &str, is_looping: bool) -> io::Result<()> {
+    pub fn play_sound(&mut self, path: &Path, is_looping: bool) -> Result<(), AudioError> {
         // In visual-only mode, just update timestamps without actual playback
         if self.visual_only_mode {
             self.last_played = Some(Instant::now());
             return Ok(());
         }
 
-        if let Some(stream_handle) = &self.stream_handle {
-            if let Ok(sink) = Sink::try_new(stream_handle) {
-                let sink = Arc::new(sink);
-                let (mixer_controller, mixer) = mixer(2, 44100);
-
-                if let Ok(file) = File::open(file_path) {
-                    let file_buf = BufReader::new(file);
-                    if let Ok(source) = Decoder::new(file_buf) {
-                        // Apply base effects
-                        let main_source = source
-                            .speed(self.effect_manager.get_playback_speed())
-                            .amplify(self.effect_manager.get_volume());
-
-                        mixer_controller.add(main_source);
-
-                        // Add reverb if enabled
-                        if self.effect_manager.is_reverb_enabled() {
-                            if let Ok(reverb_file) = File::open(file_path) {
-                                let reverb_buf = BufReader::new(reverb_file);
-                                if let Ok(reverb_source) = Decoder::new(reverb_buf) {
-                                    let reverb = reverb_source
-                                        .speed(self.effect_manager.get_playback_speed())
-                                        .amplify(self.effect_manager.get_volume() * 0.4)
-                                        .delay(Duration::from_secs_f32(
-                                            self.effect_manager.get_reverb_delay(),
-                                        ));
-
-                                    mixer_controller.add(reverb);
-                                }
-                            }
-                        }
-
-                        // Apply lowpass filter
-                        let lowpass_cutoff = self.effect_manager.get_lowpass_cutoff();
-                        if lowpass_cutoff < 20000 {
-                            let filtered_mix = mixer.convert_samples().low_pass(lowpass_cutoff);
-                            sink.append(filtered_mix);
-                        } else {
-                            sink.append(mixer);
-                        }
-
-                        self.active_sinks.push((Arc::clone(&sink), is_looping));
-                        self.last_played = Some(Instant::now());
-                    } else {
-                        self.add_message("Error decoding audio file");
-                    }
-                } else {
-                    self.add_message(&format!(
-                        "Error opening file: Make sure {} exists!",
-                        file_path
-                    ));
-                }
+        let audio = self.load_decoded(path)?;
+
+        // Loops use the region saved for this file, or the whole file
+        let region = is_looping.then(|| {
+            self.loop_store
+                .get(path)
+                .unwrap_or(LoopRegion::whole(audio.frames()))
+        });
+
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let head = PlayHead::new();
+        self.play_audio(&name, audio, region, Some(Arc::clone(&head)))?;
+        self.playhead = Some((path.to_path_buf(), head));
+        Ok(())
+    }
+
+    // How far into a file we are, while it's playing
+    pub fn position(&self, path: &Path) -> Option<usize> {
+        match &self.playhead {
+            // Only the source holds the other reference, so it's gone once
+            // the sound has finished
+            Some((playing, head)) if playing == path && Arc::strong_count(head) > 1 => {
+                Some(head.frame())
             }
+            _ => None,
         }
+    }
 
+    // Jump to a frame of a file, starting it first if it isn't playing
+    pub fn seek(&mut self, path: &Path, frame: usize) -> Result<(), AudioError> {
+        if self.position(path).is_none() {
+            self.play_sound(path, false)?;
+        }
+        if let Some((_, head)) = &self.playhead {
+            head.seek(frame);
+        }
         Ok(())
     }
 
-    pub fn update_looping_sounds(&self) {
+    // Play audio that's already in memory, e.g. a preview of an edit
+    pub fn play_buffer(&mut self, audio: DecodedAudio) -> Result<(), AudioError> {
         if self.visual_only_mode {
-            return;
+            self.last_played = Some(Instant::now());
+            return Ok(());
+        }
+
+        self.play_audio("Edit preview", Arc::new(audio), None, None)
+    }
+
+    fn play_audio(
+        &mut self,
+        name: &str,
+        audio: Arc<DecodedAudio>,
+        region: Option<LoopRegion>,
+        head: Option<Arc<PlayHead>>,
+    ) -> Result<(), AudioError> {
+        let is_looping = region.is_some();
+
+        // Make sure the new sound starts with the latest settings
+        self.publish_effects();
+
+        let Some(bus) = &self.bus else {
+            return Err(AudioError::Device(
+                "the output device could not be opened".to_string(),
+            ));
+        };
+
+        let (mixer_controller, mixer) = mixer::<f32>(2, 44100);
+
+        // Speed changes the playback rate itself, everything else is
+        // done by the effect chain
+        let mut source = BufferSource::new(audio, region);
+        if let Some(head) = head {
+            source = source.with_head(head);
         }
+        mixer_controller.add(source.speed(self.effect_manager.get_playback_speed()));
+        let chain = ChainSource::new(mixer, Arc::clone(&self.shared_effects));
+
+        // The tuner undoes the speed of whichever sound was started last
+        if let Ok(mut tap) = self.tap.lock() {
+            tap.playback_speed = self.effect_manager.get_playback_speed();
+        }
+
+        let voice = Voice::new(
+            self.next_voice_id,
+            name,
+            is_looping,
+            bus,
+            chain,
+            Arc::clone(&self.shared_effects),
+        );
+        self.next_voice_id += 1;
+        self.voices.push(voice);
+        self.last_played = Some(Instant::now());
+        Ok(())
+    }
+
+    // Run audio through the effect chain and the master dynamics, the way it
+    // sounds when played (minus the amp envelope and the mixer)
+    pub fn render(&self, audio: Arc<DecodedAudio>) -> DecodedAudio {
+        let shared = SharedEffects::new(&self.effect_manager);
+        let meter = DynamicsMeter::new();
+
+        // Speed changes the rate the audio comes out at, so bring it back to
+        // the file's own rate
+        let (channels, sample_rate) = (audio.channels, audio.sample_rate);
+        let source = UniformSourceIterator::new(
+            BufferSource::new(audio, None).speed(self.effect_manager.get_playback_speed()),
+            channels,
+            sample_rate,
+        );
+        let chain = ChainSource::new(source, Arc::clone(&shared));
+        let gate = Gate::new(chain, Arc::clone(&shared), Arc::clone(&meter));
+        let compressor = Compressor::new(gate, Arc::clone(&shared), Arc::clone(&meter));
+        let limiter = Limiter::new(compressor, shared, meter);
 
-        // Implementation remains similar but uses effect_manager
-        // ...
+        let channels = limiter.channels();
+        let sample_rate = limiter.sample_rate();
+        // Nothing waits on a render, so the lookahead delay can go
+        let latency = limiter.latency();
+        DecodedAudio {
+            samples: limiter.skip(latency).collect(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    // Decode a file into memory, reusing the last one if it's the same file
+    pub fn load_decoded(&mut self, path: &Path) -> Result<Arc<DecodedAudio>, AudioError> {
+        if let Some((cached_path, audio)) = &self.decoded
+            && cached_path == path
+        {
+            return Ok(Arc::clone(audio));
+        }
+
+        let audio = Arc::new(DecodedAudio::load(path)?);
+        self.overview = audio.peaks(OVERVIEW_POINTS);
+        self.decoded = Some((path.to_path_buf(), Arc::clone(&audio)));
+        Ok(audio)
+    }
+
+    // The decoded file, if it's the one we have in memory
+    pub fn decoded_audio(&self, path: &Path) -> Option<&DecodedAudio> {
+        match &self.decoded {
+            Some((cached_path, audio)) if cached_path == path => Some(audio),
+            _ => None,
+        }
+    }
+
+    // Peaks across the whole of a file, if it's the one we have in memory
+    pub fn overview(&self, path: &Path) -> Option<&[f32]> {
+        self.decoded_audio(path).map(|_| self.overview.as_slice())
+    }
+
+    // Drop the in-memory copy of a file that was changed on disk
+    pub fn forget_decoded(&mut self, path: &Path) {
+        if self.decoded_audio(path).is_some() {
+            self.decoded = None;
+        }
+    }
+
+    // Voices fade out with the amp envelope's release, and are cleaned up
+    // once they're done
+    pub fn stop_all(&mut self) {
+        for voice in &mut self.voices {
+            voice.stop();
+        }
+    }
+
+    pub fn stop_voice(&mut self, index: usize) {
+        if let Some(voice) = self.voices.get_mut(index) {
+            voice.stop();
+        }
     }
 
     pub fn cleanup_finished(&mut self) {
-        self.active_sinks
-            .retain(|(sink, is_looping)| *is_looping || !sink.empty());
+        // Looping sounds never run dry, so everything that ended is done
+        self.voices.retain(|voice| !voice.is_finished());
     }
 
     pub fn is_playing(&self) -> bool {
-        self.visual_only_mode || !self.active_sinks.is_empty()
+        self.visual_only_mode || !self.voices.is_empty()
+    }
+
+    pub fn loop_count(&self) -> usize {
+        self.voices
+            .iter()
+            .filter(|voice| voice.is_looping && !voice.releasing)
+            .count()
+    }
+
+    // Hand changed effect settings over to the playing sounds
+    fn publish_effects(&mut self) {
+        if self.effect_manager != self.published_effects {
+            self.shared_effects.publish(&self.effect_manager);
+            self.published_effects = self.effect_manager.clone();
+        }
     }
 
     pub fn update(&mut self) {
+        self.publish_effects();
+
+        let is_playing = !self.visual_only_mode && !self.voices.is_empty();
+        self.ab.update(&self.tap, is_playing);
+
+        // Mute/solo depend on all voices, so resolve them together
+        let any_solo = self.voices.iter().any(|voice| voice.solo);
+        let trim = self.ab.trim();
+        for voice in &self.voices {
+            voice.apply(any_solo, trim);
+        }
+
         self.visualizer.update(
-            &self.active_sinks,
+            self.voices.len(),
             self.last_played,
             self.visual_only_mode,
             &self.effect_manager,
         );
+
+        self.tuner.update(&self.tap, is_playing);
+
+        if let Some(bus) = &self.bus {
+            self.gain_reduction.update(&bus.meter);
+        }
+
+        if let Err(e) = self.recorder.update() {
+            self.messages.error(&format!("Recording failed: {}", e));
+            // The writer is gone either way, so don't keep the device open
+            let _ = self.recorder.disarm();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_in_step_with_the_input() {
+        let player = AudioPlayer::new(None, &Config::new());
+        let audio = Arc::new(DecodedAudio {
+            samples: (0..4000).map(|i| (i / 2 % 50) as f32 / 100.0).collect(),
+            channels: 2,
+            sample_rate: 44100,
+        });
+
+        let rendered = player.render(Arc::clone(&audio));
+        assert_eq!(rendered.channels, 2);
+        assert_eq!(rendered.sample_rate, 44100);
+        // Same length, and the first sample is still the first sample
+        assert_eq!(rendered.samples.len(), audio.samples.len());
+        for (a, b) in rendered.samples.iter().zip(&audio.samples) {
+            assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn renders_at_the_files_own_rate() {
+        let mut player = AudioPlayer::new(None, &Config::new());
+        player.effect_manager.playback_speed = 1.25;
+        let audio = Arc::new(DecodedAudio {
+            samples: vec![0.25; 4000],
+            channels: 2,
+            sample_rate: 44100,
+        });
+
+        let rendered = player.render(audio);
+        assert_eq!(rendered.channels, 2);
+        assert_eq!(rendered.sample_rate, 44100);
+        // Faster is shorter
+        assert!(
+            (rendered.frames() as i64 - 1600).abs() <= 2,
+            "{}",
+            rendered.frames()
+        );
     }
 }
@@ -0,0 +1,30 @@
+// Cursor over the parameters of a mode that lists them (Dynamics, Delay, ...)
+pub struct ParamList<P: 'static> {
+    params: &'static [P],
+    pub selected: usize,
+}
+
+impl<P: Copy> ParamList<P> {
+    pub fn new(params: &'static [P]) -> Self {
+        Self {
+            params,
+            selected: 0,
+        }
+    }
+
+    pub fn params(&self) -> &'static [P] {
+        self.params
+    }
+
+    pub fn selected_param(&self) -> P {
+        self.params[self.selected]
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.params.len();
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = (self.selected + self.params.len() - 1) % self.params.len();
+    }
+}
@@ -0,0 +1,248 @@
+use crate::audio_player::tap::SharedTap;
+use std::time::{Duration, Instant};
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+// Samples pulled from the tap per analysis, halved again by decimation
+const ANALYSIS_SIZE: usize = 4096;
+// YIN threshold on the normalized difference function
+const YIN_THRESHOLD: f32 = 0.15;
+// Quieter than this and we don't bother guessing a pitch
+const MIN_RMS: f32 = 0.01;
+const MIN_FREQUENCY: f32 = 25.0;
+const MAX_FREQUENCY: f32 = 2000.0;
+const ANALYSIS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TunerReading {
+    // Detected fundamental at the output (after the speed change)
+    pub frequency: f32,
+    pub note: &'static str,
+    pub octave: i32,
+    pub cents: f32,
+    // What the fundamental would be at 1.0x speed
+    pub source_frequency: f32,
+    // Pitch shift caused by the playback speed, in semitones
+    pub speed_shift: f32,
+}
+
+impl TunerReading {
+    pub fn new(frequency: f32, playback_speed: f32) -> Self {
+        let (note, octave, cents) = frequency_to_note(frequency);
+
+        Self {
+            frequency,
+            note,
+            octave,
+            cents,
+            source_frequency: frequency / playback_speed,
+            speed_shift: 12.0 * playback_speed.log2(),
+        }
+    }
+
+    // Note name of the pitch before the speed change, e.g. "E1 -3c"
+    pub fn source_note(&self) -> String {
+        let (note, octave, cents) = frequency_to_note(self.source_frequency);
+        format!("{}{} {:+.0}c", note, octave, cents)
+    }
+}
+
+// Map a frequency to the nearest equal-tempered note (A4 = 440Hz)
+pub fn frequency_to_note(frequency: f32) -> (&'static str, i32, f32) {
+    let midi = 69.0 + 12.0 * (frequency / 440.0).log2();
+    let nearest = midi.round();
+    let cents = (midi - nearest) * 100.0;
+    let index = (nearest as i32).rem_euclid(12) as usize;
+    let octave = (nearest as i32).div_euclid(12) - 1;
+
+    (NOTE_NAMES[index], octave, cents)
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+}
+
+// YIN fundamental frequency estimation
+// (de Cheveigné & Kawahara, 2002)
+pub fn detect_pitch(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    // Decimate by two - the bundled samples are bass notes, so we lose nothing
+    // useful and the difference function gets four times cheaper
+    let decimated: Vec<f32> = samples
+        .chunks_exact(2)
+        .map(|pair| (pair[0] + pair[1]) * 0.5)
+        .collect();
+    let sample_rate = sample_rate as f32 / 2.0;
+
+    if rms(&decimated) < MIN_RMS {
+        return None;
+    }
+
+    let window = decimated.len() / 2;
+    let tau_min = (sample_rate / MAX_FREQUENCY) as usize;
+    let tau_max = ((sample_rate / MIN_FREQUENCY) as usize).min(window);
+    if tau_min < 2 || tau_max <= tau_min + 1 {
+        return None;
+    }
+
+    // Difference function
+    let mut diff = vec![0.0f32; tau_max];
+    for (tau, d) in diff.iter_mut().enumerate().skip(1) {
+        *d = (0..window)
+            .map(|j| {
+                let delta = decimated[j] - decimated[j + tau];
+                delta * delta
+            })
+            .sum();
+    }
+
+    // Cumulative mean normalized difference
+    let mut cmnd = vec![1.0f32; tau_max];
+    let mut running_sum = 0.0;
+    for tau in 1..tau_max {
+        running_sum += diff[tau];
+        cmnd[tau] = if running_sum > 0.0 {
+            diff[tau] * tau as f32 / running_sum
+        } else {
+            1.0
+        };
+    }
+
+    // First dip under the threshold, followed down to its local minimum
+    let mut best_tau = None;
+    let mut tau = tau_min;
+    while tau < tau_max {
+        if cmnd[tau] < YIN_THRESHOLD {
+            while tau + 1 < tau_max && cmnd[tau + 1] < cmnd[tau] {
+                tau += 1;
+            }
+            best_tau = Some(tau);
+            break;
+        }
+        tau += 1;
+    }
+    let tau = best_tau?;
+
+    // Parabolic interpolation for sub-sample accuracy
+    let refined = if tau > 0 && tau + 1 < tau_max {
+        let (s0, s1, s2) = (cmnd[tau - 1], cmnd[tau], cmnd[tau + 1]);
+        let denominator = 2.0 * (2.0 * s1 - s2 - s0);
+        if denominator.abs() > f32::EPSILON {
+            tau as f32 + (s2 - s0) / denominator
+        } else {
+            tau as f32
+        }
+    } else {
+        tau as f32
+    };
+
+    Some(sample_rate / refined)
+}
+
+// Live tuner fed from the output tap
+pub struct Tuner {
+    pub reading: Option<TunerReading>,
+    last_analysis: Option<Instant>,
+}
+
+impl Tuner {
+    pub fn new() -> Self {
+        Self {
+            reading: None,
+            last_analysis: None,
+        }
+    }
+
+    pub fn update(&mut self, tap: &SharedTap, is_playing: bool) {
+        if !is_playing {
+            self.reading = None;
+            return;
+        }
+
+        // No need to run YIN on every frame
+        if self
+            .last_analysis
+            .is_some_and(|t| t.elapsed() < ANALYSIS_INTERVAL)
+        {
+            return;
+        }
+        self.last_analysis = Some(Instant::now());
+
+        let (samples, sample_rate, playback_speed) = match tap.lock() {
+            Ok(buffer) => (
+                buffer.latest(ANALYSIS_SIZE),
+                buffer.sample_rate,
+                buffer.playback_speed,
+            ),
+            Err(_) => return,
+        };
+        if samples.len() < ANALYSIS_SIZE {
+            return;
+        }
+
+        if rms(&samples) < MIN_RMS {
+            self.reading = None;
+            return;
+        }
+
+        // Keep the last reading through short unvoiced stretches
+        if let Some(frequency) = detect_pitch(&samples, sample_rate) {
+            self.reading = Some(TunerReading::new(frequency, playback_speed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    fn sine(frequency: f32, sample_rate: u32) -> Vec<f32> {
+        (0..ANALYSIS_SIZE)
+            .map(|i| 0.5 * (TAU * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn names_notes() {
+        assert_eq!(frequency_to_note(440.0), ("A", 4, 0.0));
+        let (note, octave, cents) = frequency_to_note(261.63);
+        assert_eq!((note, octave), ("C", 4));
+        assert!(cents.abs() < 1.0);
+        // Low E of a bass, a little flat
+        let (note, octave, cents) = frequency_to_note(41.0);
+        assert_eq!((note, octave), ("E", 1));
+        assert!((-10.0..0.0).contains(&cents));
+    }
+
+    #[test]
+    fn detects_sines() {
+        for frequency in [55.0, 110.0, 220.0, 440.0, 880.0] {
+            let detected = detect_pitch(&sine(frequency, 44100), 44100).unwrap();
+            assert!(
+                (detected - frequency).abs() / frequency < 0.01,
+                "{} detected as {}",
+                frequency,
+                detected
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_silence_and_noise_floor() {
+        assert_eq!(detect_pitch(&[0.0; ANALYSIS_SIZE], 44100), None);
+        let quiet: Vec<f32> = sine(220.0, 44100).iter().map(|s| s * 0.01).collect();
+        assert_eq!(detect_pitch(&quiet, 44100), None);
+    }
+
+    #[test]
+    fn undoes_the_playback_speed() {
+        // An A3 played at double speed sounds as A4
+        let reading = TunerReading::new(440.0, 2.0);
+        assert_eq!(reading.note, "A");
+        assert_eq!(reading.octave, 4);
+        assert!((reading.speed_shift - 12.0).abs() < 1e-4);
+        assert_eq!(reading.source_note(), "A3 +0c");
+    }
+}
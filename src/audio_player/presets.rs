@@ -0,0 +1,294 @@
+use crate::audio_player::effects::EffectManager;
+use crate::utils;
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+const PRESETS_FILE: &str = "presets.toml";
+
+// Named snapshots of every effect setting, saved to the config directory as
+// one TOML table per preset
+pub struct PresetStore {
+    presets: BTreeMap<String, EffectManager>,
+    // Presets in the file that couldn't be read, kept as they are so saving
+    // doesn't lose them
+    unreadable: toml::Table,
+    // Set when the file as a whole couldn't be read. Saving would replace
+    // it with only the presets made since, so it's refused
+    pub read_error: Option<String>,
+    path: Option<PathBuf>,
+}
+
+impl PresetStore {
+    pub fn load() -> Self {
+        Self::load_from(utils::config_file(PRESETS_FILE))
+    }
+
+    fn load_from(path: Option<PathBuf>) -> Self {
+        let mut store = Self {
+            presets: BTreeMap::new(),
+            unreadable: toml::Table::new(),
+            read_error: None,
+            path,
+        };
+        let Some(path) = &store.path else {
+            return store;
+        };
+
+        let table = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .parse::<toml::Table>()
+                .map_err(|e| e.message().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return store,
+            Err(e) => Err(e.to_string()),
+        };
+        match table {
+            Ok(table) => {
+                for (name, value) in table {
+                    match value.clone().try_into::<EffectManager>() {
+                        Ok(effects) => {
+                            store.presets.insert(name, effects);
+                        }
+                        Err(_) => {
+                            store.unreadable.insert(name, value);
+                        }
+                    }
+                }
+            }
+            Err(e) => store.read_error = Some(e),
+        }
+        store
+    }
+
+    // Names of the presets that couldn't be read
+    pub fn skipped(&self) -> Vec<&str> {
+        self.unreadable.keys().map(String::as_str).collect()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.presets.keys().map(String::as_str).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.presets.len()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EffectManager> {
+        self.presets.get(name)
+    }
+
+    // Index of a preset in the (alphabetical) list
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.presets.keys().position(|key| key == name)
+    }
+
+    // Add a preset, replacing any with the same name
+    pub fn save_as(&mut self, name: &str, effects: &EffectManager) -> io::Result<()> {
+        self.unreadable.remove(name);
+        self.presets.insert(name.to_string(), effects.clone());
+        self.save()
+    }
+
+    // Returns false if there's no such preset or the new name is taken
+    pub fn rename(&mut self, from: &str, to: &str) -> io::Result<bool> {
+        if from == to || self.presets.contains_key(to) || self.unreadable.contains_key(to) {
+            return Ok(false);
+        }
+        let Some(effects) = self.presets.remove(from) else {
+            return Ok(false);
+        };
+        self.presets.insert(to.to_string(), effects);
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn delete(&mut self, name: &str) -> io::Result<()> {
+        self.presets.remove(name);
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.read_error.is_some() {
+            return Err(io::Error::other(format!(
+                "{} could not be read, not overwriting it",
+                path.display()
+            )));
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut table = self.unreadable.clone();
+        for (name, effects) in &self.presets {
+            let value = toml::Value::try_from(effects)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            table.insert(name.clone(), value);
+        }
+        let contents =
+            toml::to_string(&table).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        utils::write_atomically(path, |temp| fs::write(temp, contents))
+    }
+}
+
+// What the preset browser is asking a name for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresetPrompt {
+    SaveAs,
+    Rename,
+}
+
+// State of the preset browser popup
+pub struct PresetBrowser {
+    pub selected: usize,
+    // Set while typing a name, with what's been typed so far
+    pub prompt: Option<PresetPrompt>,
+    pub input: String,
+    pub confirm_delete: bool,
+    // The preset last loaded or saved, where cycling continues from
+    pub current: Option<String>,
+}
+
+impl PresetBrowser {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            prompt: None,
+            input: String::new(),
+            confirm_delete: false,
+            current: None,
+        }
+    }
+
+    pub fn select_next(&mut self, store: &PresetStore) {
+        if store.len() > 0 {
+            self.selected = (self.selected + 1) % store.len();
+        }
+    }
+
+    pub fn select_prev(&mut self, store: &PresetStore) {
+        if store.len() > 0 {
+            self.selected = (self.selected + store.len() - 1) % store.len();
+        }
+    }
+
+    pub fn selected_name(&self, store: &PresetStore) -> Option<String> {
+        store
+            .names()
+            .get(self.selected)
+            .map(|name| name.to_string())
+    }
+
+    pub fn start_prompt(&mut self, prompt: PresetPrompt, input: &str) {
+        self.prompt = Some(prompt);
+        self.input = input.to_string();
+    }
+
+    pub fn cancel_prompt(&mut self) {
+        self.prompt = None;
+        self.input.clear();
+    }
+
+    // The name after the current preset (or before, going back), wrapping
+    // around the list
+    pub fn cycle(&self, store: &PresetStore, forward: bool) -> Option<String> {
+        let names = store.names();
+        if names.is_empty() {
+            return None;
+        }
+        let index = match self
+            .current
+            .as_deref()
+            .and_then(|name| store.position(name))
+        {
+            Some(index) if forward => (index + 1) % names.len(),
+            Some(index) => (index + names.len() - 1) % names.len(),
+            None if forward => 0,
+            None => names.len() - 1,
+        };
+        Some(names[index].to_string())
+    }
+
+    // Keep the selection in range and on the current preset when possible
+    pub fn follow(&mut self, store: &PresetStore, name: &str) {
+        self.current = Some(name.to_string());
+        self.selected = store
+            .position(name)
+            .unwrap_or(self.selected)
+            .min(store.len().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presets_file(name: &str, contents: Option<&str>) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("audirust-presets-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(PRESETS_FILE);
+        if let Some(contents) = contents {
+            fs::write(&path, contents).unwrap();
+        }
+        path
+    }
+
+    fn written(effects: &EffectManager) -> String {
+        let mut table = toml::Table::new();
+        table.insert("good".to_string(), toml::Value::try_from(effects).unwrap());
+        toml::to_string(&table).unwrap()
+    }
+
+    #[test]
+    fn keeps_presets_it_cannot_read() {
+        let broken = "[broken]\nvolume = \"loud\"\n";
+        let path = presets_file(
+            "unreadable",
+            Some(&(written(&EffectManager::new()) + broken)),
+        );
+
+        let mut store = PresetStore::load_from(Some(path.clone()));
+        assert_eq!(store.names(), ["good"]);
+        assert_eq!(store.skipped(), ["broken"]);
+        assert_eq!(store.read_error, None);
+
+        // Its name is taken, and it's still there after saving
+        assert!(!store.rename("good", "broken").unwrap());
+        store.save_as("new", &EffectManager::new()).unwrap();
+        let store = PresetStore::load_from(Some(path.clone()));
+        assert_eq!(store.names(), ["good", "new"]);
+        assert_eq!(store.skipped(), ["broken"]);
+
+        // Saving over it replaces it
+        let mut store = store;
+        store.save_as("broken", &EffectManager::new()).unwrap();
+        let store = PresetStore::load_from(Some(path));
+        assert_eq!(store.names(), ["broken", "good", "new"]);
+        assert!(store.skipped().is_empty());
+    }
+
+    #[test]
+    fn never_overwrites_a_file_it_cannot_read() {
+        let contents = "[good\nvolume = 0.5\n";
+        let path = presets_file("broken", Some(contents));
+
+        let mut store = PresetStore::load_from(Some(path.clone()));
+        assert!(store.read_error.is_some());
+        assert!(store.save_as("new", &EffectManager::new()).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+    }
+
+    #[test]
+    fn starts_without_a_file() {
+        let path = presets_file("missing", None);
+        let mut store = PresetStore::load_from(Some(path.clone()));
+        assert_eq!(store.read_error, None);
+        assert_eq!(store.len(), 0);
+
+        store.save_as("first", &EffectManager::new()).unwrap();
+        let store = PresetStore::load_from(Some(path));
+        assert_eq!(store.get("first"), Some(&EffectManager::new()));
+    }
+}
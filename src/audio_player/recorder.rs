@@ -0,0 +1,547 @@
+use crate::audio_player::sample::DecodedAudio;
+use crate::audio_player::tap::SharedTap;
+use rodio::cpal::{
+    self, FromSample, SizedSample,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+// Level meter falloff per update (~60 per second)
+const LEVEL_DECAY: f32 = 0.92;
+
+// Where recordings are captured from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    // The default cpal input device
+    Device,
+    // Our own playback, taken from the output tap
+    Loopback,
+    // A WAV/MP3/... file played back in real time, standing in for hardware
+    File,
+}
+
+impl InputKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputKind::Device => "Default input device",
+            InputKind::Loopback => "Loopback (our output)",
+            InputKind::File => "File (fake input)",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            InputKind::Device => InputKind::Loopback,
+            InputKind::Loopback => InputKind::File,
+            InputKind::File => InputKind::Device,
+        }
+    }
+}
+
+// An opened input, polled for new samples every update
+enum InputDevice {
+    Device {
+        // Kept alive for as long as we capture
+        _stream: cpal::Stream,
+        captured: Arc<Mutex<Vec<f32>>>,
+        // What went wrong on the audio thread, picked up by the next poll
+        error: Arc<Mutex<Option<String>>>,
+        channels: u16,
+        sample_rate: u32,
+    },
+    Loopback {
+        tap: SharedTap,
+    },
+    File {
+        audio: DecodedAudio,
+        // Set by the first poll
+        started: Option<Instant>,
+        position: usize,
+    },
+}
+
+impl InputDevice {
+    fn open_device() -> io::Result<Self> {
+        let host = cpal::default_host();
+        let device = host
+            .default_input_device()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No input device found"))?;
+        let config = device.default_input_config().map_err(io::Error::other)?;
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let error = Arc::new(Mutex::new(None));
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_stream::<f32>(
+                &device,
+                &config.config(),
+                Arc::clone(&captured),
+                Arc::clone(&error),
+            ),
+            cpal::SampleFormat::I16 => build_stream::<i16>(
+                &device,
+                &config.config(),
+                Arc::clone(&captured),
+                Arc::clone(&error),
+            ),
+            cpal::SampleFormat::U16 => build_stream::<u16>(
+                &device,
+                &config.config(),
+                Arc::clone(&captured),
+                Arc::clone(&error),
+            ),
+            cpal::SampleFormat::I32 => build_stream::<i32>(
+                &device,
+                &config.config(),
+                Arc::clone(&captured),
+                Arc::clone(&error),
+            ),
+            format => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported input sample format {}", format),
+            )),
+        }?;
+        stream.play().map_err(io::Error::other)?;
+
+        Ok(InputDevice::Device {
+            _stream: stream,
+            captured,
+            error,
+            channels: config.channels(),
+            sample_rate: config.sample_rate().0,
+        })
+    }
+
+    fn open_loopback(tap: &SharedTap) -> Self {
+        // Only what's played from now on, in stereo, and kept for us until
+        // the next poll rather than only as long as the tuner needs
+        if let Ok(mut buffer) = tap.lock() {
+            buffer.start_capture();
+        }
+        InputDevice::Loopback {
+            tap: Arc::clone(tap),
+        }
+    }
+
+    fn open_file(path: &Path) -> io::Result<Self> {
+        Ok(InputDevice::File {
+            audio: DecodedAudio::load(path)?,
+            started: None,
+            position: 0,
+        })
+    }
+
+    fn channels(&self) -> u16 {
+        match self {
+            InputDevice::Device { channels, .. } => *channels,
+            InputDevice::Loopback { tap } => tap.lock().map(|buffer| buffer.channels).unwrap_or(2),
+            InputDevice::File { audio, .. } => audio.channels,
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            InputDevice::Device { sample_rate, .. } => *sample_rate,
+            InputDevice::Loopback { tap, .. } => {
+                tap.lock().map(|buffer| buffer.sample_rate).unwrap_or(44100)
+            }
+            InputDevice::File { audio, .. } => audio.sample_rate,
+        }
+    }
+
+    // Append everything captured since the last poll to `out`, or tell
+    // why the device stopped delivering
+    fn poll(&mut self, out: &mut Vec<f32>, now: Instant) -> io::Result<()> {
+        match self {
+            InputDevice::Device {
+                captured, error, ..
+            } => {
+                if let Ok(mut captured) = captured.lock() {
+                    out.append(&mut captured);
+                }
+                if let Some(error) = error.lock().ok().and_then(|mut error| error.take()) {
+                    return Err(io::Error::other(error));
+                }
+            }
+            InputDevice::Loopback { tap } => {
+                if let Ok(mut buffer) = tap.lock() {
+                    let (samples, dropped) = buffer.take_capture();
+                    out.extend(samples);
+                    if dropped > 0 {
+                        return Err(io::Error::other(format!(
+                            "fell too far behind the output, {} samples were lost",
+                            dropped
+                        )));
+                    }
+                }
+            }
+            InputDevice::File {
+                audio,
+                started,
+                position,
+            } => {
+                // Hand out the file at real-time pace, looping forever like
+                // a device would keep on delivering
+                let frames = audio.frames();
+                if frames == 0 {
+                    return Ok(());
+                }
+                let channels = audio.channels as usize;
+                let elapsed = now.duration_since(*started.get_or_insert(now));
+                let due = (elapsed.as_secs_f64() * audio.sample_rate as f64) as usize;
+                while *position < due {
+                    let frame = *position % frames;
+                    out.extend_from_slice(&audio.samples[frame * channels..(frame + 1) * channels]);
+                    *position += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for InputDevice {
+    fn drop(&mut self) {
+        if let InputDevice::Loopback { tap } = self
+            && let Ok(mut buffer) = tap.lock()
+        {
+            buffer.stop_capture();
+        }
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    captured: Arc<Mutex<Vec<f32>>>,
+    error: Arc<Mutex<Option<String>>>,
+) -> io::Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                if let Ok(mut captured) = captured.lock() {
+                    captured.extend(data.iter().map(|s| s.to_sample::<f32>()));
+                }
+            },
+            move |e| {
+                if let Ok(mut error) = error.lock() {
+                    error.get_or_insert(e.to_string());
+                }
+            },
+            None,
+        )
+        .map_err(io::Error::other)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecorderState {
+    Idle,
+    Armed,
+    Recording,
+}
+
+pub struct Recorder {
+    pub input: InputKind,
+    pub state: RecorderState,
+    // Peak input level (0.0 - 1.0) with a falloff for the meter
+    pub level: f32,
+    pub recorded_frames: u64,
+    pub current_file: Option<PathBuf>,
+    device: Option<InputDevice>,
+    writer: Option<hound::WavWriter<BufWriter<File>>>,
+    buffer: Vec<f32>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            input: InputKind::Device,
+            state: RecorderState::Idle,
+            level: 0.0,
+            recorded_frames: 0,
+            current_file: None,
+            device: None,
+            writer: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.device.as_ref().map(|device| device.sample_rate())
+    }
+
+    pub fn recorded_secs(&self) -> f32 {
+        match self.sample_rate() {
+            Some(rate) if rate > 0 => self.recorded_frames as f32 / rate as f32,
+            _ => 0.0,
+        }
+    }
+
+    // Switching inputs disarms, the new one gets opened on the next arm
+    pub fn next_input(&mut self) -> io::Result<()> {
+        self.disarm()?;
+        self.input = self.input.next();
+        Ok(())
+    }
+
+    // Open the input and start metering. `fake_file` feeds the file input
+    pub fn arm(&mut self, tap: &SharedTap, fake_file: Option<&Path>) -> io::Result<()> {
+        if self.state != RecorderState::Idle {
+            return Ok(());
+        }
+
+        let device = match self.input {
+            InputKind::Device => InputDevice::open_device()?,
+            InputKind::Loopback => InputDevice::open_loopback(tap),
+            InputKind::File => {
+                let path = fake_file.ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "No file selected for fake input")
+                })?;
+                InputDevice::open_file(path)?
+            }
+        };
+
+        self.device = Some(device);
+        self.state = RecorderState::Armed;
+        Ok(())
+    }
+
+    pub fn disarm(&mut self) -> io::Result<()> {
+        self.stop()?;
+        self.device = None;
+        self.state = RecorderState::Idle;
+        self.level = 0.0;
+        Ok(())
+    }
+
+    // Start writing to the next free "rec_NNN.wav" in `dir`
+    pub fn record(
+        &mut self,
+        dir: &Path,
+        tap: &SharedTap,
+        fake_file: Option<&Path>,
+    ) -> io::Result<()> {
+        if self.state == RecorderState::Recording {
+            return Ok(());
+        }
+        self.arm(tap, fake_file)?;
+
+        let Some(device) = &self.device else {
+            return Ok(());
+        };
+        let spec = hound::WavSpec {
+            channels: device.channels(),
+            sample_rate: device.sample_rate(),
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let path = next_recording_path(dir);
+        self.writer = Some(hound::WavWriter::create(&path, spec).map_err(io::Error::other)?);
+        self.current_file = Some(path);
+        self.recorded_frames = 0;
+        self.state = RecorderState::Recording;
+
+        // Don't write what piled up while we were only armed
+        self.buffer.clear();
+        Ok(())
+    }
+
+    // Finish the file and go back to just metering.
+    // Returns the path of the finished recording
+    pub fn stop(&mut self) -> io::Result<Option<PathBuf>> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(None);
+        };
+        self.state = RecorderState::Armed;
+        writer.finalize().map_err(io::Error::other)?;
+        Ok(self.current_file.clone())
+    }
+
+    pub fn update(&mut self) -> io::Result<()> {
+        self.update_at(Instant::now())
+    }
+
+    // `now` paces the file input
+    fn update_at(&mut self, now: Instant) -> io::Result<()> {
+        self.level *= LEVEL_DECAY;
+
+        let Some(device) = &mut self.device else {
+            return Ok(());
+        };
+        let channels = device.channels().max(1) as u64;
+        device.poll(&mut self.buffer, now)?;
+
+        let peak = self.buffer.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        self.level = self.level.max(peak.min(1.0));
+
+        if let Some(writer) = &mut self.writer {
+            for sample in &self.buffer {
+                let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+                writer.write_sample(value).map_err(io::Error::other)?;
+            }
+            self.recorded_frames += self.buffer.len() as u64 / channels;
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+fn next_recording_path(dir: &Path) -> PathBuf {
+    let mut n = 1;
+    loop {
+        let candidate = dir.join(format!("rec_{:03}.wav", n));
+        if !candidate.exists() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_player::sample::WavFormat;
+    use crate::audio_player::tap::{OutputTap, TapBuffer};
+    use rodio::buffer::SamplesBuffer;
+    use std::{fs, time::Duration};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("audirust-recorder-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Every sample of a finished recording, with its format
+    fn read_recording(path: &Path) -> (hound::WavSpec, Vec<i16>) {
+        let mut reader = hound::WavReader::open(path).unwrap();
+        let samples = reader.samples::<i16>().map(Result::unwrap).collect();
+        (reader.spec(), samples)
+    }
+
+    #[test]
+    fn records_from_a_file() {
+        let dir = temp_dir("file");
+        let source = dir.join("source.wav");
+        let audio = DecodedAudio {
+            samples: (0..48000 * 2)
+                .map(|i| ((i / 2) % 100) as f32 / 200.0)
+                .collect(),
+            channels: 2,
+            sample_rate: 48000,
+        };
+        audio.write_wav(&source, WavFormat::PCM16).unwrap();
+
+        let tap = TapBuffer::new_shared(1.0);
+        let mut recorder = Recorder::new();
+        recorder.input = InputKind::File;
+        recorder.record(&dir, &tap, Some(&source)).unwrap();
+        assert_eq!(recorder.state, RecorderState::Recording);
+
+        // The file is handed out at real-time pace, from the first update
+        let start = Instant::now();
+        recorder.update_at(start).unwrap();
+        assert_eq!(recorder.recorded_frames, 0);
+        recorder
+            .update_at(start + Duration::from_millis(50))
+            .unwrap();
+        assert_eq!(recorder.recorded_frames, 2400);
+        recorder
+            .update_at(start + Duration::from_millis(100))
+            .unwrap();
+        let frames = recorder.recorded_frames;
+        assert_eq!(frames, 4800);
+        let path = recorder.stop().unwrap().unwrap();
+        assert_eq!(path, dir.join("rec_001.wav"));
+
+        let (spec, samples) = read_recording(&path);
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 48000);
+        assert_eq!(spec.bits_per_sample, 16);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Int);
+        assert_eq!(samples.len() as u64, frames * 2);
+        // It starts at the start of the file
+        let expected: Vec<i16> = (0..20)
+            .map(|i| ((i / 2 % 100) as f32 / 200.0 * i16::MAX as f32).round() as i16)
+            .collect();
+        assert_eq!(&samples[..20], expected.as_slice());
+    }
+
+    // Send `frames` stereo frames of `left`/`right` through an output tap
+    fn play(tap: &SharedTap, sample_rate: u32, frames: usize, left: f32, right: f32) {
+        let samples = [left, right].repeat(frames);
+        OutputTap::new(SamplesBuffer::new(2, sample_rate, samples), Arc::clone(tap)).for_each(drop);
+    }
+
+    #[test]
+    fn records_from_the_loopback() {
+        let dir = temp_dir("loopback");
+        let tap = TapBuffer::new_shared(1.0);
+        // Played before recording started, so not recorded
+        play(&tap, 22050, 512, 0.9, 0.9);
+
+        let mut recorder = Recorder::new();
+        recorder.next_input().unwrap();
+        assert_eq!(recorder.input, InputKind::Loopback);
+        recorder.record(&dir, &tap, None).unwrap();
+
+        play(&tap, 22050, 512, 0.5, -0.25);
+        recorder.update().unwrap();
+        play(&tap, 22050, 1024, -0.5, 0.25);
+        recorder.update().unwrap();
+        assert_eq!(recorder.recorded_frames, 1536);
+        assert!(recorder.level > 0.45);
+        let path = recorder.stop().unwrap().unwrap();
+
+        // Both channels, as they were played
+        let (spec, samples) = read_recording(&path);
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 22050);
+        assert_eq!(samples.len(), 1536 * 2);
+        assert!(
+            samples[..1024]
+                .chunks(2)
+                .all(|frame| frame == [16384, -8192])
+        );
+        assert!(
+            samples[1024..]
+                .chunks(2)
+                .all(|frame| frame == [-16384, 8192])
+        );
+
+        // Recording again picks the next free name
+        recorder.record(&dir, &tap, None).unwrap();
+        assert_eq!(recorder.stop().unwrap().unwrap(), dir.join("rec_002.wav"));
+
+        // Nothing is kept for the recorder once it's let go
+        recorder.disarm().unwrap();
+        play(&tap, 22050, 512, 0.5, 0.5);
+        assert!(tap.lock().unwrap().take_capture().0.is_empty());
+    }
+
+    #[test]
+    fn reports_loopback_audio_it_fell_behind_on() {
+        let dir = temp_dir("behind");
+        let tap = TapBuffer::new_shared(1.0);
+        let mut recorder = Recorder::new();
+        recorder.input = InputKind::Loopback;
+        recorder.record(&dir, &tap, None).unwrap();
+
+        // 30 seconds at 100 Hz is kept, 1096 frames on top of that are not
+        play(&tap, 100, 4096, 0.5, 0.5);
+        let error = recorder.update().unwrap_err();
+        assert!(error.to_string().contains("2192 samples"), "{}", error);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::audio_player::error::AudioError;
+use crate::audio_player::looping::LoopRegion;
+use crate::utils;
+use rodio::{Decoder, Source};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+// How the samples of a WAV file are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    pub bits: u16,
+    pub sample_format: hound::SampleFormat,
+}
+
+impl WavFormat {
+    // What new files are written as
+    pub const PCM16: Self = Self {
+        bits: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    // The format of an existing WAV file, so rewriting it loses nothing
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let spec = hound::WavReader::open(path)
+            .map_err(io::Error::other)?
+            .spec();
+        Ok(Self {
+            bits: spec.bits_per_sample,
+            sample_format: spec.sample_format,
+        })
+    }
+}
+
+// No seek waiting to be done
+const NO_SEEK: usize = usize::MAX;
+
+// A whole audio file decoded into memory as interleaved f32 samples
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl DecodedAudio {
+    pub fn load(path: &Path) -> Result<Self, AudioError> {
+        let file = File::open(path).map_err(|e| AudioError::Open(path.to_path_buf(), e))?;
+        let decoder =
+            Decoder::new(BufReader::new(file)).map_err(|e| AudioError::decode(path, e))?;
+
+        let channels = decoder.channels().max(1);
+        let sample_rate = decoder.sample_rate();
+        let samples = decoder.convert_samples::<f32>().collect();
+
+        Ok(Self {
+            samples,
+            channels,
+            sample_rate,
+        })
+    }
+
+    // Write the audio out as a WAV file. It's written next to the target
+    // first and then moved over it, so a failed write leaves the old file
+    pub fn write_wav(&self, path: &Path, format: WavFormat) -> io::Result<()> {
+        utils::write_atomically(path, |temp| self.write_wav_file(temp, format))
+    }
+
+    fn write_wav_file(&self, path: &Path, format: WavFormat) -> io::Result<()> {
+        let spec = hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: format.bits,
+            sample_format: format.sample_format,
+        };
+
+        let mut writer = hound::WavWriter::create(path, spec).map_err(io::Error::other)?;
+        let scale = match format.sample_format {
+            hound::SampleFormat::Float => 1.0,
+            hound::SampleFormat::Int => ((1i64 << (format.bits - 1)) - 1) as f64,
+        };
+        for sample in &self.samples {
+            let sample = sample.clamp(-1.0, 1.0);
+            let written = match format.sample_format {
+                hound::SampleFormat::Float => writer.write_sample(sample),
+                hound::SampleFormat::Int => {
+                    writer.write_sample((sample as f64 * scale).round() as i32)
+                }
+            };
+            written.map_err(io::Error::other)?;
+        }
+        writer.finalize().map_err(io::Error::other)
+    }
+
+    // Loudest sample in each of `points` equal stretches of the audio, for
+    // drawing an overview
+    pub fn peaks(&self, points: usize) -> Vec<f32> {
+        let channels = self.channels as usize;
+        let frames = self.frames();
+        (0..points)
+            .map(|i| {
+                let start = i * frames / points;
+                let end = ((i + 1) * frames / points).max(start + 1).min(frames);
+                self.samples
+                    .get(start * channels..end * channels)
+                    .unwrap_or(&[])
+                    .iter()
+                    .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+            })
+            .collect()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    pub fn frames_to_secs(&self, frames: usize) -> f32 {
+        frames as f32 / self.sample_rate as f32
+    }
+
+    pub fn secs_to_frames(&self, secs: f32) -> usize {
+        (secs.max(0.0) * self.sample_rate as f32).round() as usize
+    }
+
+    // Mono value of a frame, used for zero crossing detection
+    fn frame_value(&self, frame: usize) -> f32 {
+        let channels = self.channels as usize;
+        let start = frame * channels;
+        self.samples[start..start + channels].iter().sum::<f32>() / channels as f32
+    }
+
+    // Closest frame to `frame` where the signal crosses zero going upwards.
+    // Falls back to `frame` itself if there is no crossing in the file
+    pub fn nearest_zero_crossing(&self, frame: usize) -> usize {
+        let frames = self.frames();
+        if frames < 2 {
+            return frame;
+        }
+
+        let is_crossing = |f: usize| {
+            f > 0 && f < frames && self.frame_value(f - 1) < 0.0 && self.frame_value(f) >= 0.0
+        };
+
+        let frame = frame.min(frames - 1);
+        for distance in 0..frames {
+            if frame >= distance && is_crossing(frame - distance) {
+                return frame - distance;
+            }
+            if is_crossing(frame + distance) {
+                return frame + distance;
+            }
+            if frame < distance && frame + distance >= frames {
+                break;
+            }
+        }
+        frame
+    }
+}
+
+// Where a BufferSource is in its audio, and where it's asked to jump to,
+// shared between the audio thread and the UI
+pub struct PlayHead {
+    frame: AtomicUsize,
+    seek: AtomicUsize,
+}
+
+impl PlayHead {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            frame: AtomicUsize::new(0),
+            seek: AtomicUsize::new(NO_SEEK),
+        })
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame.load(Ordering::Relaxed)
+    }
+
+    pub fn seek(&self, frame: usize) {
+        self.seek.store(frame, Ordering::Relaxed);
+    }
+}
+
+// Plays a decoded buffer from the start, optionally looping a region of it
+pub struct BufferSource {
+    audio: Arc<DecodedAudio>,
+    region: Option<LoopRegion>,
+    frame: usize,
+    channel: usize,
+    head: Option<Arc<PlayHead>>,
+}
+
+impl BufferSource {
+    pub fn new(audio: Arc<DecodedAudio>, region: Option<LoopRegion>) -> Self {
+        let region = region.map(|r| r.clamped(audio.frames()));
+
+        Self {
+            audio,
+            region,
+            frame: 0,
+            channel: 0,
+            head: None,
+        }
+    }
+
+    // Report the position to, and take seeks from, a play head
+    pub fn with_head(mut self, head: Arc<PlayHead>) -> Self {
+        self.head = Some(head);
+        self
+    }
+}
+
+impl Iterator for BufferSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let channels = self.audio.channels as usize;
+
+        if self.channel == 0
+            && let Some(head) = &self.head
+        {
+            let seek = head.seek.swap(NO_SEEK, Ordering::Relaxed);
+            if seek != NO_SEEK {
+                self.frame = seek.min(self.audio.frames());
+            }
+            head.frame.store(self.frame, Ordering::Relaxed);
+        }
+
+        if let Some(region) = &self.region
+            && self.frame >= region.end
+        {
+            self.frame = region.start;
+        }
+
+        let mut sample = *self
+            .audio
+            .samples
+            .get(self.frame * channels + self.channel)?;
+
+        // Blend the tail of the loop into the audio leading up to the loop
+        // start, so the jump back lands on matching material
+        if let Some(region) = &self.region
+            && region.crossfade > 0
+            && self.frame + region.crossfade >= region.end
+        {
+            let offset = self.frame + region.crossfade - region.end;
+            let t = offset as f32 / region.crossfade as f32;
+            let lead_in = region.start - region.crossfade + offset;
+            let lead_sample = self.audio.samples[lead_in * channels + self.channel];
+
+            // Equal-gain crossfade: the two sides are the same material, so
+            // an equal-power one would bump steady audio up by 3 dB
+            sample += (lead_sample - sample) * t;
+        }
+
+        self.channel += 1;
+        if self.channel >= channels {
+            self.channel = 0;
+            self.frame += 1;
+        }
+
+        Some(sample)
+    }
+}
+
+impl Source for BufferSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.audio.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self.region {
+            Some(_) => None,
+            None => Some(Duration::from_secs_f32(
+                self.audio.frames_to_secs(self.audio.frames()),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("audirust-sample-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn ramp() -> DecodedAudio {
+        DecodedAudio {
+            samples: (0..200).map(|i| i as f32 / 100.0 - 1.0).collect(),
+            channels: 2,
+            sample_rate: 44100,
+        }
+    }
+
+    fn mono(samples: Vec<f32>) -> Arc<DecodedAudio> {
+        Arc::new(DecodedAudio {
+            samples,
+            channels: 1,
+            sample_rate: 1000,
+        })
+    }
+
+    #[test]
+    fn finds_upward_zero_crossings() {
+        // Crosses upwards into frames 4 and 12, downwards into frame 8
+        let audio = mono(
+            [-1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0]
+                .repeat(2)
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(audio.nearest_zero_crossing(4), 4);
+        assert_eq!(audio.nearest_zero_crossing(6), 4);
+        assert_eq!(audio.nearest_zero_crossing(9), 12);
+        assert_eq!(audio.nearest_zero_crossing(100), 12);
+
+        // Nothing to snap to
+        let flat = mono(vec![0.5; 10]);
+        assert_eq!(flat.nearest_zero_crossing(3), 3);
+    }
+
+    #[test]
+    fn loops_the_region_with_a_crossfade() {
+        // Each sample is its own frame number
+        let audio = mono((0..30).map(|i| i as f32).collect());
+        let region = LoopRegion {
+            start: 10,
+            end: 20,
+            crossfade: 4,
+        };
+        let played: Vec<f32> = BufferSource::new(audio, Some(region)).take(40).collect();
+
+        // Untouched up to the crossfade
+        assert_eq!(&played[..17], (0..17).map(|i| i as f32).collect::<Vec<_>>());
+        // The tail blends into the four frames before the loop start
+        assert_eq!(played[17], 17.0 * 0.75 + 7.0 * 0.25);
+        // Then it's back at the start, every ten frames
+        assert_eq!(played[20], 10.0);
+        assert_eq!(played[30], 10.0);
+        assert_eq!(played[21..30], played[31..40]);
+    }
+
+    #[test]
+    fn crossfade_keeps_steady_audio_steady() {
+        let audio = mono(vec![1.0; 100]);
+        let region = LoopRegion {
+            start: 50,
+            end: 100,
+            crossfade: 40,
+        };
+        for sample in BufferSource::new(audio, Some(region)).take(300) {
+            assert_eq!(sample, 1.0);
+        }
+    }
+
+    #[test]
+    fn seeks_through_the_play_head() {
+        let head = PlayHead::new();
+        let mut source = BufferSource::new(mono((0..10).map(|i| i as f32).collect()), None)
+            .with_head(Arc::clone(&head));
+        assert_eq!(source.next(), Some(0.0));
+        head.seek(7);
+        assert_eq!(source.next(), Some(7.0));
+        assert_eq!(head.frame(), 7);
+        assert_eq!(source.count(), 2);
+    }
+
+    #[test]
+    fn rewriting_keeps_the_wav_format() {
+        let path = temp_path("format.wav");
+        let float = WavFormat {
+            bits: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let pcm24 = WavFormat {
+            bits: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        for format in [float, pcm24, WavFormat::PCM16] {
+            ramp().write_wav(&path, format).unwrap();
+            assert_eq!(WavFormat::of(&path).unwrap(), format);
+
+            let loaded = DecodedAudio::load(&path).unwrap();
+            assert_eq!(loaded.channels, 2);
+            assert_eq!(loaded.frames(), 100);
+            for (a, b) in loaded.samples.iter().zip(&ramp().samples) {
+                assert!((a - b).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn failed_write_leaves_the_original() {
+        let path = temp_path("original.wav");
+        ramp().write_wav(&path, WavFormat::PCM16).unwrap();
+        let before = fs::read(&path).unwrap();
+
+        // hound can't write 12-bit samples, so this fails part way
+        let odd = WavFormat {
+            bits: 12,
+            sample_format: hound::SampleFormat::Int,
+        };
+        assert!(ramp().write_wav(&path, odd).is_err());
+        assert_eq!(fs::read(&path).unwrap(), before);
+        assert!(!path.with_file_name(".original.wav.part").exists());
+    }
+}
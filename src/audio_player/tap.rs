@@ -0,0 +1,184 @@
+use rodio::Source;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// How many mono samples we keep around for analysis (~185ms at 44.1kHz)
+const TAP_CAPACITY: usize = 8192;
+// Samples are batched locally before taking the lock
+const FLUSH_SIZE: usize = 512;
+// How far the loopback recorder may fall behind before audio is dropped
+const CAPTURE_SECS: usize = 30;
+
+// Recent mono samples of what is being sent to the output
+pub struct TapBuffer {
+    pub samples: VecDeque<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // Speed the tapped sound was started with, so the tuner can undo it
+    pub playback_speed: f32,
+    // Total samples ever pushed, so readers can tell what's new
+    pub written: u64,
+    // Everything sent to the output, in all its channels, while the
+    // loopback recorder listens. Emptied by the recorder on every update
+    capture: Option<Vec<f32>>,
+    // Captured samples that didn't fit before the recorder came for them
+    capture_dropped: u64,
+}
+
+pub type SharedTap = Arc<Mutex<TapBuffer>>;
+
+impl TapBuffer {
+    pub fn new_shared(playback_speed: f32) -> SharedTap {
+        Arc::new(Mutex::new(Self {
+            samples: VecDeque::with_capacity(TAP_CAPACITY),
+            sample_rate: 44100,
+            channels: 2,
+            playback_speed,
+            written: 0,
+            capture: None,
+            capture_dropped: 0,
+        }))
+    }
+
+    // Keep everything played from now on, until `stop_capture`
+    pub fn start_capture(&mut self) {
+        self.capture = Some(Vec::new());
+        self.capture_dropped = 0;
+    }
+
+    pub fn stop_capture(&mut self) {
+        self.capture = None;
+    }
+
+    // What was captured since the last call, and how many samples were
+    // dropped because the last call was too long ago
+    pub fn take_capture(&mut self) -> (Vec<f32>, u64) {
+        let samples = self
+            .capture
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        (samples, std::mem::take(&mut self.capture_dropped))
+    }
+
+    fn push_capture(&mut self, samples: &[f32]) {
+        let capacity = CAPTURE_SECS * self.sample_rate as usize * self.channels as usize;
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+        let room = capacity.saturating_sub(capture.len());
+        let kept = samples.len().min(room);
+        capture.extend_from_slice(&samples[..kept]);
+        self.capture_dropped += (samples.len() - kept) as u64;
+    }
+
+    // Samples pushed after the `since` count, plus the new count
+    pub fn since(&self, since: u64) -> (Vec<f32>, u64) {
+        let new = (self.written.saturating_sub(since) as usize).min(self.samples.len());
+        (self.latest(new), self.written)
+    }
+
+    // Copy the newest `count` samples (or fewer if not available yet)
+    pub fn latest(&self, count: usize) -> Vec<f32> {
+        let start = self.samples.len().saturating_sub(count);
+        self.samples.iter().skip(start).copied().collect()
+    }
+}
+
+// Source wrapper that passes audio through unchanged while copying a
+// mono downmix of it (and, for the recorder, the audio itself) into a
+// shared buffer
+pub struct OutputTap<S> {
+    input: S,
+    tap: SharedTap,
+    pending: Vec<f32>,
+    pending_capture: Vec<f32>,
+    frame_sum: f32,
+    frame_pos: u16,
+}
+
+impl<S> OutputTap<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, tap: SharedTap) -> Self {
+        if let Ok(mut buffer) = tap.lock() {
+            buffer.sample_rate = input.sample_rate();
+            buffer.channels = input.channels();
+            buffer.samples.clear();
+        }
+
+        Self {
+            input,
+            tap,
+            pending: Vec::with_capacity(FLUSH_SIZE),
+            pending_capture: Vec::new(),
+            frame_sum: 0.0,
+            frame_pos: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Ok(mut buffer) = self.tap.lock() {
+            buffer.written += self.pending.len() as u64;
+            buffer.samples.extend(self.pending.drain(..));
+            let overflow = buffer.samples.len().saturating_sub(TAP_CAPACITY);
+            buffer.samples.drain(..overflow);
+            buffer.push_capture(&self.pending_capture);
+        } else {
+            self.pending.clear();
+        }
+        self.pending_capture.clear();
+    }
+}
+
+impl<S> Iterator for OutputTap<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        let channels = self.input.channels().max(1);
+
+        self.pending_capture.push(sample);
+        self.frame_sum += sample;
+        self.frame_pos += 1;
+        if self.frame_pos >= channels {
+            self.pending.push(self.frame_sum / channels as f32);
+            self.frame_sum = 0.0;
+            self.frame_pos = 0;
+
+            if self.pending.len() >= FLUSH_SIZE {
+                self.flush();
+            }
+        }
+
+        Some(sample)
+    }
+}
+
+impl<S> Source for OutputTap<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
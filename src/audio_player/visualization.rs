@@ -1,8 +1,6 @@
 use crate::audio_player::effects::EffectManager;
-use rodio::Sink;
 use std::{
     collections::VecDeque,
-    sync::Arc,
     time::{Duration, Instant},
 };
 
@@ -21,17 +19,15 @@ impl WaveformVisualizer {
 
     pub fn update(
         &mut self,
-        active_sinks: &[(Arc<Sink>, bool)],
+        active_voices: usize,
         last_played: Option<Instant>,
         visual_only_mode: bool,
         effect_manager: &EffectManager,
     ) {
         // Reset waveform if no active sounds and last played was over 5 seconds ago
-        if active_sinks.is_empty()
-            && last_played.map_or(true, |t| t.elapsed() > Duration::from_secs(5))
-        {
+        if active_voices == 0 && last_played.is_none_or(|t| t.elapsed() > Duration::from_secs(5)) {
             for val in &mut self.waveform_values {
-                *val = *val * 0.9; // Fade out
+                *val *= 0.9; // Fade out
                 if *val < 0.01 {
                     *val = 0.0;
                 }
@@ -39,7 +35,7 @@ impl WaveformVisualizer {
             return;
         }
 
-        let is_active = !active_sinks.is_empty();
+        let is_active = active_voices > 0;
 
         // Use actual audio samples if available
         if !self.audio_samples.is_empty() {
@@ -85,7 +81,7 @@ impl WaveformVisualizer {
                 }
             } else {
                 // Fade out
-                *val = *val * 0.95;
+                *val *= 0.95;
                 if *val < 0.01 {
                     *val = 0.0;
                 }
@@ -135,7 +131,7 @@ impl WaveformVisualizer {
                 *val = (*val * 0.7).min(1.0);
             } else {
                 // Fade out
-                *val = *val * 0.95;
+                *val *= 0.95;
                 if *val < 0.01 {
                     *val = 0.0;
                 }
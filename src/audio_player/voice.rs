@@ -0,0 +1,326 @@
+use crate::audio_player::bus::MasterBus;
+use crate::audio_player::chain::{ChainSource, SharedEffects};
+use crate::audio_player::envelope::{AdsrSettings, Envelope};
+use rodio::Source;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+// How often (in frames) a voice picks up new gain/pan values
+const CONTROL_INTERVAL: usize = 64;
+
+// Gain and pan of a voice as seen by the audio thread (stored as f32 bits),
+// plus the flags to stop it and to tell it's done
+pub struct VoiceControls {
+    gain: AtomicU32,
+    pan: AtomicU32,
+    stopped: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl VoiceControls {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            gain: AtomicU32::new(1.0f32.to_bits()),
+            pan: AtomicU32::new(0.0f32.to_bits()),
+            stopped: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        })
+    }
+
+    fn set(&self, gain: f32, pan: f32) {
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+        self.pan.store(pan.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.gain.load(Ordering::Relaxed)),
+            f32::from_bits(self.pan.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+// A playing sound with its own mixer settings
+pub struct Voice {
+    pub id: u64,
+    pub name: String,
+    pub is_looping: bool,
+    pub started: Instant,
+    pub gain: f32,
+    // -1.0 (left) to 1.0 (right)
+    pub pan: f32,
+    pub muted: bool,
+    pub solo: bool,
+    // Stopped, but still fading out with the release
+    pub releasing: bool,
+    controls: Arc<VoiceControls>,
+}
+
+impl Voice {
+    // Start a voice on the master bus, following the voice's mixer settings
+    // and the amp envelope
+    pub fn new<S>(
+        id: u64,
+        name: &str,
+        is_looping: bool,
+        bus: &MasterBus,
+        source: ChainSource<S>,
+        shared: Arc<SharedEffects>,
+    ) -> Self
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        let controls = VoiceControls::new();
+        bus.add(VoiceSource::new(source, Arc::clone(&controls), shared));
+
+        Self {
+            id,
+            name: name.to_string(),
+            is_looping,
+            started: Instant::now(),
+            gain: 1.0,
+            pan: 0.0,
+            muted: false,
+            solo: false,
+            releasing: false,
+            controls,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn change_gain(&mut self, increase: bool) {
+        if increase {
+            self.gain = (self.gain + 0.1).min(2.0);
+        } else {
+            self.gain = (self.gain - 0.1).max(0.0);
+        }
+    }
+
+    pub fn change_pan(&mut self, right: bool) {
+        if right {
+            self.pan = (self.pan + 0.1).min(1.0);
+        } else {
+            self.pan = (self.pan - 0.1).max(-1.0);
+        }
+        // Keep "center" exactly at zero after a few steps
+        if self.pan.abs() < 0.001 {
+            self.pan = 0.0;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.controls.finished.load(Ordering::Relaxed)
+    }
+
+    // Start the release, the voice is finished once it has faded out
+    pub fn stop(&mut self) {
+        self.releasing = true;
+        self.controls.stopped.store(true, Ordering::Relaxed);
+    }
+
+    // Push the settings to the audio thread. Muted voices, and voices that
+    // aren't soloed while another one is, go silent. The trim comes on top
+    // of the voice's own gain
+    pub fn apply(&self, any_solo: bool, trim: f32) {
+        let audible = !self.muted && (!any_solo || self.solo);
+        let gain = if audible { self.gain * trim } else { 0.0 };
+        self.controls.set(gain, self.pan);
+    }
+}
+
+// Applies a voice's amp envelope, gain and pan (as a balance on stereo
+// material)
+struct VoiceSource<S> {
+    input: ChainSource<S>,
+    controls: Arc<VoiceControls>,
+    shared: Arc<SharedEffects>,
+    generation: Option<u64>,
+    envelope_settings: AdsrSettings,
+    envelope: Envelope,
+    releasing: bool,
+    channel: u16,
+    frames_until_check: usize,
+    level: f32,
+    left: f32,
+    right: f32,
+}
+
+impl<S> VoiceSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn new(
+        input: ChainSource<S>,
+        controls: Arc<VoiceControls>,
+        shared: Arc<SharedEffects>,
+    ) -> Self {
+        Self {
+            input,
+            controls,
+            shared,
+            generation: None,
+            envelope_settings: AdsrSettings::new(0.0, 0.0, 1.0, 0.0),
+            envelope: Envelope::new(),
+            releasing: false,
+            channel: 0,
+            frames_until_check: 0,
+            level: 0.0,
+            left: 1.0,
+            right: 1.0,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let (gain, pan) = self.controls.get();
+        // Panning only turns the opposite side down, center stays at unity
+        self.left = gain * (1.0 - pan.max(0.0));
+        self.right = gain * (1.0 + pan.min(0.0));
+
+        if let Some(settings) = self
+            .shared
+            .read_if_changed(&mut self.generation, |effects| effects.amp_envelope)
+        {
+            self.envelope_settings = settings;
+        }
+
+        // Stopping releases the amp envelope and the ones in the chain
+        if !self.releasing && self.controls.stopped.load(Ordering::Relaxed) {
+            self.releasing = true;
+            self.envelope.release();
+            self.input.release();
+        }
+    }
+
+    fn finish(&self) -> Option<f32> {
+        self.controls.finished.store(true, Ordering::Relaxed);
+        None
+    }
+}
+
+impl<S> Iterator for VoiceSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            if self.frames_until_check == 0 {
+                self.refresh();
+                self.frames_until_check = CONTROL_INTERVAL;
+            }
+            self.frames_until_check -= 1;
+
+            if self.envelope.is_done() {
+                return self.finish();
+            }
+            self.level = self
+                .envelope
+                .next(&self.envelope_settings, self.input.sample_rate());
+        }
+
+        let Some(sample) = self.input.next() else {
+            return self.finish();
+        };
+        let channels = self.input.channels();
+
+        let gain = match (channels, self.channel) {
+            (2, 0) => self.left,
+            (2, _) => self.right,
+            // No panning for mono or multichannel, just the gain
+            _ => (self.left + self.right) * 0.5,
+        };
+
+        self.channel = (self.channel + 1) % channels.max(1);
+        Some(sample * gain * self.level)
+    }
+}
+
+// The bus drops a voice once it's done, make sure it gets cleaned up even
+// if it never got to say so
+impl<S> Drop for VoiceSource<S> {
+    fn drop(&mut self) {
+        self.controls.finished.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<S> Source for VoiceSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_player::effects::EffectManager;
+    use rodio::buffer::SamplesBuffer;
+
+    // At 1 kHz every millisecond is one frame
+    const RATE: u32 = 1000;
+
+    fn voice(frames: usize, release: f32) -> (VoiceSource<SamplesBuffer<f32>>, Arc<VoiceControls>) {
+        let mut effects = EffectManager::new();
+        effects.chain.clear();
+        effects.amp_envelope = AdsrSettings::new(0.0, 0.0, 1.0, release);
+        let shared = SharedEffects::new(&effects);
+
+        let input = SamplesBuffer::new(2, RATE, vec![1.0; frames * 2]);
+        let controls = VoiceControls::new();
+        let source = VoiceSource::new(
+            ChainSource::new(input, Arc::clone(&shared)),
+            Arc::clone(&controls),
+            shared,
+        );
+        (source, controls)
+    }
+
+    #[test]
+    fn applies_gain_and_pan() {
+        let (source, controls) = voice(10, 0.0);
+        controls.set(0.5, -0.5);
+        let output: Vec<f32> = source.collect();
+
+        assert_eq!(output.len(), 20);
+        assert!(output.chunks(2).all(|frame| frame == [0.5, 0.25]));
+        assert!(controls.finished.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn fades_out_after_stopping() {
+        let (mut source, controls) = voice(1000, 10.0);
+        let before: Vec<f32> = source.by_ref().take(2 * CONTROL_INTERVAL).collect();
+        assert!(before.iter().all(|&s| s == 1.0));
+
+        controls.stopped.store(true, Ordering::Relaxed);
+        // A control check is due, so it's gone after exactly the release
+        let after: Vec<f32> = source.collect();
+        assert_eq!(after.len(), 2 * 10);
+        assert!(after.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(after[after.len() - 1], 0.0);
+        assert!(controls.finished.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,409 @@
+use crate::audio_player::effects::Adjustable;
+use crate::config::Config;
+use crate::file_manager::FileManager;
+use crate::utils;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const MAX_HISTORY: usize = 100;
+
+// Command names for completion, with what they take
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("vol", "<volume>"),
+    ("speed", "<factor>"),
+    ("lpf", "<Hz>"),
+    ("balance", "<-1 to 1>"),
+    ("width", "<width>"),
+    ("reverb", "on|off|toggle"),
+    ("cd", "<directory>"),
+    ("play", "<file>"),
+    ("loop", "<file>"),
+    ("stop", ""),
+    ("loop-start", "<seconds>"),
+    ("loop-end", "<seconds>"),
+    ("export", "<file.wav>"),
+    ("preset", "<name>"),
+    ("theme", "<name>"),
+    ("quit", ""),
+];
+
+// A parsed `:` command, with its values already checked
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Volume(f32),
+    Speed(f32),
+    Lowpass(f32),
+    Balance(f32),
+    Width(f32),
+    // None toggles
+    Reverb(Option<bool>),
+    Cd(String),
+    Play(String),
+    Loop(String),
+    Stop,
+    // Seconds into the current file
+    LoopStart(f32),
+    LoopEnd(f32),
+    // Whether to replace an existing file (`export!`)
+    Export(String, bool),
+    Preset(String),
+    Theme(String),
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str, config: &Config) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+
+        // Values are checked against the ranges of the config
+        let value = |range: &Adjustable| -> Result<f32, String> {
+            if arg.is_empty() {
+                return Err(format!("{}: missing value", name));
+            }
+            let value: f32 = arg
+                .parse()
+                .map_err(|_| format!("{}: '{}' is not a number", name, arg))?;
+            if (range.min..=range.max).contains(&value) {
+                Ok(value)
+            } else {
+                Err(format!(
+                    "{}: {} is out of range ({} - {})",
+                    name, value, range.min, range.max
+                ))
+            }
+        };
+        let secs = || -> Result<f32, String> {
+            if arg.is_empty() {
+                return Err(format!("{}: missing time", name));
+            }
+            match arg.parse::<f32>() {
+                Ok(secs) if secs >= 0.0 => Ok(secs),
+                _ => Err(format!("{}: '{}' is not a time in seconds", name, arg)),
+            }
+        };
+        let text = || -> Result<String, String> {
+            if arg.is_empty() {
+                Err(format!("{}: missing argument", name))
+            } else {
+                Ok(arg.to_string())
+            }
+        };
+
+        match name {
+            "vol" | "volume" => value(&config.volume).map(Command::Volume),
+            "speed" => value(&config.speed).map(Command::Speed),
+            "lpf" | "lowpass" => value(&config.lowpass).map(Command::Lowpass),
+            "balance" | "bal" => value(&config.balance).map(Command::Balance),
+            "width" => value(&config.stereo_width).map(Command::Width),
+            "reverb" => match arg {
+                "on" => Ok(Command::Reverb(Some(true))),
+                "off" => Ok(Command::Reverb(Some(false))),
+                "" | "toggle" => Ok(Command::Reverb(None)),
+                _ => Err(format!("reverb: expected on, off or toggle, not '{}'", arg)),
+            },
+            "cd" => Ok(Command::Cd(
+                if arg.is_empty() { "~" } else { arg }.to_string(),
+            )),
+            "play" => text().map(Command::Play),
+            "loop" => text().map(Command::Loop),
+            "stop" => Ok(Command::Stop),
+            "loop-start" => secs().map(Command::LoopStart),
+            "loop-end" => secs().map(Command::LoopEnd),
+            "export" => text().map(|path| Command::Export(path, false)),
+            "export!" => text().map(|path| Command::Export(path, true)),
+            "preset" => text().map(Command::Preset),
+            "theme" => text().map(Command::Theme),
+            "q" | "quit" => Ok(Command::Quit),
+            "" => Err("No command".to_string()),
+            _ => Err(format!("Unknown command: {}", name)),
+        }
+    }
+}
+
+// A path typed on the command line, relative to the browser's directory
+pub fn resolve_path(dir: &Path, path: &str) -> PathBuf {
+    let path = utils::expand_home(path);
+    if path.is_absolute() {
+        path
+    } else {
+        dir.join(path)
+    }
+}
+
+// The `:` command line, with its history and tab completion
+pub struct CommandLine {
+    pub active: bool,
+    pub input: String,
+    history: Vec<String>,
+    // Where we are while going through the history, and what was typed
+    // before starting to
+    history_index: Option<usize>,
+    draft: String,
+    // Candidates for the word being completed, where it starts in the
+    // input, and which one is shown
+    pub completions: Vec<String>,
+    completion_start: usize,
+    pub completion_index: usize,
+    // What the last command did, or why it failed, shown until the next key
+    pub result: Option<Result<String, String>>,
+}
+
+impl CommandLine {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            input: String::new(),
+            history: Vec::new(),
+            history_index: None,
+            draft: String::new(),
+            completions: Vec::new(),
+            completion_start: 0,
+            completion_index: 0,
+            result: None,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.active = true;
+        self.input.clear();
+        self.history_index = None;
+        self.completions.clear();
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+        self.input.clear();
+        self.completions.clear();
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.completions.clear();
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.completions.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.input.clear();
+        self.completions.clear();
+    }
+
+    // Close the line, remembering what was entered
+    pub fn submit(&mut self) -> String {
+        let line = self.input.trim().to_string();
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.close();
+        line
+    }
+
+    pub fn history_prev(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match self.history_index {
+            Some(index) => index.saturating_sub(1),
+            None => {
+                self.draft = self.input.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.input = self.history[index].clone();
+        self.completions.clear();
+    }
+
+    pub fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.input = self.history[index + 1].clone();
+        } else {
+            // Past the newest entry is what was being typed
+            self.history_index = None;
+            self.input = std::mem::take(&mut self.draft);
+        }
+        self.completions.clear();
+    }
+
+    // Complete the word before the cursor, or go on to the next (or
+    // previous) candidate if we just did
+    pub fn complete(
+        &mut self,
+        forward: bool,
+        files: &FileManager,
+        presets: &[&str],
+        themes: &[&str],
+    ) {
+        if self.completions.is_empty() {
+            let (start, candidates) = candidates(&self.input, files, presets, themes);
+            if candidates.is_empty() {
+                return;
+            }
+            self.completion_start = start;
+            self.completion_index = if forward { 0 } else { candidates.len() - 1 };
+            self.completions = candidates;
+        } else if forward {
+            self.completion_index = (self.completion_index + 1) % self.completions.len();
+        } else {
+            self.completion_index =
+                (self.completion_index + self.completions.len() - 1) % self.completions.len();
+        }
+
+        self.input.truncate(self.completion_start);
+        self.input
+            .push_str(&self.completions[self.completion_index]);
+
+        // A single match is done, so the next Tab can go into a directory
+        if self.completions.len() == 1 {
+            self.completions.clear();
+        }
+    }
+}
+
+// What the last word of the input could be, and where that word starts
+fn candidates(
+    input: &str,
+    files: &FileManager,
+    presets: &[&str],
+    themes: &[&str],
+) -> (usize, Vec<String>) {
+    let Some((name, _)) = input.split_once(char::is_whitespace) else {
+        let names = COMMANDS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .filter(|name| name.starts_with(input))
+            .collect();
+        return (0, names);
+    };
+
+    // The argument is the rest of the line, so file names can have spaces
+    let start = input.len() - input[name.len()..].trim_start().len();
+    let arg = &input[start..];
+
+    let candidates = match name {
+        "reverb" => ["on", "off", "toggle"]
+            .iter()
+            .filter(|value| value.starts_with(arg))
+            .map(|value| value.to_string())
+            .collect(),
+        "preset" => presets
+            .iter()
+            .filter(|preset| preset.starts_with(arg))
+            .map(|preset| preset.to_string())
+            .collect(),
+        "theme" => themes
+            .iter()
+            .filter(|theme| theme.starts_with(arg))
+            .map(|theme| theme.to_string())
+            .collect(),
+        "cd" => paths(files, arg, true),
+        "play" | "loop" | "export" | "export!" => paths(files, arg, false),
+        _ => Vec::new(),
+    };
+    (start, candidates)
+}
+
+// Directories (with a trailing /) and audio files matching a partly typed
+// path, as the whole path to put in its place
+fn paths(files: &FileManager, arg: &str, dirs_only: bool) -> Vec<String> {
+    let (parent, prefix) = match arg.rfind('/') {
+        Some(index) => arg.split_at(index + 1),
+        None => ("", arg),
+    };
+    let dir = resolve_path(
+        &files.current_dir,
+        if parent.is_empty() { "." } else { parent },
+    );
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut found: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().to_str()?.to_string();
+            // Hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            if path.is_dir() {
+                Some(format!("{}{}/", parent, name))
+            } else if !dirs_only && files.is_audio_file(&path) {
+                Some(format!("{}{}", parent, name))
+            } else {
+                None
+            }
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        let config = Config::new();
+        let parse = |line: &str| Command::parse(line, &config);
+
+        assert_eq!(parse("vol 0.5"), Ok(Command::Volume(0.5)));
+        assert_eq!(parse("  reverb  "), Ok(Command::Reverb(None)));
+        assert_eq!(parse("cd"), Ok(Command::Cd("~".to_string())));
+        assert_eq!(
+            parse("play my file.wav"),
+            Ok(Command::Play("my file.wav".to_string()))
+        );
+        assert_eq!(
+            parse("export out.wav"),
+            Ok(Command::Export("out.wav".to_string(), false))
+        );
+        assert_eq!(
+            parse("export! out.wav"),
+            Ok(Command::Export("out.wav".to_string(), true))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let config = Config::new();
+        let parse = |line: &str| Command::parse(line, &config);
+
+        assert_eq!(parse("vol"), Err("vol: missing value".to_string()));
+        assert_eq!(
+            parse("vol loud"),
+            Err("vol: 'loud' is not a number".to_string())
+        );
+        assert_eq!(
+            parse("vol 5"),
+            Err("vol: 5 is out of range (0 - 2)".to_string())
+        );
+        assert_eq!(
+            parse("loop-end -1"),
+            Err("loop-end: '-1' is not a time in seconds".to_string())
+        );
+        assert_eq!(
+            parse("export!"),
+            Err("export!: missing argument".to_string())
+        );
+        assert_eq!(parse("dance"), Err("Unknown command: dance".to_string()));
+    }
+}
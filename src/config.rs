@@ -0,0 +1,500 @@
+use crate::audio_player::effects::{
+    Adjustable, BALANCE, EffectKind, EffectManager, LOWPASS, SPEED, STEREO_WIDTH, VOLUME,
+};
+use crate::keymap::Keymap;
+use crate::theme::{COLOR_NAMES, THEMES, Theme};
+use crate::utils;
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf, str::FromStr, time::Duration};
+
+const CONFIG_FILE: &str = "config.toml";
+
+// Settings from config.toml, with everything that isn't set (or isn't
+// valid) at the built-in default
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub volume: Adjustable,
+    pub speed: Adjustable,
+    pub lowpass: Adjustable,
+    pub balance: Adjustable,
+    pub stereo_width: Adjustable,
+    pub reverb: bool,
+    pub reverb_delay: f32,
+    pub theme: Theme,
+    // Themes from the config file, by name
+    pub themes: BTreeMap<String, Theme>,
+    pub start_dir: Option<PathBuf>,
+    // Output device by name, the system default if not set
+    pub audio_device: Option<String>,
+    pub waveform_points: usize,
+    pub poll_interval: Duration,
+    // Mouse capture gets in the way of selecting text in the terminal, so
+    // it can be turned off
+    pub mouse: bool,
+    pub keymap: Keymap,
+}
+
+// The file as written, every field optional
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    volume: RawAdjustable,
+    speed: RawAdjustable,
+    lowpass: RawAdjustable,
+    balance: RawAdjustable,
+    stereo_width: RawAdjustable,
+    reverb: RawReverb,
+    // Changes to the theme in use
+    colors: BTreeMap<String, String>,
+    // Custom themes, color name to color, plus the built-in theme they start
+    // from as `base`
+    themes: BTreeMap<String, BTreeMap<String, String>>,
+    files: RawFiles,
+    audio: RawAudio,
+    ui: RawUi,
+    // Key sequence to action name, per mode
+    keys: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAdjustable {
+    default: Option<f32>,
+    step: Option<f32>,
+    min: Option<f32>,
+    max: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawReverb {
+    enabled: Option<bool>,
+    delay: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawFiles {
+    start_dir: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAudio {
+    device: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawUi {
+    waveform_points: Option<usize>,
+    poll_ms: Option<u64>,
+    mouse: Option<bool>,
+    theme: Option<String>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self {
+            volume: VOLUME,
+            speed: SPEED,
+            lowpass: LOWPASS,
+            balance: BALANCE,
+            stereo_width: STEREO_WIDTH,
+            reverb: false,
+            reverb_delay: 0.06,
+            // No colors when NO_COLOR is set (https://no-color.org), unless
+            // the config picks a theme
+            theme: if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                Theme::monochrome()
+            } else {
+                Theme::dark()
+            },
+            themes: BTreeMap::new(),
+            start_dir: None,
+            audio_device: None,
+            waveform_points: 100,
+            poll_interval: Duration::from_millis(16),
+            mouse: true,
+            keymap: Keymap::new(),
+        }
+    }
+
+    // A custom theme, or a built-in one
+    pub fn theme_named(&self, name: &str) -> Option<Theme> {
+        self.themes
+            .get(name)
+            .copied()
+            .or_else(|| Theme::builtin(name))
+    }
+
+    pub fn theme_names(&self) -> Vec<&str> {
+        let mut names = THEMES.to_vec();
+        names.extend(
+            self.themes
+                .keys()
+                .map(String::as_str)
+                .filter(|name| !THEMES.contains(name)),
+        );
+        names
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        utils::config_file(CONFIG_FILE)
+    }
+
+    // Read the config file. A missing file is fine, anything wrong with it
+    // is returned as one error per problem, with the defaults used instead
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(path) = Self::path() else {
+            return (Self::new(), Vec::new());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Self::new(), Vec::new()),
+            Err(e) => (
+                Self::new(),
+                vec![format!("Could not read {}: {}", path.display(), e)],
+            ),
+        }
+    }
+
+    fn parse(contents: &str) -> (Self, Vec<String>) {
+        let mut config = Self::new();
+        let mut errors = Vec::new();
+
+        let raw: RawConfig = match toml::from_str(contents) {
+            Ok(raw) => raw,
+            Err(e) => {
+                // Nothing we can trust, so it's all defaults
+                errors.push(e.message().to_string() + &location(contents, e.span()));
+                return (config, errors);
+            }
+        };
+
+        // Hard limits of what the effects can do, config ranges must be inside
+        let settings = [
+            ("volume", &raw.volume, &mut config.volume, (0.0, 10.0)),
+            ("speed", &raw.speed, &mut config.speed, (0.05, 10.0)),
+            (
+                "lowpass",
+                &raw.lowpass,
+                &mut config.lowpass,
+                (20.0, 20000.0),
+            ),
+            ("balance", &raw.balance, &mut config.balance, (-1.0, 1.0)),
+            (
+                "stereo_width",
+                &raw.stereo_width,
+                &mut config.stereo_width,
+                (0.0, 4.0),
+            ),
+        ];
+        for (name, raw, target, limits) in settings {
+            match adjustable(raw, *target, limits) {
+                Ok(value) => *target = value,
+                Err(e) => errors.push(format!("{}: {}", name, e)),
+            }
+        }
+
+        if let Some(enabled) = raw.reverb.enabled {
+            config.reverb = enabled;
+        }
+        if let Some(delay) = raw.reverb.delay {
+            if (0.001..=2.0).contains(&delay) {
+                config.reverb_delay = delay;
+            } else {
+                errors.push(format!(
+                    "reverb.delay: {} is out of range (0.001 - 2.0 seconds)",
+                    delay
+                ));
+            }
+        }
+
+        // Custom themes start from a built-in one and change some colors
+        for (name, settings) in &raw.themes {
+            let base = settings.get("base").map_or(THEMES[0], String::as_str);
+            let Some(mut theme) = Theme::builtin(base) else {
+                errors.push(format!(
+                    "themes.{}.base: unknown theme '{}' (built-in: {})",
+                    name,
+                    base,
+                    THEMES.join(", ")
+                ));
+                continue;
+            };
+            let colors = settings.iter().filter(|(key, _)| *key != "base");
+            set_colors(&mut theme, colors, &format!("themes.{}", name), &mut errors);
+            config.themes.insert(name.clone(), theme);
+        }
+        if let Some(name) = &raw.ui.theme {
+            match config.theme_named(name) {
+                Some(theme) => config.theme = theme,
+                None => errors.push(format!(
+                    "ui.theme: unknown theme '{}' (known: {})",
+                    name,
+                    config.theme_names().join(", ")
+                )),
+            }
+        }
+        set_colors(&mut config.theme, &raw.colors, "colors", &mut errors);
+
+        if let Some(dir) = raw.files.start_dir {
+            let path = utils::expand_home(&dir);
+            if path.is_dir() {
+                config.start_dir = Some(path);
+            } else {
+                errors.push(format!(
+                    "files.start_dir: {} is not a directory",
+                    path.display()
+                ));
+            }
+        }
+
+        config.audio_device = raw.audio.device.filter(|name| !name.is_empty());
+
+        if let Some(points) = raw.ui.waveform_points {
+            if (10..=2000).contains(&points) {
+                config.waveform_points = points;
+            } else {
+                errors.push(format!(
+                    "ui.waveform_points: {} is out of range (10 - 2000)",
+                    points
+                ));
+            }
+        }
+        if let Some(poll_ms) = raw.ui.poll_ms {
+            if (1..=1000).contains(&poll_ms) {
+                config.poll_interval = Duration::from_millis(poll_ms);
+            } else {
+                errors.push(format!(
+                    "ui.poll_ms: {} is out of range (1 - 1000)",
+                    poll_ms
+                ));
+            }
+        }
+        if let Some(mouse) = raw.ui.mouse {
+            config.mouse = mouse;
+        }
+
+        for (section, bindings) in &raw.keys {
+            for (keys, action) in bindings {
+                if let Err(e) = config.keymap.bind_from_config(section, keys, action) {
+                    errors.push(format!("keys.{}: \"{}\": {}", section, keys, e));
+                }
+            }
+        }
+
+        (config, errors)
+    }
+
+    // The effect settings to start with
+    pub fn effects(&self) -> EffectManager {
+        let mut effects = EffectManager::new();
+        effects.volume = self.volume.default;
+        effects.playback_speed = self.speed.default;
+        effects.lowpass_cutoff = self.lowpass.default.round() as u32;
+        effects.balance = self.balance.default;
+        effects.stereo_width = self.stereo_width.default;
+        effects.reverb_delay = self.reverb_delay;
+        if self.reverb {
+            effects.toggle_effect(EffectKind::Reverb);
+        }
+        effects
+    }
+
+    // Keep settings from a preset within the ranges
+    pub fn clamp_effects(&self, effects: &mut EffectManager) {
+        effects.clamp();
+        effects.volume = self.volume.clamp(effects.volume);
+        effects.playback_speed = self.speed.clamp(effects.playback_speed);
+        effects.lowpass_cutoff = self.lowpass.clamp(effects.lowpass_cutoff as f32).round() as u32;
+        effects.balance = self.balance.clamp(effects.balance);
+        effects.stereo_width = self.stereo_width.clamp(effects.stereo_width);
+    }
+}
+
+// Apply what's set on top of the built-in values, and check the result
+fn adjustable(
+    raw: &RawAdjustable,
+    built_in: Adjustable,
+    (lowest, highest): (f32, f32),
+) -> Result<Adjustable, String> {
+    let value = Adjustable::new(
+        raw.default.unwrap_or(built_in.default),
+        raw.step.unwrap_or(built_in.step),
+        raw.min.unwrap_or(built_in.min),
+        raw.max.unwrap_or(built_in.max),
+    );
+
+    if value.step <= 0.0 {
+        Err(format!("step must be above 0, not {}", value.step))
+    } else if value.min >= value.max {
+        Err(format!(
+            "min ({}) must be below max ({})",
+            value.min, value.max
+        ))
+    } else if value.min < lowest || value.max > highest {
+        Err(format!(
+            "range {} - {} is outside what's possible ({} - {})",
+            value.min, value.max, lowest, highest
+        ))
+    } else if !(value.min..=value.max).contains(&value.default) {
+        Err(format!(
+            "default {} is outside the range {} - {}",
+            value.default, value.min, value.max
+        ))
+    } else {
+        Ok(value)
+    }
+}
+
+// " (line N)" for a byte range of the file
+fn location(contents: &str, span: Option<std::ops::Range<usize>>) -> String {
+    match span {
+        Some(span) => {
+            let line = contents[..span.start.min(contents.len())]
+                .matches('\n')
+                .count()
+                + 1;
+            format!(" (line {})", line)
+        }
+        None => String::new(),
+    }
+}
+
+// Set the colors of a theme from their names, `section` is where they're
+// from for the errors
+fn set_colors<'a>(
+    theme: &mut Theme,
+    colors: impl IntoIterator<Item = (&'a String, &'a String)>,
+    section: &str,
+    errors: &mut Vec<String>,
+) {
+    for (name, value) in colors {
+        match (theme.get_mut(name), Color::from_str(value)) {
+            (Some(color), Ok(parsed)) => *color = parsed,
+            (Some(_), Err(_)) => errors.push(format!(
+                "{}.{}: '{}' is not a color (try a name like \"light-blue\" or \"#ff8800\")",
+                section, name, value
+            )),
+            (None, _) => errors.push(format!(
+                "{}.{}: unknown color setting (known: {})",
+                section,
+                name,
+                COLOR_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_settings_on_top_of_the_defaults() {
+        let (config, errors) = Config::parse(
+            r#"
+            [volume]
+            default = 0.5
+            max = 3.0
+
+            [reverb]
+            enabled = true
+            delay = 0.1
+
+            [ui]
+            poll_ms = 30
+            mouse = false
+            "#,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(config.volume.default, 0.5);
+        assert_eq!(config.volume.max, 3.0);
+        assert_eq!(config.volume.step, VOLUME.step);
+        assert_eq!(config.poll_interval, Duration::from_millis(30));
+        assert!(!config.mouse);
+
+        let effects = config.effects();
+        assert_eq!(effects.volume, 0.5);
+        assert_eq!(effects.reverb_delay, 0.1);
+        assert!(effects.is_active(EffectKind::Reverb));
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_the_line() {
+        let (config, errors) = Config::parse("[volume]\ndefault = 0.5\nstep = \n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].ends_with("(line 3)"), "{}", errors[0]);
+        // Nothing is taken from a file that doesn't parse
+        assert_eq!(config.volume.default, VOLUME.default);
+
+        let (_, errors) = Config::parse("[volume]\nloudness = 2.0\n");
+        assert!(errors[0].contains("loudness"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn keeps_the_default_for_invalid_values() {
+        let (config, errors) = Config::parse(
+            r#"
+            volume = { step = 0.0 }
+            speed = { min = 2.0, max = 1.0 }
+            lowpass = { max = 50000.0 }
+            balance = { default = 2.0 }
+            reverb = { delay = 5.0 }
+            ui = { waveform_points = 5, poll_ms = 0 }
+            files = { start_dir = "/no/such/directory" }
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "volume: step must be above 0, not 0",
+                "speed: min (2) must be below max (1)",
+                "lowpass: range 500 - 50000 is outside what's possible (20 - 20000)",
+                "balance: default 2 is outside the range -1 - 1",
+                "reverb.delay: 5 is out of range (0.001 - 2.0 seconds)",
+                "files.start_dir: /no/such/directory is not a directory",
+                "ui.waveform_points: 5 is out of range (10 - 2000)",
+                "ui.poll_ms: 0 is out of range (1 - 1000)",
+            ]
+        );
+        assert_eq!(config.volume, VOLUME);
+        assert_eq!(config.speed, SPEED);
+        assert_eq!(config.lowpass, LOWPASS);
+        assert_eq!(config.balance, BALANCE);
+        assert_eq!(config.reverb_delay, 0.06);
+        assert_eq!(config.start_dir, None);
+        assert_eq!(config.waveform_points, 100);
+    }
+
+    #[test]
+    fn clamps_presets_to_the_ranges() {
+        let (config, _) = Config::parse("[volume]\nmax = 1.5\n");
+        let mut effects = EffectManager::new();
+        effects.volume = 50.0;
+        effects.playback_speed = 0.0;
+        effects.lowpass_cutoff = 0;
+        effects.balance = f32::NAN;
+        effects.distortion.bits = 0;
+        config.clamp_effects(&mut effects);
+
+        assert_eq!(effects.volume, 1.5);
+        assert_eq!(effects.playback_speed, SPEED.min);
+        assert_eq!(effects.lowpass_cutoff, LOWPASS.min as u32);
+        assert_eq!(effects.balance, BALANCE.min);
+        assert_eq!(effects.distortion.bits, 1);
+    }
+
+    #[test]
+    fn ignores_an_empty_device_name() {
+        let (config, _) = Config::parse("[audio]\ndevice = \"\"\n");
+        assert_eq!(config.audio_device, None);
+        let (config, _) = Config::parse("[audio]\ndevice = \"USB Audio\"\n");
+        assert_eq!(config.audio_device.as_deref(), Some("USB Audio"));
+    }
+}
@@ -11,9 +11,11 @@ pub struct FileManager {
 }
 
 impl FileManager {
-    pub fn new() -> Self {
-        // Start with the current directory
-        let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    pub fn new(start_dir: Option<PathBuf>) -> Self {
+        // Start with the configured directory, or the current one
+        let current_dir = start_dir
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("."));
 
         let mut manager = Self {
             current_dir,
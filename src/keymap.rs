@@ -0,0 +1,903 @@
+use crate::app::AppMode;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+// Everything a key can be bound to. Modes decide what the generic ones
+// (next, increase, toggle, ...) do to whatever they show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    // Work in every mode
+    Quit,
+    Back,
+    SwitchAb,
+    Undo,
+    Redo,
+    CommandLine,
+    // Normal mode
+    Play,
+    Loop,
+    Stop,
+    ToggleReverb,
+    NextPreset,
+    PrevPreset,
+    CopyAb,
+    ToggleLoudnessMatch,
+    Open(AppMode),
+    // Volume, pitch and filter modes
+    VolumeUp,
+    VolumeDown,
+    SpeedUp,
+    SpeedDown,
+    LowpassUp,
+    LowpassDown,
+    // Stereo mode
+    BalanceLeft,
+    BalanceRight,
+    CenterBalance,
+    WidthUp,
+    WidthDown,
+    ToggleMono,
+    SwapChannels,
+    // Lists and parameters
+    Next,
+    Prev,
+    Increase,
+    Decrease,
+    NextField,
+    PrevField,
+    Toggle,
+    Select,
+    Parent,
+    Add,
+    Remove,
+    Rename,
+    MoveUp,
+    MoveDown,
+    Confirm,
+    // Loop mode
+    SnapToZero,
+    ClearLoop,
+    Audition,
+    // Edit mode
+    Preview,
+    SaveCopy,
+    Overwrite,
+    // Record mode
+    CycleInput,
+    Arm,
+    Record,
+    // Mixer mode
+    GainUp,
+    GainDown,
+    Mute,
+    Solo,
+    // Message log
+    ClearMessages,
+    // Vim motions
+    Top,
+    Bottom,
+    HalfPageDown,
+    HalfPageUp,
+    Repeat,
+    // Does nothing, to unbind a key from the config
+    Nop,
+}
+
+// Name (as used in the config file) and description of every action
+const ACTIONS: &[(&str, Action, &str)] = &[
+    ("quit", Action::Quit, "Quit"),
+    ("back", Action::Back, "Back to normal mode"),
+    ("switch-ab", Action::SwitchAb, "Switch A/B"),
+    ("undo", Action::Undo, "Undo"),
+    ("redo", Action::Redo, "Redo"),
+    ("command-line", Action::CommandLine, "Command line"),
+    ("play", Action::Play, "Play"),
+    ("loop", Action::Loop, "Loop"),
+    ("stop", Action::Stop, "Stop"),
+    ("toggle-reverb", Action::ToggleReverb, "Toggle reverb"),
+    ("next-preset", Action::NextPreset, "Next preset"),
+    ("prev-preset", Action::PrevPreset, "Previous preset"),
+    ("copy-ab", Action::CopyAb, "Copy A/B slot"),
+    (
+        "toggle-loudness-match",
+        Action::ToggleLoudnessMatch,
+        "A/B loudness matching",
+    ),
+    ("open-volume", Action::Open(AppMode::Volume), "Volume mode"),
+    ("open-pitch", Action::Open(AppMode::Pitch), "Pitch mode"),
+    ("open-filter", Action::Open(AppMode::Filter), "Filter mode"),
+    ("open-stereo", Action::Open(AppMode::Stereo), "Stereo mode"),
+    (
+        "open-dynamics",
+        Action::Open(AppMode::Dynamics),
+        "Dynamics mode",
+    ),
+    ("open-delay", Action::Open(AppMode::Delay), "Delay mode"),
+    (
+        "open-modulation",
+        Action::Open(AppMode::Modulation),
+        "Modulation mode",
+    ),
+    (
+        "open-distortion",
+        Action::Open(AppMode::Distortion),
+        "Distortion mode",
+    ),
+    (
+        "open-synth-filter",
+        Action::Open(AppMode::SynthFilter),
+        "Synth filter mode",
+    ),
+    (
+        "open-envelope",
+        Action::Open(AppMode::Envelope),
+        "Amp envelope mode",
+    ),
+    ("open-presets", Action::Open(AppMode::Presets), "Presets"),
+    (
+        "open-browser",
+        Action::Open(AppMode::FileBrowser),
+        "File browser",
+    ),
+    ("open-loop", Action::Open(AppMode::Loop), "Loop mode"),
+    ("open-edit", Action::Open(AppMode::Edit), "Edit mode"),
+    ("open-record", Action::Open(AppMode::Record), "Record mode"),
+    ("open-chain", Action::Open(AppMode::Chain), "Effect chain"),
+    ("open-mixer", Action::Open(AppMode::Mixer), "Mixer"),
+    (
+        "open-messages",
+        Action::Open(AppMode::Messages),
+        "Message log",
+    ),
+    ("volume-up", Action::VolumeUp, "Volume up"),
+    ("volume-down", Action::VolumeDown, "Volume down"),
+    ("speed-up", Action::SpeedUp, "Speed up"),
+    ("speed-down", Action::SpeedDown, "Speed down"),
+    ("lowpass-up", Action::LowpassUp, "Raise the cutoff"),
+    ("lowpass-down", Action::LowpassDown, "Lower the cutoff"),
+    ("balance-left", Action::BalanceLeft, "Balance left"),
+    ("balance-right", Action::BalanceRight, "Balance right"),
+    (
+        "center-balance",
+        Action::CenterBalance,
+        "Center the balance",
+    ),
+    ("width-up", Action::WidthUp, "Wider"),
+    ("width-down", Action::WidthDown, "Narrower"),
+    ("toggle-mono", Action::ToggleMono, "Mono"),
+    ("swap-channels", Action::SwapChannels, "Swap L/R"),
+    ("next", Action::Next, "Next"),
+    ("prev", Action::Prev, "Previous"),
+    ("increase", Action::Increase, "Increase"),
+    ("decrease", Action::Decrease, "Decrease"),
+    ("next-field", Action::NextField, "Next field"),
+    ("prev-field", Action::PrevField, "Previous field"),
+    ("toggle", Action::Toggle, "On/off"),
+    ("select", Action::Select, "Select"),
+    ("parent", Action::Parent, "Parent directory"),
+    ("add", Action::Add, "Add"),
+    ("remove", Action::Remove, "Remove"),
+    ("rename", Action::Rename, "Rename"),
+    ("move-up", Action::MoveUp, "Move up"),
+    ("move-down", Action::MoveDown, "Move down"),
+    ("confirm", Action::Confirm, "Yes"),
+    ("snap-to-zero", Action::SnapToZero, "Snap to zero crossing"),
+    ("clear-loop", Action::ClearLoop, "Clear the loop"),
+    ("audition", Action::Audition, "Audition"),
+    ("preview", Action::Preview, "Preview"),
+    ("save-copy", Action::SaveCopy, "Save as new file"),
+    ("overwrite", Action::Overwrite, "Overwrite the original"),
+    ("cycle-input", Action::CycleInput, "Next input"),
+    ("arm", Action::Arm, "Arm/disarm"),
+    ("record", Action::Record, "Record"),
+    ("gain-up", Action::GainUp, "Gain up"),
+    ("gain-down", Action::GainDown, "Gain down"),
+    ("mute", Action::Mute, "Mute"),
+    ("solo", Action::Solo, "Solo"),
+    ("top", Action::Top, "First (or max)"),
+    ("bottom", Action::Bottom, "Last (or min)"),
+    ("half-page-down", Action::HalfPageDown, "Half a page down"),
+    ("half-page-up", Action::HalfPageUp, "Half a page up"),
+    ("repeat", Action::Repeat, "Repeat the last action"),
+    ("nop", Action::Nop, "Nothing"),
+];
+
+impl Action {
+    pub fn parse(name: &str) -> Option<Self> {
+        ACTIONS
+            .iter()
+            .find(|(action_name, _, _)| *action_name == name)
+            .map(|(_, action, _)| *action)
+    }
+
+    pub fn description(&self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(_, action, _)| action == self)
+            .map(|(_, _, description)| *description)
+            .unwrap_or("")
+    }
+
+    // A count typed first (5j) runs these that many times
+    pub fn takes_count(&self) -> bool {
+        matches!(
+            self,
+            Action::Next
+                | Action::Prev
+                | Action::Increase
+                | Action::Decrease
+                | Action::NextField
+                | Action::PrevField
+                | Action::MoveUp
+                | Action::MoveDown
+                | Action::VolumeUp
+                | Action::VolumeDown
+                | Action::SpeedUp
+                | Action::SpeedDown
+                | Action::LowpassUp
+                | Action::LowpassDown
+                | Action::BalanceLeft
+                | Action::BalanceRight
+                | Action::WidthUp
+                | Action::WidthDown
+                | Action::GainUp
+                | Action::GainDown
+                | Action::NextPreset
+                | Action::PrevPreset
+                | Action::HalfPageDown
+                | Action::HalfPageUp
+        )
+    }
+
+    // What `.` can repeat: anything but getting around the app itself
+    pub fn is_repeatable(&self) -> bool {
+        !matches!(
+            self,
+            Action::Quit
+                | Action::Back
+                | Action::SwitchAb
+                | Action::Undo
+                | Action::Redo
+                | Action::CommandLine
+                | Action::Open(_)
+                | Action::Repeat
+                | Action::Nop
+        )
+    }
+}
+
+// Modes by the name used for their section in the config file
+const MODE_NAMES: &[(&str, AppMode)] = &[
+    ("normal", AppMode::Normal),
+    ("volume", AppMode::Volume),
+    ("pitch", AppMode::Pitch),
+    ("filter", AppMode::Filter),
+    ("browser", AppMode::FileBrowser),
+    ("loop", AppMode::Loop),
+    ("edit", AppMode::Edit),
+    ("record", AppMode::Record),
+    ("chain", AppMode::Chain),
+    ("mixer", AppMode::Mixer),
+    ("stereo", AppMode::Stereo),
+    ("dynamics", AppMode::Dynamics),
+    ("delay", AppMode::Delay),
+    ("modulation", AppMode::Modulation),
+    ("distortion", AppMode::Distortion),
+    ("synth-filter", AppMode::SynthFilter),
+    ("envelope", AppMode::Envelope),
+    ("presets", AppMode::Presets),
+    ("messages", AppMode::Messages),
+];
+
+// One key press. Shift is part of the character for printable keys, so it
+// only counts as a modifier for the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self {
+            code,
+            modifiers: modifiers
+                & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT),
+        }
+    }
+
+    pub fn from_event(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+
+    // Vim notation, the way bindings are written in the config
+    pub fn name(&self) -> String {
+        let key = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char('<') => "lt".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::BackTab => "S-Tab".to_string(),
+            KeyCode::Backspace => "BS".to_string(),
+            KeyCode::Up => "Up".to_string(),
+            KeyCode::Down => "Down".to_string(),
+            KeyCode::Left => "Left".to_string(),
+            KeyCode::Right => "Right".to_string(),
+            KeyCode::PageUp => "PageUp".to_string(),
+            KeyCode::PageDown => "PageDown".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            KeyCode::Delete => "Del".to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            _ => "?".to_string(),
+        };
+
+        let mut prefix = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            prefix.push_str("C-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            prefix.push_str("A-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            prefix.push_str("S-");
+        }
+
+        let plain = matches!(self.code, KeyCode::Char(c) if c != ' ' && c != '<');
+        if plain && prefix.is_empty() {
+            key
+        } else {
+            format!("<{}{}>", prefix, key)
+        }
+    }
+}
+
+pub fn sequence_name(keys: &[KeyChord]) -> String {
+    keys.iter().map(KeyChord::name).collect()
+}
+
+// Parse a key sequence in Vim notation: plain characters, and named or
+// modified keys in angle brackets, like "<Space>v", "<C-r>" or "<S-Tab>"
+pub fn parse_keys(text: &str) -> Result<Vec<KeyChord>, String> {
+    let mut keys = Vec::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE));
+            continue;
+        }
+
+        let mut name = String::new();
+        loop {
+            match chars.next() {
+                Some('>') => break,
+                Some(c) => name.push(c),
+                None => return Err(format!("missing '>' in \"{}\"", text)),
+            }
+        }
+        keys.push(parse_named(&name)?);
+    }
+
+    if keys.is_empty() {
+        Err("empty key sequence".to_string())
+    } else {
+        Ok(keys)
+    }
+}
+
+fn parse_named(name: &str) -> Result<KeyChord, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    // A modifier is a single letter and a dash, the key itself may be "-"
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        match rest.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => modifiers |= KeyModifiers::CONTROL,
+            b'A' | b'M' => modifiers |= KeyModifiers::ALT,
+            b'S' => modifiers |= KeyModifiers::SHIFT,
+            _ => return Err(format!("unknown modifier in <{}>", name)),
+        }
+        rest = &rest[2..];
+    }
+
+    let code = match rest.to_ascii_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "enter" | "cr" | "return" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+        "tab" => KeyCode::Tab,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "del" | "delete" => KeyCode::Delete,
+        key if key.len() > 1
+            && key.starts_with('f')
+            && let Ok(n) = key[1..].parse::<u8>() =>
+        {
+            KeyCode::F(n)
+        }
+        _ => {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(format!("unknown key <{}>", name)),
+            }
+        }
+    };
+
+    Ok(KeyChord::new(code, modifiers))
+}
+
+// What a sequence of keys means in a mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lookup {
+    // Bound to exactly these keys
+    pub action: Option<Action>,
+    // Longer sequences start with these keys
+    pub more: bool,
+}
+
+// Key sequences to actions, per mode. Bindings of the current mode come
+// before the global ones
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    global: HashMap<Vec<KeyChord>, Action>,
+    modes: HashMap<AppMode, HashMap<Vec<KeyChord>, Action>>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        let mut keymap = Self {
+            global: HashMap::new(),
+            modes: HashMap::new(),
+        };
+
+        for (keys, action) in [
+            ("q", Action::Back),
+            ("<Esc>", Action::Back),
+            ("`", Action::SwitchAb),
+            ("u", Action::Undo),
+            ("<C-r>", Action::Redo),
+            (":", Action::CommandLine),
+            (".", Action::Repeat),
+        ] {
+            keymap.bind(None, keys, action);
+        }
+
+        // Up/down and left/right do what j/k and h/l do in most modes
+        let lists = [
+            ("j", Action::Next),
+            ("<Down>", Action::Next),
+            ("k", Action::Prev),
+            ("<Up>", Action::Prev),
+            ("h", Action::Decrease),
+            ("<Left>", Action::Decrease),
+            ("l", Action::Increase),
+            ("<Right>", Action::Increase),
+        ];
+        let toggle = [("b", Action::Toggle), ("<Enter>", Action::Toggle)];
+        let motions = [
+            ("gg", Action::Top),
+            ("G", Action::Bottom),
+            ("<C-d>", Action::HalfPageDown),
+            ("<C-u>", Action::HalfPageUp),
+        ];
+
+        let modes: [(AppMode, &[(&str, Action)]); 19] = [
+            (
+                AppMode::Normal,
+                &[
+                    ("q", Action::Quit),
+                    ("p", Action::Play),
+                    ("r", Action::Loop),
+                    ("e", Action::ToggleReverb),
+                    ("]", Action::NextPreset),
+                    ("[", Action::PrevPreset),
+                    ("B", Action::CopyAb),
+                    ("M", Action::ToggleLoudnessMatch),
+                    ("<Space>v", Action::Open(AppMode::Volume)),
+                    ("<Space>c", Action::Open(AppMode::Pitch)),
+                    ("<Space>g", Action::Open(AppMode::Filter)),
+                    ("<Space>w", Action::Open(AppMode::Stereo)),
+                    ("<Space>d", Action::Open(AppMode::Dynamics)),
+                    ("<Space>y", Action::Open(AppMode::Delay)),
+                    ("<Space>o", Action::Open(AppMode::Modulation)),
+                    ("<Space>z", Action::Open(AppMode::Distortion)),
+                    ("<Space>n", Action::Open(AppMode::SynthFilter)),
+                    ("<Space>a", Action::Open(AppMode::Envelope)),
+                    ("<Space>P", Action::Open(AppMode::Presets)),
+                    ("<Space>f", Action::Open(AppMode::FileBrowser)),
+                    ("<Space>l", Action::Open(AppMode::Loop)),
+                    ("<Space>t", Action::Open(AppMode::Edit)),
+                    ("<Space>m", Action::Open(AppMode::Record)),
+                    ("<Space>e", Action::Open(AppMode::Chain)),
+                    ("<Space>x", Action::Open(AppMode::Mixer)),
+                    ("<Space>M", Action::Open(AppMode::Messages)),
+                ],
+            ),
+            (
+                AppMode::Volume,
+                &[
+                    ("j", Action::VolumeDown),
+                    ("<Down>", Action::VolumeDown),
+                    ("k", Action::VolumeUp),
+                    ("<Up>", Action::VolumeUp),
+                ],
+            ),
+            (
+                AppMode::Pitch,
+                &[
+                    ("j", Action::SpeedDown),
+                    ("<Down>", Action::SpeedDown),
+                    ("k", Action::SpeedUp),
+                    ("<Up>", Action::SpeedUp),
+                ],
+            ),
+            (
+                AppMode::Filter,
+                &[
+                    ("j", Action::LowpassDown),
+                    ("<Down>", Action::LowpassDown),
+                    ("k", Action::LowpassUp),
+                    ("<Up>", Action::LowpassUp),
+                ],
+            ),
+            (
+                AppMode::Stereo,
+                &[
+                    ("h", Action::BalanceLeft),
+                    ("<Left>", Action::BalanceLeft),
+                    ("l", Action::BalanceRight),
+                    ("<Right>", Action::BalanceRight),
+                    ("j", Action::WidthDown),
+                    ("<Down>", Action::WidthDown),
+                    ("k", Action::WidthUp),
+                    ("<Up>", Action::WidthUp),
+                    ("c", Action::CenterBalance),
+                    ("m", Action::ToggleMono),
+                    ("s", Action::SwapChannels),
+                ],
+            ),
+            (
+                AppMode::FileBrowser,
+                &[
+                    ("h", Action::Parent),
+                    ("<Left>", Action::Parent),
+                    ("l", Action::Select),
+                    ("<Right>", Action::Select),
+                    ("<Enter>", Action::Select),
+                    ("p", Action::Play),
+                    ("r", Action::Loop),
+                ],
+            ),
+            (
+                AppMode::Loop,
+                &[
+                    ("<Tab>", Action::NextField),
+                    ("j", Action::MoveDown),
+                    ("<Down>", Action::MoveDown),
+                    ("k", Action::MoveUp),
+                    ("<Up>", Action::MoveUp),
+                    ("z", Action::SnapToZero),
+                    ("c", Action::ClearLoop),
+                    ("r", Action::Audition),
+                    ("s", Action::Stop),
+                ],
+            ),
+            (
+                AppMode::Edit,
+                &[
+                    ("p", Action::Preview),
+                    ("<Enter>", Action::Preview),
+                    ("s", Action::Stop),
+                    ("w", Action::SaveCopy),
+                    ("W", Action::Overwrite),
+                    ("y", Action::Confirm),
+                ],
+            ),
+            (
+                AppMode::Record,
+                &[
+                    ("d", Action::CycleInput),
+                    ("a", Action::Arm),
+                    ("r", Action::Record),
+                    ("s", Action::Stop),
+                ],
+            ),
+            (
+                AppMode::Chain,
+                &[
+                    ("J", Action::MoveDown),
+                    ("K", Action::MoveUp),
+                    ("a", Action::Add),
+                    ("d", Action::Remove),
+                    ("x", Action::Remove),
+                ],
+            ),
+            (
+                AppMode::Mixer,
+                &[
+                    ("K", Action::GainUp),
+                    ("+", Action::GainUp),
+                    ("=", Action::GainUp),
+                    ("J", Action::GainDown),
+                    ("-", Action::GainDown),
+                    ("m", Action::Mute),
+                    ("s", Action::Solo),
+                    ("x", Action::Remove),
+                    ("d", Action::Remove),
+                ],
+            ),
+            (AppMode::Dynamics, &[]),
+            (AppMode::Delay, &[]),
+            (
+                AppMode::Modulation,
+                &[("<Tab>", Action::NextField), ("<S-Tab>", Action::PrevField)],
+            ),
+            (AppMode::Distortion, &[]),
+            (AppMode::SynthFilter, &[]),
+            (AppMode::Envelope, &[("s", Action::Stop)]),
+            (
+                AppMode::Presets,
+                &[
+                    ("l", Action::Select),
+                    ("<Right>", Action::Select),
+                    ("<Enter>", Action::Select),
+                    ("a", Action::Add),
+                    ("r", Action::Rename),
+                    ("d", Action::Remove),
+                    ("x", Action::Remove),
+                    ("]", Action::NextPreset),
+                    ("[", Action::PrevPreset),
+                    ("y", Action::Confirm),
+                ],
+            ),
+            (AppMode::Messages, &[("c", Action::ClearMessages)]),
+        ];
+
+        for (mode, bindings) in modes {
+            // Shared list keys first, so the mode's own can replace them
+            if !matches!(
+                mode,
+                AppMode::Normal | AppMode::Volume | AppMode::Pitch | AppMode::Filter
+            ) {
+                for (keys, action) in lists {
+                    keymap.bind(Some(mode), keys, action);
+                }
+            }
+            if matches!(
+                mode,
+                AppMode::Chain
+                    | AppMode::Delay
+                    | AppMode::Modulation
+                    | AppMode::Distortion
+                    | AppMode::SynthFilter
+            ) {
+                for (keys, action) in toggle {
+                    keymap.bind(Some(mode), keys, action);
+                }
+            }
+            if !matches!(mode, AppMode::Normal | AppMode::Loop | AppMode::Record) {
+                for (keys, action) in motions {
+                    keymap.bind(Some(mode), keys, action);
+                }
+            }
+            for (keys, action) in bindings {
+                keymap.bind(Some(mode), keys, *action);
+            }
+        }
+
+        keymap
+    }
+
+    fn bind(&mut self, mode: Option<AppMode>, keys: &str, action: Action) {
+        if let Ok(keys) = parse_keys(keys) {
+            let bindings = match mode {
+                Some(mode) => self.modes.entry(mode).or_default(),
+                None => &mut self.global,
+            };
+            bindings.insert(keys, action);
+        }
+    }
+
+    // Apply a binding from the config, `section` being a mode name or
+    // "global"
+    pub fn bind_from_config(
+        &mut self,
+        section: &str,
+        keys: &str,
+        action: &str,
+    ) -> Result<(), String> {
+        let mode = if section == "global" {
+            None
+        } else {
+            match MODE_NAMES.iter().find(|(name, _)| *name == section) {
+                Some((_, mode)) => Some(*mode),
+                None => return Err(format!("unknown mode \"{}\"", section)),
+            }
+        };
+        let keys = parse_keys(keys)?;
+        let action =
+            Action::parse(action).ok_or_else(|| format!("unknown action \"{}\"", action))?;
+
+        let bindings = match mode {
+            Some(mode) => self.modes.entry(mode).or_default(),
+            None => &mut self.global,
+        };
+        bindings.insert(keys, action);
+        Ok(())
+    }
+
+    pub fn lookup(&self, mode: AppMode, keys: &[KeyChord]) -> Lookup {
+        let mode_bindings = self.modes.get(&mode);
+        let action = mode_bindings
+            .and_then(|bindings| bindings.get(keys))
+            .or_else(|| self.global.get(keys))
+            .copied();
+
+        let longer = |bindings: &HashMap<Vec<KeyChord>, Action>| {
+            bindings
+                .keys()
+                .any(|bound| bound.len() > keys.len() && bound.starts_with(keys))
+        };
+        let more = mode_bindings.is_some_and(longer) || longer(&self.global);
+
+        Lookup { action, more }
+    }
+
+    // Bindings of a mode that continue the given keys, for the help popup,
+    // as (the rest of the keys, action)
+    pub fn continuations(&self, mode: AppMode, keys: &[KeyChord]) -> Vec<(String, Action)> {
+        let mut found: Vec<(String, Action)> = self
+            .modes
+            .get(&mode)
+            .into_iter()
+            .flatten()
+            .chain(self.global.iter())
+            .filter(|(bound, action)| {
+                bound.len() > keys.len() && bound.starts_with(keys) && **action != Action::Nop
+            })
+            .map(|(bound, action)| (sequence_name(&bound[keys.len()..]), *action))
+            .collect();
+
+        found.sort_by(|a, b| a.1.description().cmp(b.1.description()).then(a.0.cmp(&b.0)));
+        found.dedup_by(|a, b| a.0 == b.0);
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord::new(code, modifiers)
+    }
+
+    fn key(c: char) -> KeyChord {
+        chord(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn parses_vim_notation() {
+        assert_eq!(parse_keys("gg"), Ok(vec![key('g'), key('g')]));
+        assert_eq!(parse_keys("<Space>v"), Ok(vec![key(' '), key('v')]));
+        assert_eq!(
+            parse_keys("<C-r><a-Left><lt>"),
+            Ok(vec![
+                chord(KeyCode::Char('r'), KeyModifiers::CONTROL),
+                chord(KeyCode::Left, KeyModifiers::ALT),
+                key('<'),
+            ])
+        );
+        assert_eq!(
+            parse_keys("<S-Tab>"),
+            Ok(vec![chord(KeyCode::BackTab, KeyModifiers::NONE)])
+        );
+        assert_eq!(
+            parse_keys("<F12><C-->"),
+            Ok(vec![
+                chord(KeyCode::F(12), KeyModifiers::NONE),
+                chord(KeyCode::Char('-'), KeyModifiers::CONTROL),
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_sequences() {
+        assert_eq!(parse_keys(""), Err("empty key sequence".to_string()));
+        assert_eq!(
+            parse_keys("<C-r"),
+            Err("missing '>' in \"<C-r\"".to_string())
+        );
+        assert_eq!(
+            parse_keys("<Spcae>"),
+            Err("unknown key <Spcae>".to_string())
+        );
+        assert_eq!(
+            parse_keys("<X-a>"),
+            Err("unknown modifier in <X-a>".to_string())
+        );
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for text in [
+            "gg", "<Space>v", "<C-r>", "<A-S-Up>", "<lt>", "<S-Tab>", "<F5>",
+        ] {
+            let keys = parse_keys(text).unwrap();
+            assert_eq!(sequence_name(&keys), text);
+        }
+        // Shift is part of the character for printable keys
+        assert_eq!(chord(KeyCode::Char('G'), KeyModifiers::SHIFT).name(), "G");
+    }
+
+    #[test]
+    fn looks_up_mode_bindings_before_global_ones() {
+        let keymap = Keymap::new();
+        let q = [key('q')];
+        assert_eq!(
+            keymap.lookup(AppMode::Normal, &q).action,
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            keymap.lookup(AppMode::Volume, &q).action,
+            Some(Action::Back)
+        );
+
+        // A prefix has no action of its own, but more to come
+        let space = parse_keys("<Space>").unwrap();
+        let lookup = keymap.lookup(AppMode::Normal, &space);
+        assert_eq!(
+            lookup,
+            Lookup {
+                action: None,
+                more: true
+            }
+        );
+        assert!(
+            keymap
+                .continuations(AppMode::Normal, &space)
+                .contains(&("v".to_string(), Action::Open(AppMode::Volume)))
+        );
+    }
+
+    #[test]
+    fn binds_from_the_config() {
+        let mut keymap = Keymap::new();
+        keymap.bind_from_config("normal", "P", "play").unwrap();
+        keymap.bind_from_config("global", "<C-q>", "quit").unwrap();
+        assert_eq!(
+            keymap.lookup(AppMode::Normal, &[key('P')]).action,
+            Some(Action::Play)
+        );
+        let ctrl_q = [chord(KeyCode::Char('q'), KeyModifiers::CONTROL)];
+        assert_eq!(
+            keymap.lookup(AppMode::Volume, &ctrl_q).action,
+            Some(Action::Quit)
+        );
+
+        assert_eq!(
+            keymap.bind_from_config("nowhere", "x", "play"),
+            Err("unknown mode \"nowhere\"".to_string())
+        );
+        assert_eq!(
+            keymap.bind_from_config("normal", "x", "dance"),
+            Err("unknown action \"dance\"".to_string())
+        );
+        assert!(keymap.bind_from_config("normal", "<Nope>", "play").is_err());
+    }
+}
@@ -1,21 +1,26 @@
 mod app;
 mod audio_player;
+mod command;
+mod config;
 mod file_manager;
+mod keymap;
+mod theme;
 mod ui;
 mod utils;
 
 use app::App;
+use config::Config;
 use crossterm::{
-    event::{self, Event},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use ratatui::{Terminal, backend::CrosstermBackend};
-use rodio::OutputStream;
-use std::{
-    io::{self, stdout},
-    time::Duration,
+use ratatui::{Terminal, backend::CrosstermBackend, layout::Rect};
+use rodio::{
+    OutputStream, OutputStreamHandle,
+    cpal::traits::{DeviceTrait, HostTrait},
 };
+use std::io::{self, stdout};
 
 fn main() -> io::Result<()> {
     // Setup terminal
@@ -25,11 +30,17 @@ fn main() -> io::Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Set up audio - but continue even if it fails
-    let stream_handle = match OutputStream::try_default() {
-        Ok((_stream, handle)) => {
-            // Keep stream alive by storing it in a tuple
-            Some(handle)
+    let (config, mut config_errors) = Config::load();
+    if config.mouse {
+        execute!(terminal.backend_mut(), EnableMouseCapture)?;
+    }
+
+    // Set up audio - but continue even if it fails. The stream has to stay
+    // alive for as long as we play anything
+    let (_stream, stream_handle) = match open_output(config.audio_device.as_deref()) {
+        Ok((stream, handle, error)) => {
+            config_errors.extend(error);
+            (Some(stream), Some(handle))
         }
         Err(e) => {
             // Log the error and continue in visual-only mode
@@ -37,28 +48,33 @@ fn main() -> io::Result<()> {
                 "Audio device not available: {}. Running in visual-only mode.",
                 e
             );
-            None
+            (None, None)
         }
     };
 
     // Create the app state
-    let mut app = App::new(stream_handle);
+    let poll_interval = config.poll_interval;
+    let mut app = App::new(stream_handle, config, config_errors);
 
     // Add a message if we're in visual-only mode
     if app.player.visual_only_mode {
         app.player
-            .add_message("Running in visual-only mode (no audio device)");
+            .messages
+            .warn("Running in visual-only mode (no audio device)");
     }
 
     loop {
         // Draw the UI
+        let size = terminal.size()?;
+        app.screen = Rect::new(0, 0, size.width, size.height);
         terminal.draw(|f| ui::draw(f, &app))?;
 
-        // Handle key events
-        if event::poll(Duration::from_millis(16))? {
-            // ~60fps
-            if let Event::Key(key) = event::read()? {
-                app.handle_key_events(key.code)?;
+        // Handle key and mouse events
+        if event::poll(poll_interval)? {
+            match event::read()? {
+                Event::Key(key) => app.handle_key_events(key)?,
+                Event::Mouse(mouse) => app.handle_mouse_event(mouse)?,
+                _ => {}
             }
         }
 
@@ -73,8 +89,43 @@ fn main() -> io::Result<()> {
 
     // Restore terminal
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     terminal.show_cursor()?;
 
     Ok(())
 }
+
+// Open the output device with the given name, or the default one. A named
+// device that can't be found falls back to the default, with an error to show
+fn open_output(
+    device_name: Option<&str>,
+) -> Result<(OutputStream, OutputStreamHandle, Option<String>), rodio::StreamError> {
+    let Some(name) = device_name else {
+        let (stream, handle) = OutputStream::try_default()?;
+        return Ok((stream, handle, None));
+    };
+
+    let device = rodio::cpal::default_host()
+        .output_devices()
+        .ok()
+        .and_then(|mut devices| {
+            devices.find(|device| device.name().is_ok_and(|device| device == name))
+        });
+
+    let error = match device.map(|device| OutputStream::try_from_device(&device)) {
+        Some(Ok((stream, handle))) => return Ok((stream, handle, None)),
+        Some(Err(e)) => format!("audio.device: could not open '{}': {}", name, e),
+        None => format!("audio.device: no output device named '{}'", name),
+    };
+
+    let (stream, handle) = OutputStream::try_default()?;
+    Ok((
+        stream,
+        handle,
+        Some(format!("{}, using the default", error)),
+    ))
+}
@@ -0,0 +1,205 @@
+use ratatui::style::Color;
+
+// Names of the built-in themes, the first one is the default
+pub const THEMES: [&str; 5] = ["dark", "light", "high-contrast", "colorblind", "monochrome"];
+
+// What the colors are called in the config file
+pub const COLOR_NAMES: [&str; 16] = [
+    "title",
+    "volume",
+    "speed",
+    "lowpass",
+    "stereo",
+    "reverb",
+    "active",
+    "waveform",
+    "text",
+    "dim",
+    "highlight",
+    "good",
+    "warning",
+    "error",
+    "meter",
+    "popup",
+];
+
+// Every color the UI uses, by what it's for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub title: Color,
+    pub volume: Color,
+    pub speed: Color,
+    pub lowpass: Color,
+    pub stereo: Color,
+    pub reverb: Color,
+    // Whatever the current mode is adjusting
+    pub active: Color,
+    pub waveform: Color,
+    // Plain text, and text that's there but not important (hints, bypassed
+    // effects, muted voices)
+    pub text: Color,
+    pub dim: Color,
+    // The selected entry of a list, and keys in help texts
+    pub highlight: Color,
+    // Levels and tuning: fine, getting close, too much
+    pub good: Color,
+    pub warning: Color,
+    pub error: Color,
+    // Gain reduction meters and envelope shapes
+    pub meter: Color,
+    // Background of popups
+    pub popup: Color,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            title: Color::Cyan,
+            volume: Color::Yellow,
+            speed: Color::Green,
+            lowpass: Color::Blue,
+            stereo: Color::Cyan,
+            reverb: Color::Magenta,
+            active: Color::Red,
+            waveform: Color::Green,
+            text: Color::White,
+            dim: Color::DarkGray,
+            highlight: Color::Yellow,
+            good: Color::Green,
+            warning: Color::Yellow,
+            error: Color::Red,
+            meter: Color::Magenta,
+            popup: Color::Black,
+        }
+    }
+
+    // For terminals with a light background
+    pub fn light() -> Self {
+        Self {
+            title: Color::Blue,
+            volume: Color::Indexed(130),
+            speed: Color::Indexed(28),
+            lowpass: Color::Blue,
+            stereo: Color::Indexed(30),
+            reverb: Color::Magenta,
+            active: Color::Red,
+            waveform: Color::Indexed(28),
+            text: Color::Black,
+            dim: Color::DarkGray,
+            highlight: Color::Indexed(130),
+            good: Color::Indexed(28),
+            warning: Color::Indexed(130),
+            error: Color::Red,
+            meter: Color::Magenta,
+            popup: Color::White,
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            title: Color::LightCyan,
+            volume: Color::LightYellow,
+            speed: Color::LightGreen,
+            lowpass: Color::LightBlue,
+            stereo: Color::LightCyan,
+            reverb: Color::LightMagenta,
+            active: Color::LightRed,
+            waveform: Color::LightGreen,
+            text: Color::White,
+            dim: Color::Gray,
+            highlight: Color::LightYellow,
+            good: Color::LightGreen,
+            warning: Color::LightYellow,
+            error: Color::LightRed,
+            meter: Color::LightMagenta,
+            popup: Color::Black,
+        }
+    }
+
+    // The Okabe-Ito palette, which stays apart for the common kinds of
+    // color blindness. Good and bad are blue and vermillion, not green and red
+    pub fn colorblind() -> Self {
+        let orange = Color::Rgb(0xe6, 0x9f, 0x00);
+        let sky_blue = Color::Rgb(0x56, 0xb4, 0xe9);
+        let bluish_green = Color::Rgb(0x00, 0x9e, 0x73);
+        let yellow = Color::Rgb(0xf0, 0xe4, 0x42);
+        let blue = Color::Rgb(0x00, 0x72, 0xb2);
+        let vermillion = Color::Rgb(0xd5, 0x5e, 0x00);
+        let reddish_purple = Color::Rgb(0xcc, 0x79, 0xa7);
+
+        Self {
+            title: sky_blue,
+            volume: orange,
+            speed: bluish_green,
+            lowpass: blue,
+            stereo: sky_blue,
+            reverb: reddish_purple,
+            active: vermillion,
+            waveform: bluish_green,
+            text: Color::White,
+            dim: Color::DarkGray,
+            highlight: yellow,
+            good: sky_blue,
+            warning: yellow,
+            error: vermillion,
+            meter: reddish_purple,
+            popup: Color::Black,
+        }
+    }
+
+    // The terminal's own colors only. Selections are still marked with > and
+    // bold text
+    pub fn monochrome() -> Self {
+        Self {
+            title: Color::Reset,
+            volume: Color::Reset,
+            speed: Color::Reset,
+            lowpass: Color::Reset,
+            stereo: Color::Reset,
+            reverb: Color::Reset,
+            active: Color::Reset,
+            waveform: Color::Reset,
+            text: Color::Reset,
+            dim: Color::Reset,
+            highlight: Color::Reset,
+            good: Color::Reset,
+            warning: Color::Reset,
+            error: Color::Reset,
+            meter: Color::Reset,
+            popup: Color::Reset,
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "colorblind" => Some(Self::colorblind()),
+            "monochrome" => Some(Self::monochrome()),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Color> {
+        match name {
+            "title" => Some(&mut self.title),
+            "volume" => Some(&mut self.volume),
+            "speed" => Some(&mut self.speed),
+            "lowpass" => Some(&mut self.lowpass),
+            "stereo" => Some(&mut self.stereo),
+            "reverb" => Some(&mut self.reverb),
+            "active" => Some(&mut self.active),
+            "waveform" => Some(&mut self.waveform),
+            "text" => Some(&mut self.text),
+            "dim" => Some(&mut self.dim),
+            "highlight" => Some(&mut self.highlight),
+            "good" => Some(&mut self.good),
+            "warning" => Some(&mut self.warning),
+            "error" => Some(&mut self.error),
+            "meter" => Some(&mut self.meter),
+            "popup" => Some(&mut self.popup),
+            _ => None,
+        }
+    }
+}
@@ -1,13 +1,329 @@
 use crate::app::{App, AppMode};
+use crate::audio_player::editor::EDIT_PARAMS;
+use crate::audio_player::effects::EffectKind;
+use crate::audio_player::looping::LoopMarker;
+use crate::audio_player::messages::Severity;
+use crate::audio_player::presets::PresetPrompt;
+use crate::audio_player::recorder::RecorderState;
+use crate::command;
+use crate::config::Config;
+use crate::keymap;
+use crate::theme::Theme;
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
     widgets::{Block, Borders, Clear, Gauge, Paragraph, Sparkline},
 };
 
+// Rows the panel below the controls keeps before rows above it are left out
+const PANEL_MIN: u16 = 5;
+// From this wide the panel goes next to the controls instead of below them
+const WIDE_WIDTH: u16 = 140;
+// Narrower than this the tuner is left out, to give the file name room
+const NARROW_WIDTH: u16 = 60;
+
+// The rows of the main screen, top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Title,
+    File,
+    Volume,
+    Speed,
+    Effects,
+    LoopRegion,
+    Overview,
+    Controls,
+}
+
+const ROWS: [Row; 8] = [
+    Row::Title,
+    Row::File,
+    Row::Volume,
+    Row::Speed,
+    Row::Effects,
+    Row::LoopRegion,
+    Row::Overview,
+    Row::Controls,
+];
+
+// Rows to leave out when the terminal is too short, least missed first
+const COLLAPSE_ORDER: [Row; 8] = [
+    Row::Controls,
+    Row::Overview,
+    Row::LoopRegion,
+    Row::File,
+    Row::Speed,
+    Row::Effects,
+    Row::Volume,
+    Row::Title,
+];
+
+// Where everything on the main screen goes. The mouse handling works out
+// what was clicked from the same rects. Anything that doesn't fit gets an
+// empty rect, which draws nothing and can't be clicked
+pub struct MainLayout {
+    pub title: Rect,
+    pub file: Rect,
+    pub tuner: Rect,
+    pub volume: Rect,
+    pub speed: Rect,
+    pub lowpass: Rect,
+    pub stereo: Rect,
+    pub reverb: Rect,
+    pub loop_region: Rect,
+    pub overview: Rect,
+    pub controls: Rect,
+    // The visualization, or the panel of the current mode
+    pub panel: Rect,
+    pub status: Rect,
+    // Boxes are two rows, a title and the contents, instead of three with
+    // a border
+    pub compact: bool,
+}
+
+impl MainLayout {
+    pub fn new(area: Rect) -> Self {
+        let [body, status] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)])
+            .areas(area);
+
+        // Wide terminals put the panel to the right, with the full height
+        let (stack, side_panel) = if area.width >= WIDE_WIDTH {
+            let [stack, panel] = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(body);
+            (stack, Some(panel))
+        } else {
+            (body, None)
+        };
+        let panel_min = if side_panel.is_some() { 0 } else { PANEL_MIN };
+
+        // Drop the borders first, then whole rows, until the panel has room
+        let compact = stack.height < ROWS.len() as u16 * 3 + panel_min;
+        let row_height = if compact { 2 } else { 3 };
+        let mut shown = ROWS.to_vec();
+        for row in COLLAPSE_ORDER {
+            if shown.len() as u16 * row_height + panel_min <= stack.height {
+                break;
+            }
+            shown.retain(|shown| *shown != row);
+        }
+
+        let constraints = shown
+            .iter()
+            .map(|_| Constraint::Length(row_height))
+            .chain([Constraint::Min(0)]);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(stack);
+        let row = |row: Row| match shown.iter().position(|shown| *shown == row) {
+            Some(index) => chunks[index],
+            None => Rect::default(),
+        };
+
+        // Without borders, boxes side by side need a gap between them
+        let gap = if compact { 1 } else { 0 };
+        let (file, tuner) = if area.width < NARROW_WIDTH {
+            (row(Row::File), Rect::default())
+        } else {
+            let [file, tuner] = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .spacing(gap)
+                .areas(row(Row::File));
+            (file, tuner)
+        };
+
+        // Effects area - split horizontally
+        let [lowpass, stereo, reverb] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(40),
+                Constraint::Percentage(35),
+                Constraint::Percentage(25),
+            ])
+            .spacing(gap)
+            .areas(row(Row::Effects));
+
+        Self {
+            title: row(Row::Title),
+            file,
+            tuner,
+            volume: row(Row::Volume),
+            speed: row(Row::Speed),
+            lowpass,
+            stereo,
+            reverb,
+            loop_region: row(Row::LoopRegion),
+            overview: row(Row::Overview),
+            controls: row(Row::Controls),
+            panel: side_panel.unwrap_or(chunks[shown.len()]),
+            status,
+            compact,
+        }
+    }
+
+    // The box around one of the rows
+    pub fn block<'a>(&self, title: impl Into<Line<'a>>) -> Block<'a> {
+        if self.compact {
+            Block::default().title(title)
+        } else {
+            Block::default().borders(Borders::ALL).title(title)
+        }
+    }
+
+    // What's inside a box, without its border or title
+    pub fn inner(&self, area: Rect) -> Rect {
+        self.block("").inner(area)
+    }
+}
+
+// The file browser's header, list and instructions. Short terminals lose
+// the header and keep one line of instructions
+pub struct BrowserLayout {
+    pub header: Rect,
+    pub list: Rect,
+    pub instructions: Rect,
+}
+
+impl BrowserLayout {
+    pub fn new(area: Rect) -> Self {
+        let (header, instructions) = if area.height < 14 { (0, 1) } else { (3, 3) };
+        let [header, list, instructions] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(header),       // Header
+                Constraint::Min(0),               // File list
+                Constraint::Length(instructions), // Instructions
+            ])
+            .margin(1)
+            .areas(area);
+
+        Self {
+            header,
+            list,
+            instructions,
+        }
+    }
+}
+
+// A popup of at most the given size in the middle of an area
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+// First entry shown of a list `height` rows high, so the selected one is
+// always on screen
+pub fn list_offset(selected: usize, height: u16) -> usize {
+    (selected + 1).saturating_sub(height.max(1) as usize)
+}
+
 pub fn draw(f: &mut Frame, app: &App) {
+    draw_screen(f, app);
+
+    // The log shows everything already, so no toasts over it
+    if app.mode != AppMode::Messages {
+        render_toasts(f, app);
+    }
+
+    if !app.config_errors.is_empty() {
+        render_config_errors(f, app);
+    }
+}
+
+// The latest messages, stacked in the top right corner until they expire
+fn render_toasts(f: &mut Frame, app: &App) {
+    let theme = &app.config.theme;
+    let area = f.area();
+    let mut y = area.y;
+    for message in app.player.messages.toasts(3) {
+        let text = format!("{} {}", message.severity.name(), message.text);
+        let width = (text.chars().count() as u16 + 4).min(60).min(area.width);
+        if width < 5 || y + 3 > area.bottom() {
+            break;
+        }
+        let toast_area = Rect::new(area.right() - width, y, width, 3);
+        let color = severity_color(theme, message.severity);
+
+        f.render_widget(Clear, toast_area);
+        let toast = Paragraph::new(Line::from(vec![
+            Span::styled(
+                message.severity.name(),
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!(" {}", message.text),
+                Style::default().fg(theme.text),
+            ),
+        ]))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(color)),
+        )
+        .style(Style::default().bg(theme.popup));
+        f.render_widget(toast, toast_area);
+        y += 3;
+    }
+}
+
+fn severity_color(theme: &Theme, severity: Severity) -> Color {
+    match severity {
+        Severity::Info => theme.text,
+        Severity::Warning => theme.warning,
+        Severity::Error => theme.error,
+    }
+}
+
+fn render_config_errors(f: &mut Frame, app: &App) {
+    let theme = &app.config.theme;
+    let popup_area = centered(f.area(), 80, app.config_errors.len() as u16 + 5);
+
+    let path = Config::path()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "config".to_string());
+
+    f.render_widget(Clear, popup_area);
+    let block = Block::default()
+        .title(format!("Problems in {}", path))
+        .borders(Borders::ALL)
+        .style(Style::default().bg(theme.popup).fg(theme.text));
+
+    let mut lines: Vec<Line> = app
+        .config_errors
+        .iter()
+        .map(|error| {
+            Line::from(Span::styled(
+                format!("- {}", error),
+                Style::default().fg(theme.error),
+            ))
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "Built-in defaults are used instead. Press any key to continue",
+        Style::default().fg(theme.dim),
+    )));
+
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(ratatui::widgets::Wrap { trim: false });
+    f.render_widget(paragraph, popup_area);
+}
+
+fn draw_screen(f: &mut Frame, app: &App) {
     // If in file browser mode, show that instead of normal UI
     if app.mode == AppMode::FileBrowser {
         render_file_browser(f, app);
@@ -16,21 +332,8 @@ pub fn draw(f: &mut Frame, app: &App) {
 
     // Normal UI rendering for other modes
     // Create the layout
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints(
-            [
-                Constraint::Length(3), // Title
-                Constraint::Length(3), // Current file
-                Constraint::Length(3), // Volume
-                Constraint::Length(3), // Speed
-                Constraint::Length(3), // Effects area
-                Constraint::Length(3), // Controls
-                Constraint::Min(0),    // Waveform visualization
-            ]
-            .as_ref(),
-        )
-        .split(f.area());
+    let theme = &app.config.theme;
+    let layout = MainLayout::new(f.area());
 
     // Title with playback status and current mode
     let mode_text = match app.mode {
@@ -39,6 +342,20 @@ pub fn draw(f: &mut Frame, app: &App) {
         AppMode::Pitch => " [PITCH MODE]",
         AppMode::Filter => " [FILTER MODE]",
         AppMode::FileBrowser => " [FILE BROWSER]",
+        AppMode::Loop => " [LOOP MODE]",
+        AppMode::Edit => " [EDIT MODE]",
+        AppMode::Record => " [RECORD MODE]",
+        AppMode::Chain => " [EFFECT CHAIN]",
+        AppMode::Mixer => " [MIXER]",
+        AppMode::Stereo => " [STEREO MODE]",
+        AppMode::Dynamics => " [DYNAMICS]",
+        AppMode::Delay => " [DELAY]",
+        AppMode::Modulation => " [MODULATION]",
+        AppMode::Distortion => " [DISTORTION]",
+        AppMode::SynthFilter => " [SYNTH FILTER]",
+        AppMode::Envelope => " [AMP ENVELOPE]",
+        AppMode::Presets => " [PRESETS]",
+        AppMode::Messages => " [MESSAGES]",
     };
 
     let status = if app.player.is_playing() {
@@ -51,15 +368,36 @@ pub fn draw(f: &mut Frame, app: &App) {
         ""
     };
 
-    let title = Paragraph::new(format!("Audio Player{}{}", status, mode_text))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("TUI Audio Player"),
-        )
-        .style(Style::default().fg(Color::Cyan))
-        .alignment(ratatui::prelude::Alignment::Center);
-    f.render_widget(title, chunks[0]);
+    // Recording keeps going outside Record mode, so always flag it
+    let recording = if app.player.recorder.state == RecorderState::Recording {
+        " [REC]"
+    } else {
+        ""
+    };
+
+    let preset = match &app.preset_browser.current {
+        Some(name) => format!(" - {}", name),
+        None => String::new(),
+    };
+
+    // Which A/B slot is playing, and how far it's turned down to match
+    let ab = &app.player.ab;
+    let ab_text = if !ab.is_active() {
+        String::new()
+    } else if ab.trim_db() < -0.05 {
+        format!(" [{} {:.1}dB]", ab.active.name(), ab.trim_db())
+    } else {
+        format!(" [{}]", ab.active.name())
+    };
+
+    let title = Paragraph::new(format!(
+        "Audio Player{}{}{}{}{}",
+        preset, ab_text, status, recording, mode_text
+    ))
+    .block(layout.block("TUI Audio Player"))
+    .style(Style::default().fg(theme.title))
+    .alignment(ratatui::prelude::Alignment::Center);
+    f.render_widget(title, layout.title);
 
     let file_text = match &app.current_audio_file {
         Some(file_name) => format!("🎵 {}", file_name),
@@ -67,66 +405,91 @@ pub fn draw(f: &mut Frame, app: &App) {
     };
 
     let current_file = Paragraph::new(file_text)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Current Audio File"),
-        )
-        .style(Style::default().fg(Color::Cyan))
+        .block(layout.block("Current Audio File"))
+        .style(Style::default().fg(theme.title))
         .alignment(ratatui::prelude::Alignment::Center);
 
-    f.render_widget(current_file, chunks[1]);
+    f.render_widget(current_file, layout.file);
+
+    // Tuner readout of the detected fundamental
+    let (tuner_text, tuner_color) = match &app.player.tuner.reading {
+        Some(reading) => {
+            let mut text = format!(
+                "{}{} {:+.0}c  {:.1}Hz",
+                reading.note, reading.octave, reading.cents, reading.frequency
+            );
+            // Show what the speed change did to the original pitch
+            if reading.speed_shift.abs() >= 0.01 {
+                text.push_str(&format!(
+                    "  ({:+.1}st from {})",
+                    reading.speed_shift,
+                    reading.source_note()
+                ));
+            }
+
+            let color = if reading.cents.abs() <= 5.0 {
+                theme.good
+            } else if reading.cents.abs() <= 20.0 {
+                theme.warning
+            } else {
+                theme.error
+            };
+            (text, color)
+        }
+        None => ("--".to_string(), theme.dim),
+    };
+
+    let tuner = Paragraph::new(tuner_text)
+        .block(layout.block("Tuner"))
+        .style(Style::default().fg(tuner_color))
+        .alignment(ratatui::prelude::Alignment::Center);
+
+    f.render_widget(tuner, layout.tuner);
 
     // Volume gauge
-    let volume_percent = (app.player.effect_manager.get_volume() / 2.0 * 100.0) as u16;
+    let volume_percent = (app
+        .config
+        .volume
+        .fraction(app.player.effect_manager.get_volume())
+        * 100.0) as u16;
     let volume_gauge = Gauge::default()
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(if app.mode == AppMode::Volume {
-                    "Volume (j/k to adjust)"
-                } else {
-                    "Volume"
-                }),
-        )
+        .block(layout.block(if app.mode == AppMode::Volume {
+            "Volume (j/k to adjust)"
+        } else {
+            "Volume"
+        }))
         .gauge_style(Style::default().fg(if app.mode == AppMode::Volume {
-            Color::Red
+            theme.active
         } else {
-            Color::Yellow
+            theme.volume
         }))
         .percent(volume_percent)
         .label(format!("{:.1}x", app.player.effect_manager.get_volume()));
-    f.render_widget(volume_gauge, chunks[2]);
+    f.render_widget(volume_gauge, layout.volume);
 
     // Speed gauge
-    let speed_percent = (app.player.effect_manager.get_playback_speed() / 3.0 * 100.0) as u16;
+    let speed_percent = (app
+        .config
+        .speed
+        .fraction(app.player.effect_manager.get_playback_speed())
+        * 100.0) as u16;
     let speed_gauge = Gauge::default()
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(if app.mode == AppMode::Pitch {
-                    "Playback Speed (j/k to adjust)"
-                } else {
-                    "Playback Speed"
-                }),
-        )
+        .block(layout.block(if app.mode == AppMode::Pitch {
+            "Playback Speed (j/k to adjust)"
+        } else {
+            "Playback Speed"
+        }))
         .gauge_style(Style::default().fg(if app.mode == AppMode::Pitch {
-            Color::Red
+            theme.active
         } else {
-            Color::Green
+            theme.speed
         }))
         .percent(speed_percent)
         .label(format!(
             "{:.1}x",
             app.player.effect_manager.get_playback_speed()
         ));
-    f.render_widget(speed_gauge, chunks[3]);
-
-    // Effects area - split horizontally
-    let effects_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
-        .split(chunks[4]);
+    f.render_widget(speed_gauge, layout.speed);
 
     // Low-pass filter
     let lowpass_cutoff = app.player.effect_manager.get_lowpass_cutoff();
@@ -139,27 +502,64 @@ pub fn draw(f: &mut Frame, app: &App) {
     let filter_percent = if lowpass_cutoff >= 20000 {
         100
     } else {
-        (lowpass_cutoff as f32 / 20000.0 * 100.0) as u16
+        (app.config.lowpass.fraction(lowpass_cutoff as f32) * 100.0) as u16
     };
 
     let lowpass_gauge = Gauge::default()
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(if app.mode == AppMode::Filter {
-                    "Low-Pass Filter (j/k to adjust)"
-                } else {
-                    "Low-Pass Filter"
-                }),
-        )
+        .block(layout.block(if app.mode == AppMode::Filter {
+            "Low-Pass Filter (j/k to adjust)"
+        } else {
+            "Low-Pass Filter"
+        }))
         .gauge_style(Style::default().fg(if app.mode == AppMode::Filter {
-            Color::Red
+            theme.active
         } else {
-            Color::Blue
+            theme.lowpass
         }))
         .percent(filter_percent)
         .label(filter_text);
-    f.render_widget(lowpass_gauge, effects_chunks[0]);
+    f.render_widget(lowpass_gauge, layout.lowpass);
+
+    // Stereo image: the gauge shows the width, the label the rest
+    let effects = &app.player.effect_manager;
+    let balance = effects.get_balance();
+    let balance_text = if balance == 0.0 {
+        "C".to_string()
+    } else if balance < 0.0 {
+        format!("L{:.0}", -balance * 100.0)
+    } else {
+        format!("R{:.0}", balance * 100.0)
+    };
+    let mut stereo_text = format!(
+        "Bal {}  Width {:.0}%",
+        balance_text,
+        effects.get_stereo_width() * 100.0
+    );
+    if effects.mono {
+        stereo_text.push_str("  MONO");
+    }
+    if effects.swap_channels {
+        stereo_text.push_str("  L<>R");
+    }
+
+    let stereo_gauge = Gauge::default()
+        .block(layout.block(if app.mode == AppMode::Stereo {
+            "Stereo (h/l balance, j/k width)"
+        } else {
+            "Stereo"
+        }))
+        .gauge_style(Style::default().fg(if app.mode == AppMode::Stereo {
+            theme.active
+        } else {
+            theme.stereo
+        }))
+        .percent(if effects.mono {
+            0
+        } else {
+            (app.config.stereo_width.fraction(effects.get_stereo_width()) * 100.0) as u16
+        })
+        .label(stereo_text);
+    f.render_widget(stereo_gauge, layout.stereo);
 
     // Simplified reverb indicator
     let reverb_enabled = app.player.effect_manager.is_reverb_enabled();
@@ -170,11 +570,11 @@ pub fn draw(f: &mut Frame, app: &App) {
     };
 
     let reverb_gauge = Gauge::default()
-        .block(Block::default().borders(Borders::ALL).title(reverb_title))
+        .block(layout.block(reverb_title))
         .gauge_style(if reverb_enabled {
-            Style::default().fg(Color::Magenta)
+            Style::default().fg(theme.reverb)
         } else {
-            Style::default().fg(Color::DarkGray)
+            Style::default().fg(theme.dim)
         })
         .percent(if reverb_enabled { 100 } else { 0 })
         .label(if reverb_enabled {
@@ -183,44 +583,213 @@ pub fn draw(f: &mut Frame, app: &App) {
             "Disabled"
         });
 
-    f.render_widget(reverb_gauge, effects_chunks[1]);
+    f.render_widget(reverb_gauge, layout.reverb);
+
+    // Loop region of the current file
+    let loop_text = match &app.current_audio_path {
+        Some(path) => match (
+            app.player.loop_store.get(path),
+            app.player.decoded_audio(path),
+        ) {
+            (Some(region), Some(audio)) => {
+                let marker = |m: LoopMarker, text: String| {
+                    if app.mode == AppMode::Loop && app.loop_editor.marker == m {
+                        format!("[{}]", text)
+                    } else {
+                        text
+                    }
+                };
+                format!(
+                    "{}  {}  {}",
+                    marker(
+                        LoopMarker::Start,
+                        format!("Start {:.3}s", audio.frames_to_secs(region.start))
+                    ),
+                    marker(
+                        LoopMarker::End,
+                        format!("End {:.3}s", audio.frames_to_secs(region.end))
+                    ),
+                    marker(
+                        LoopMarker::Crossfade,
+                        format!(
+                            "X-fade {:.0}ms",
+                            audio.frames_to_secs(region.crossfade) * 1000.0
+                        )
+                    ),
+                )
+            }
+            (Some(region), None) => format!(
+                "Start {}  End {}  X-fade {} (samples)",
+                region.start, region.end, region.crossfade
+            ),
+            (None, _) if app.mode == AppMode::Loop => {
+                format!(
+                    "Whole file - [{}] j/k to set markers",
+                    marker_name(app.loop_editor.marker)
+                )
+            }
+            (None, _) => "Whole file".to_string(),
+        },
+        None => "No file selected".to_string(),
+    };
+
+    let loop_title = if app.mode == AppMode::Loop {
+        format!("Loop Region (step: {})", app.loop_editor.step_name())
+    } else {
+        "Loop Region".to_string()
+    };
+
+    let loop_region = Paragraph::new(loop_text)
+        .block(layout.block(loop_title))
+        .style(Style::default().fg(if app.mode == AppMode::Loop {
+            theme.active
+        } else {
+            theme.title
+        }))
+        .alignment(ratatui::prelude::Alignment::Center);
+    f.render_widget(loop_region, layout.loop_region);
+
+    render_overview(f, app, &layout);
 
     // Controls with status
-    let playing_info = if app.player.active_sinks.is_empty() {
+    let playing_info = if app.player.voices.is_empty() {
         String::new()
     } else {
-        let loop_count = app
-            .player
-            .active_sinks
-            .iter()
-            .filter(|(_, is_loop)| *is_loop)
-            .count();
         format!(
             " | Playing: {} (Loops: {})",
-            app.player.active_sinks.len(),
-            loop_count
+            app.player.voices.len(),
+            app.player.loop_count()
         )
     };
 
     // Update controls based on mode
     let controls_text = match app.mode {
         AppMode::Normal => format!(
-            "p: Play  r: Loop  <Space>: Menu  e: Reverb  q: Quit{}",
+            "p: Play  r: Loop  <Space>: Menu  e: Reverb  `: A/B  :: Command  q: Quit{}",
             playing_info
         ),
         AppMode::Volume => "j/k: Adjust Volume  Esc: Exit mode".to_string(),
         AppMode::Pitch => "j/k: Adjust Pitch  Esc: Exit mode".to_string(),
         AppMode::Filter => "j/k: Adjust Filter  Esc: Exit mode".to_string(),
+        AppMode::Stereo => {
+            "h/l: Balance  j/k: Width  c: Center  m: Mono  s: Swap L/R  Esc: Exit mode".to_string()
+        }
         AppMode::FileBrowser => {
             "j/k: Navigate  Enter: Select/Play  h: Up Dir  Esc: Exit".to_string()
         }
+        AppMode::Loop => {
+            "Tab: Marker  j/k: Move  h/l: Step  z: Zero-snap  c: Clear  r: Audition  s: Stop"
+                .to_string()
+        }
+        AppMode::Edit if app.sample_editor.confirm_overwrite => {
+            "Overwrite the original file? y: Yes  any other key: Cancel".to_string()
+        }
+        AppMode::Edit => {
+            "j/k: Select  h/l: Change  p: Preview  s: Stop  w: Save as new  W: Overwrite  Esc: Exit"
+                .to_string()
+        }
+        AppMode::Record => {
+            "d: Input  a: Arm/Disarm  r: Record  s: Stop  Esc: Exit (keeps recording)".to_string()
+        }
+        AppMode::Chain => {
+            "j/k: Select  J/K: Move  b: Bypass  d: Remove  h/l: Pick  a: Add  Esc: Exit".to_string()
+        }
+        AppMode::Dynamics => "j/k: Select  h/l: Change  Esc: Exit mode".to_string(),
+        AppMode::Delay => "j/k: Select  h/l: Change  b: On/Off  Esc: Exit mode".to_string(),
+        AppMode::Envelope => {
+            "j/k: Select  h/l: Change  s: Stop (release)  Esc: Exit mode".to_string()
+        }
+        AppMode::Distortion | AppMode::SynthFilter => {
+            "j/k: Select  h/l: Change  b: On/Off  Esc: Exit mode".to_string()
+        }
+        AppMode::Modulation => {
+            "j/k: Effect  Tab: Parameter  h/l: Change  b: On/Off  Esc: Exit mode".to_string()
+        }
+        AppMode::Presets => match app.preset_browser.prompt {
+            Some(_) => "Type a name  Enter: OK  Backspace: Delete  Esc: Cancel".to_string(),
+            None if app.preset_browser.confirm_delete => {
+                "Delete the preset? y: Yes  any other key: Cancel".to_string()
+            }
+            None => {
+                "j/k: Select  Enter: Load  a: Save as  r: Rename  d: Delete  [/]: Cycle  Esc: Exit"
+                    .to_string()
+            }
+        },
+        AppMode::Mixer => {
+            "j/k: Select  h/l: Pan  J/K: Gain  m: Mute  s: Solo  x: Stop voice  Esc: Exit"
+                .to_string()
+        }
+        AppMode::Messages => "j/k: Scroll  gg/G: Oldest/Newest  c: Clear  Esc: Exit".to_string(),
     };
 
     let controls = Paragraph::new(controls_text)
-        .style(Style::default().fg(Color::White))
-        .block(Block::default().borders(Borders::ALL).title("Controls"))
+        .style(Style::default().fg(theme.text))
+        .block(layout.block("Controls"))
         .alignment(ratatui::prelude::Alignment::Center);
-    f.render_widget(controls, chunks[5]);
+    f.render_widget(controls, layout.controls);
+
+    // Last effect change, and how far back undo/redo can go
+    let history = &app.history;
+    let mut status = Line::from(vec![
+        Span::styled(
+            format!(" {}", history.status.as_deref().unwrap_or("")),
+            Style::default().fg(theme.text),
+        ),
+        Span::styled(
+            format!(
+                "  (u: undo {}  Ctrl-r: redo {})",
+                history.undo_count(),
+                history.redo_count()
+            ),
+            Style::default().fg(theme.dim),
+        ),
+    ]);
+    // A count being typed, as Vim shows it
+    if let Some(count) = app.count {
+        status.push_span(Span::styled(
+            format!("  {}", count),
+            Style::default()
+                .fg(theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if !render_command_line(f, app, layout.status) {
+        f.render_widget(Paragraph::new(status), layout.status);
+    }
+
+    // Editors and the recorder take the place of the visualization
+    if matches!(
+        app.mode,
+        AppMode::Edit
+            | AppMode::Record
+            | AppMode::Chain
+            | AppMode::Mixer
+            | AppMode::Messages
+            | AppMode::Dynamics
+            | AppMode::Delay
+            | AppMode::Modulation
+            | AppMode::Distortion
+            | AppMode::SynthFilter
+            | AppMode::Envelope
+    ) {
+        match app.mode {
+            AppMode::Edit => render_edit_panel(f, app, layout.panel),
+            AppMode::Record => render_record_panel(f, app, layout.panel),
+            AppMode::Mixer => render_mixer_panel(f, app, layout.panel),
+            AppMode::Messages => render_messages_panel(f, app, layout.panel),
+            AppMode::Dynamics => render_dynamics_panel(f, app, layout.panel),
+            AppMode::Delay => render_delay_panel(f, app, layout.panel),
+            AppMode::Modulation => render_modulation_panel(f, app, layout.panel),
+            AppMode::Distortion => render_distortion_panel(f, app, layout.panel),
+            AppMode::SynthFilter => render_synth_filter_panel(f, app, layout.panel),
+            AppMode::Envelope => render_envelope_panel(f, app, layout.panel),
+            _ => render_chain_panel(f, app, layout.panel),
+        }
+        if !app.pending_keys.is_empty() {
+            render_help_popup(f, app);
+        }
+        return;
+    }
 
     // Waveform visualization (now with more space at the bottom)
     let wave_block = Block::default()
@@ -240,96 +809,126 @@ pub fn draw(f: &mut Frame, app: &App) {
         .block(wave_block)
         .data(&waveform_data)
         .style(if app.player.is_playing() {
-            Style::default().fg(Color::Green)
+            Style::default().fg(theme.waveform)
         } else {
-            Style::default().fg(Color::DarkGray)
+            Style::default().fg(theme.dim)
         });
 
-    f.render_widget(sparkline, chunks[6]);
+    f.render_widget(sparkline, layout.panel);
+
+    // The whole of the current file, with how far it has played. Clicking
+    // it seeks
+    fn render_overview(f: &mut Frame, app: &App, layout: &MainLayout) {
+        let area = layout.overview;
+        const LEVELS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
-    fn render_help_popup(f: &mut Frame) {
-        // Calculate popup size and position
-        let area = f.area();
-        let popup_width = 40;
-        let popup_height = 12; // Increased height for file browser option
-        let popup_x = (area.width - popup_width) / 2;
-        let popup_y = (area.height - popup_height) / 2;
+        let theme = &app.config.theme;
+        let file = app.current_audio_path.as_ref().and_then(|path| {
+            Some((
+                app.player.decoded_audio(path)?,
+                app.player.overview(path)?,
+                app.player.position(path),
+            ))
+        });
+        let Some((audio, peaks, position)) = file else {
+            let overview = Paragraph::new("Play a file to see its overview")
+                .block(layout.block("Overview"))
+                .style(Style::default().fg(theme.dim))
+                .alignment(ratatui::prelude::Alignment::Center);
+            f.render_widget(overview, area);
+            return;
+        };
 
-        let popup_area = Rect::new(popup_x, popup_y, popup_width, popup_height);
+        let frames = audio.frames().max(1);
+        let title = match position {
+            Some(frame) => format!(
+                "Overview {:.1}s / {:.1}s",
+                audio.frames_to_secs(frame),
+                audio.frames_to_secs(frames)
+            ),
+            None => format!("Overview {:.1}s", audio.frames_to_secs(frames)),
+        };
+        let block = layout.block(title);
+        let width = block.inner(area).width as usize;
+
+        // Each column shows the loudest of the peaks under it
+        let head = position.map(|frame| frame * width / frames);
+        let columns: Vec<Span> = (0..width)
+            .map(|column| {
+                let start = column * peaks.len() / width;
+                let end = ((column + 1) * peaks.len() / width).max(start + 1);
+                let peak = peaks
+                    .get(start..end.min(peaks.len()))
+                    .unwrap_or(&[])
+                    .iter()
+                    .fold(0.0f32, |peak, value| peak.max(*value));
+                let level = (peak.min(1.0) * 8.0).round() as usize;
+
+                let style = match head {
+                    Some(head) if column == head => Style::default()
+                        .fg(theme.text)
+                        .add_modifier(Modifier::REVERSED),
+                    Some(head) if column < head => Style::default().fg(theme.waveform),
+                    _ => Style::default().fg(theme.dim),
+                };
+                Span::styled(LEVELS[level].to_string(), style)
+            })
+            .collect();
+
+        f.render_widget(Paragraph::new(Line::from(columns)).block(block), area);
+    }
+
+    // Keys that can follow what's been typed so far, from the keymap
+    fn render_help_popup(f: &mut Frame, app: &App) {
+        let theme = &app.config.theme;
+        let bindings = app.config.keymap.continuations(app.mode, &app.pending_keys);
+
+        let popup_area = centered(f.area(), 40, bindings.len() as u16 + 5);
 
         // Render the popup background
         f.render_widget(Clear, popup_area);
 
         // Create the popup block
         let help_block = Block::default()
-            .title("Command Menu")
+            .title(format!(
+                "Command Menu {}",
+                keymap::sequence_name(&app.pending_keys)
+            ))
             .borders(Borders::ALL)
-            .style(Style::default().bg(Color::Black).fg(Color::White));
+            .style(Style::default().bg(theme.popup).fg(theme.text));
 
         f.render_widget(help_block, popup_area);
 
         // Create the inner area for text
-        let inner_area = Rect::new(popup_x + 2, popup_y + 2, popup_width - 4, popup_height - 4);
+        let inner_area = popup_area.inner(Margin::new(2, 2));
 
         // Help text
-        let help_text = vec![
-            Line::from(vec![
-                Span::styled(
-                    "v",
-                    Style::default()
-                        .fg(Color::Yellow)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(": Volume mode"),
-            ]),
-            Line::from(vec![
-                Span::styled(
-                    "c",
-                    Style::default()
-                        .fg(Color::Green)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(": Pitch mode"),
-            ]),
-            Line::from(vec![
-                Span::styled(
-                    "g",
-                    Style::default()
-                        .fg(Color::Blue)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(": Filter mode"),
-            ]),
-            Line::from(vec![
-                Span::styled(
-                    "f",
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(": File browser"),
-            ]),
-            Line::from(vec![
-                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(": Close menu"),
-            ]),
-        ];
+        let mut help_text: Vec<Line> = bindings
+            .iter()
+            .map(|(keys, action)| {
+                Line::from(vec![
+                    Span::styled(
+                        keys.clone(),
+                        Style::default()
+                            .fg(theme.highlight)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(": {}", action.description())),
+                ])
+            })
+            .collect();
+        help_text.push(Line::from(vec![
+            Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(": Close menu"),
+        ]));
 
         let help_paragraph = Paragraph::new(help_text);
         f.render_widget(help_paragraph, inner_area);
     }
 
     fn render_file_browser(f: &mut Frame, app: &App) {
-        // Use the bottom section for file browser
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(3), // Header
-                Constraint::Min(0),    // File list
-                Constraint::Length(3), // Instructions
-            ])
-            .margin(1)
-            .split(f.area());
+        let theme = &app.config.theme;
+        let layout = BrowserLayout::new(f.area());
 
         // Create a block for file browser
         let block = Block::default()
@@ -345,11 +944,12 @@ pub fn draw(f: &mut Frame, app: &App) {
             Paragraph::new("Use j/k to navigate, Enter to select/play, h to go up, Esc to exit")
                 .style(Style::default().add_modifier(Modifier::BOLD))
                 .alignment(ratatui::prelude::Alignment::Center);
-        f.render_widget(header, chunks[0]);
+        f.render_widget(header, layout.header);
 
-        // Create list of files
+        // Create list of files, scrolled to keep the selection in view
+        let offset = list_offset(app.file_manager.selected_index, layout.list.height);
         let mut items = Vec::new();
-        for (i, path) in app.file_manager.entries.iter().enumerate() {
+        for (i, path) in app.file_manager.entries.iter().enumerate().skip(offset) {
             let file_name = path
                 .file_name()
                 .map(|n| n.to_string_lossy().to_string())
@@ -365,7 +965,7 @@ pub fn draw(f: &mut Frame, app: &App) {
                 items.push(Line::from(vec![Span::styled(
                     format!("> {}", display_name),
                     Style::default()
-                        .fg(Color::Yellow)
+                        .fg(theme.highlight)
                         .add_modifier(Modifier::BOLD),
                 )]));
             } else {
@@ -376,43 +976,721 @@ pub fn draw(f: &mut Frame, app: &App) {
         if items.is_empty() {
             items.push(Line::from(vec![Span::styled(
                 "No files or directories found",
-                Style::default().fg(Color::Red),
+                Style::default().fg(theme.error),
             )]));
         }
 
         let file_list = Paragraph::new(items).block(Block::default());
-        f.render_widget(file_list, chunks[1]);
+        f.render_widget(file_list, layout.list);
 
         // Instructions
         let instructions = Paragraph::new(Text::from(vec![Line::from(vec![
             Span::styled(
                 "p",
                 Style::default()
-                    .fg(Color::Yellow)
+                    .fg(theme.highlight)
                     .add_modifier(Modifier::BOLD),
             ),
             Span::raw(": Play file  "),
             Span::styled(
                 "r",
                 Style::default()
-                    .fg(Color::Yellow)
+                    .fg(theme.highlight)
                     .add_modifier(Modifier::BOLD),
             ),
             Span::raw(": Loop file  "),
             Span::styled(
                 "Esc",
                 Style::default()
-                    .fg(Color::Yellow)
+                    .fg(theme.highlight)
                     .add_modifier(Modifier::BOLD),
             ),
             Span::raw(": Exit browser"),
         ])]))
         .alignment(ratatui::prelude::Alignment::Center);
-        f.render_widget(instructions, chunks[2]);
+        f.render_widget(instructions, layout.instructions);
+
+        let bottom = layout.instructions;
+        render_command_line(
+            f,
+            app,
+            Rect::new(bottom.x, bottom.bottom().saturating_sub(1), bottom.width, 1),
+        );
+    }
+
+    // The `:` line while typing a command, and what the last one did after.
+    // Returns false if there's nothing to show
+    fn render_command_line(f: &mut Frame, app: &App, area: Rect) -> bool {
+        let theme = &app.config.theme;
+        let line = &app.command_line;
+
+        let text = if line.active {
+            let mut spans = vec![Span::raw(format!(":{}", line.input))];
+            if line.completions.len() > 1 {
+                // Where we are in the candidates
+                spans.push(Span::styled(
+                    format!(
+                        "  [{}/{}]",
+                        line.completion_index + 1,
+                        line.completions.len()
+                    ),
+                    Style::default().fg(theme.dim),
+                ));
+            } else if let Some((_, usage)) = command::COMMANDS
+                .iter()
+                .find(|(name, _)| line.input.trim_end() == *name)
+            {
+                spans.push(Span::styled(
+                    format!(" {}", usage),
+                    Style::default().fg(theme.dim),
+                ));
+            }
+            Line::from(spans)
+        } else {
+            match &line.result {
+                Some(Ok(message)) => Line::from(Span::raw(format!(" {}", message))),
+                Some(Err(e)) => Line::from(Span::styled(
+                    format!(" {}", e),
+                    Style::default().fg(theme.error),
+                )),
+                None => return false,
+            }
+        };
+
+        f.render_widget(Clear, area);
+        f.render_widget(Paragraph::new(text), area);
+        true
+    }
+
+    fn render_edit_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let editor = &app.sample_editor;
+        let file_name = editor
+            .file
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut lines = param_lines(
+            EDIT_PARAMS
+                .iter()
+                .map(|param| editor.settings.describe(*param)),
+            editor.selected,
+            18,
+            theme,
+        );
+
+        // Result of the last save/preview
+        if let Some(message) = app.player.messages.last() {
+            lines.push(Line::from(""));
+            lines.push(Line::from(vec![Span::styled(
+                message.text.clone(),
+                Style::default().fg(theme.dim),
+            )]));
+        }
+
+        let panel = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Edit: {} (original is kept)", file_name)),
+        );
+        f.render_widget(panel, area);
+    }
+
+    fn render_chain_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let effects = &app.player.effect_manager;
+        let editor = &app.chain_editor;
+
+        let mut lines = Vec::new();
+        for (i, slot) in effects.chain.iter().enumerate() {
+            let text = format!(
+                "{}. {}{}",
+                i + 1,
+                slot.kind.name(),
+                if slot.bypassed { " (bypassed)" } else { "" }
+            );
+
+            if i == editor.selected {
+                lines.push(Line::from(vec![Span::styled(
+                    format!("> {}", text),
+                    Style::default()
+                        .fg(theme.highlight)
+                        .add_modifier(Modifier::BOLD),
+                )]));
+            } else if slot.bypassed {
+                lines.push(Line::from(vec![Span::styled(
+                    format!("  {}", text),
+                    Style::default().fg(theme.dim),
+                )]));
+            } else {
+                lines.push(Line::from(format!("  {}", text)));
+            }
+        }
+        if effects.chain.is_empty() {
+            lines.push(Line::from(vec![Span::styled(
+                "  (empty - sounds play dry)",
+                Style::default().fg(theme.dim),
+            )]));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(Line::from(match editor.candidate(effects) {
+            Some(kind) => format!("Add: < {} >", kind.name()),
+            None => "Every effect is in the chain".to_string(),
+        }));
+
+        let panel = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Effect Chain (input -> output, speed is applied first)"),
+        );
+        f.render_widget(panel, area);
+    }
+
+    fn render_dynamics_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let dynamics = &app.player.effect_manager.dynamics;
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Dynamics (master bus)");
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(inner);
+
+        let lines = param_lines(
+            app.dynamics_params
+                .params()
+                .iter()
+                .map(|param| dynamics.describe(*param)),
+            app.dynamics_params.selected,
+            14,
+            theme,
+        );
+        f.render_widget(Paragraph::new(lines), columns[0]);
+
+        // Gain reduction meters, full scale is 24dB
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ])
+            .split(columns[1]);
+
+        let reduction = &app.player.gain_reduction;
+        let meters = [
+            ("Gate", reduction.gate, dynamics.gate),
+            ("Compressor", reduction.compressor, dynamics.compressor),
+            ("Limiter", reduction.limiter, dynamics.limiter),
+        ];
+        for ((name, db, enabled), row) in meters.into_iter().zip(rows.iter()) {
+            let meter = Gauge::default()
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(format!("{} GR", name)),
+                )
+                .gauge_style(Style::default().fg(if enabled { theme.meter } else { theme.dim }))
+                .percent((db / 24.0 * 100.0).clamp(0.0, 100.0) as u16)
+                .label(if enabled {
+                    format!("-{:.1} dB", db)
+                } else {
+                    "Off".to_string()
+                });
+            f.render_widget(meter, *row);
+        }
+    }
+
+    fn render_delay_panel(f: &mut Frame, app: &App, area: Rect) {
+        let effects = &app.player.effect_manager;
+        let delay = &effects.delay;
+
+        let lines = param_lines(
+            app.delay_params
+                .params()
+                .iter()
+                .map(|param| delay.describe(*param)),
+            app.delay_params.selected,
+            14,
+            &app.config.theme,
+        );
+
+        let title = if effects.is_active(EffectKind::Delay) {
+            "Delay: ON"
+        } else {
+            "Delay: OFF (b to enable)"
+        };
+        let panel =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(panel, area);
+    }
+
+    fn render_distortion_panel(f: &mut Frame, app: &App, area: Rect) {
+        let effects = &app.player.effect_manager;
+        let on_off = |kind: EffectKind| {
+            if effects.is_active(kind) { "ON" } else { "OFF" }
+        };
+
+        let lines = param_lines(
+            app.distortion_params
+                .params()
+                .iter()
+                .map(|param| effects.distortion.describe(*param)),
+            app.distortion_params.selected,
+            14,
+            &app.config.theme,
+        );
+
+        let title = format!(
+            "Saturation: {}  Bitcrusher: {}",
+            on_off(EffectKind::Saturation),
+            on_off(EffectKind::Bitcrusher)
+        );
+        let panel =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(panel, area);
+    }
+
+    fn render_synth_filter_panel(f: &mut Frame, app: &App, area: Rect) {
+        let effects = &app.player.effect_manager;
+
+        let lines = param_lines(
+            app.filter_params
+                .params()
+                .iter()
+                .map(|param| effects.filter.describe(*param)),
+            app.filter_params.selected,
+            14,
+            &app.config.theme,
+        );
+
+        let title = if effects.is_active(EffectKind::Filter) {
+            "Multimode Filter: ON (envelope restarts with every sound)"
+        } else {
+            "Multimode Filter: OFF (b to enable)"
+        };
+        let panel =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(panel, area);
+    }
+
+    fn render_envelope_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let envelope = &app.player.effect_manager.amp_envelope;
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Amp Envelope (every sound, released on stop)");
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(4), Constraint::Min(0)])
+            .split(inner);
+
+        let lines = param_lines(
+            app.envelope_params
+                .params()
+                .iter()
+                .map(|param| (param.name(), envelope.describe(*param))),
+            app.envelope_params.selected,
+            10,
+            theme,
+        );
+        f.render_widget(Paragraph::new(lines), rows[0]);
+
+        // Shape of the envelope, with the sustain held as long as the rest
+        let width = rows[1].width as usize;
+        let hold = (envelope.attack + envelope.decay + envelope.release).max(1.0);
+        let total = envelope.attack + envelope.decay + hold + envelope.release;
+        let shape: Vec<u64> = (0..width)
+            .map(|x| {
+                let t = x as f32 / width.max(1) as f32 * total;
+                let level = if t < envelope.attack {
+                    t / envelope.attack
+                } else if t < envelope.attack + envelope.decay {
+                    let t = (t - envelope.attack) / envelope.decay;
+                    1.0 - (1.0 - envelope.sustain) * t
+                } else if t < envelope.attack + envelope.decay + hold {
+                    envelope.sustain
+                } else {
+                    let t = (t - envelope.attack - envelope.decay - hold) / envelope.release;
+                    envelope.sustain * (1.0 - t)
+                };
+                (level.clamp(0.0, 1.0) * 100.0) as u64
+            })
+            .collect();
+
+        let sparkline = Sparkline::default()
+            .data(&shape)
+            .max(100)
+            .style(Style::default().fg(theme.meter));
+        f.render_widget(sparkline, rows[1]);
+    }
+
+    fn render_modulation_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let effects = &app.player.effect_manager;
+        let selected_param = app.modulation_params.selected_param();
+
+        let mut lines = Vec::new();
+        for (i, kind) in app.modulation_effects.params().iter().enumerate() {
+            let Some(params) = effects.modulation.get(*kind) else {
+                continue;
+            };
+            let is_selected = i == app.modulation_effects.selected;
+            let active = effects.is_active(*kind);
+
+            let mut spans = vec![Span::styled(
+                format!(
+                    "{}{:<10}{:<5}",
+                    if is_selected { "> " } else { "  " },
+                    kind.name(),
+                    if active { "ON" } else { "OFF" }
+                ),
+                if is_selected {
+                    Style::default()
+                        .fg(theme.highlight)
+                        .add_modifier(Modifier::BOLD)
+                } else if active {
+                    Style::default()
+                } else {
+                    Style::default().fg(theme.dim)
+                },
+            )];
+            for param in app.modulation_params.params() {
+                let text = params.describe(*param);
+                if is_selected && *param == selected_param {
+                    spans.push(Span::styled(
+                        format!("[{}]  ", text),
+                        Style::default()
+                            .fg(theme.highlight)
+                            .add_modifier(Modifier::BOLD),
+                    ));
+                } else {
+                    spans.push(Span::raw(format!(" {}   ", text)));
+                }
+            }
+            lines.push(Line::from(spans));
+        }
+
+        let panel =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Modulation"));
+        f.render_widget(panel, area);
+    }
+
+    fn render_mixer_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let selected = app.mixer_selected_index();
+        let any_solo = app.player.voices.iter().any(|voice| voice.solo);
+
+        let mut lines = Vec::new();
+        for (i, voice) in app.player.voices.iter().enumerate() {
+            let elapsed = voice.elapsed().as_secs_f32();
+            let pan = if voice.pan == 0.0 {
+                "C".to_string()
+            } else if voice.pan < 0.0 {
+                format!("L{:.0}", -voice.pan * 100.0)
+            } else {
+                format!("R{:.0}", voice.pan * 100.0)
+            };
+
+            let text = format!(
+                "{:<28} {:<5} {:>2}:{:04.1}  gain {:.1}x  pan {:<4} {} {}",
+                voice.name.chars().take(28).collect::<String>(),
+                if voice.releasing {
+                    "REL"
+                } else if voice.is_looping {
+                    "LOOP"
+                } else {
+                    ""
+                },
+                (elapsed / 60.0) as u32,
+                elapsed % 60.0,
+                voice.gain,
+                pan,
+                if voice.muted { "M" } else { "-" },
+                if voice.solo { "S" } else { "-" },
+            );
+
+            // Voices silenced by mute/solo are greyed out
+            let audible = !voice.muted && (!any_solo || voice.solo);
+            let style = if Some(i) == selected {
+                Style::default()
+                    .fg(theme.highlight)
+                    .add_modifier(Modifier::BOLD)
+            } else if audible {
+                Style::default()
+            } else {
+                Style::default().fg(theme.dim)
+            };
+            let prefix = if Some(i) == selected { "> " } else { "  " };
+            lines.push(Line::from(vec![Span::styled(
+                format!("{}{}", prefix, text),
+                style,
+            )]));
+        }
+
+        if lines.is_empty() {
+            lines.push(Line::from(vec![Span::styled(
+                "Nothing playing",
+                Style::default().fg(theme.dim),
+            )]));
+        }
+
+        let panel =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Mixer"));
+        f.render_widget(panel, area);
+    }
+
+    fn render_messages_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let messages = app.player.messages.entries();
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Messages ({})", messages.len()));
+        let offset = list_offset(app.message_selected, block.inner(area).height);
+
+        let mut lines = Vec::new();
+        for (i, message) in messages.iter().enumerate().skip(offset) {
+            let selected = i == app.message_selected;
+            let text_style = if selected {
+                Style::default()
+                    .fg(theme.highlight)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            lines.push(Line::from(vec![
+                Span::styled(if selected { "> " } else { "  " }, text_style),
+                Span::styled(
+                    format!("{} ", message.timestamp()),
+                    Style::default().fg(theme.dim),
+                ),
+                Span::styled(
+                    format!("{:<6}", message.severity.name()),
+                    Style::default()
+                        .fg(severity_color(theme, message.severity))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(message.text.clone(), text_style),
+            ]));
+        }
+
+        if app.player.messages.is_empty() {
+            lines.push(Line::from(vec![Span::styled(
+                "No messages",
+                Style::default().fg(theme.dim),
+            )]));
+        }
+
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn render_record_panel(f: &mut Frame, app: &App, area: Rect) {
+        let theme = &app.config.theme;
+        let recorder = &app.player.recorder;
+
+        let block = Block::default().borders(Borders::ALL).title("Recorder");
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(4), // Status
+                Constraint::Length(3), // Level meter
+                Constraint::Min(0),
+            ])
+            .split(inner);
+
+        let (state_text, state_color) = match recorder.state {
+            RecorderState::Idle => ("Idle".to_string(), theme.dim),
+            RecorderState::Armed => ("Armed".to_string(), theme.warning),
+            RecorderState::Recording => (
+                format!("● Recording {:.1}s", recorder.recorded_secs()),
+                theme.error,
+            ),
+        };
+
+        let file_text = recorder
+            .current_file
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "-".to_string());
+
+        let mut lines = vec![
+            Line::from(format!("Input: {}", recorder.input.name())),
+            Line::from(vec![
+                Span::raw("State: "),
+                Span::styled(
+                    state_text,
+                    Style::default()
+                        .fg(state_color)
+                        .add_modifier(Modifier::BOLD),
+                ),
+            ]),
+            Line::from(format!("File:  {}", file_text)),
+        ];
+        if let Some(message) = app.player.messages.last() {
+            lines.push(Line::from(vec![Span::styled(
+                message.text.clone(),
+                Style::default().fg(theme.dim),
+            )]));
+        }
+        f.render_widget(Paragraph::new(lines), rows[0]);
+
+        // Input level in dBFS
+        let level_db = 20.0 * recorder.level.max(0.000_01).log10();
+        let meter = Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title("Input Level"))
+            .gauge_style(Style::default().fg(if recorder.level >= 0.99 {
+                theme.error
+            } else if recorder.level >= 0.7 {
+                theme.warning
+            } else {
+                theme.good
+            }))
+            .percent((recorder.level * 100.0) as u16)
+            .label(if recorder.state == RecorderState::Idle {
+                "-".to_string()
+            } else {
+                format!("{:.1} dBFS", level_db)
+            });
+        f.render_widget(meter, rows[1]);
+    }
+
+    // One line per parameter, with the selected one highlighted
+    fn param_lines<'a>(
+        params: impl Iterator<Item = (&'static str, String)>,
+        selected: usize,
+        name_width: usize,
+        theme: &Theme,
+    ) -> Vec<Line<'a>> {
+        params
+            .enumerate()
+            .map(|(i, (name, value))| {
+                let text = format!("{:<width$}{}", name, value, width = name_width);
+                if i == selected {
+                    Line::from(vec![Span::styled(
+                        format!("> {}", text),
+                        Style::default()
+                            .fg(theme.highlight)
+                            .add_modifier(Modifier::BOLD),
+                    )])
+                } else {
+                    Line::from(format!("  {}", text))
+                }
+            })
+            .collect()
+    }
+
+    fn marker_name(marker: LoopMarker) -> &'static str {
+        match marker {
+            LoopMarker::Start => "Start",
+            LoopMarker::End => "End",
+            LoopMarker::Crossfade => "X-fade",
+        }
+    }
+
+    fn render_presets_popup(f: &mut Frame, app: &App) {
+        let theme = &app.config.theme;
+        let store = &app.player.preset_store;
+        let browser = &app.preset_browser;
+
+        let popup_area = centered(f.area(), 50, (store.len() as u16 + 6).clamp(8, 20));
+
+        f.render_widget(Clear, popup_area);
+        let block = Block::default()
+            .title("Presets")
+            .borders(Borders::ALL)
+            .style(Style::default().bg(theme.popup).fg(theme.text));
+        let inner = block.inner(popup_area);
+        f.render_widget(block, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(2)])
+            .split(inner);
+
+        let names = store.names();
+        let mut lines: Vec<Line> = Vec::new();
+        if names.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "No presets yet, press a to save the current settings",
+                Style::default().fg(theme.dim),
+            )));
+        }
+
+        // Keep the selection in view on long lists
+        let visible = rows[0].height as usize;
+        let first = browser.selected.saturating_sub(visible.saturating_sub(1));
+        for (i, name) in names.iter().enumerate().skip(first).take(visible) {
+            let marker = if browser.current.as_deref() == Some(*name) {
+                "*"
+            } else {
+                " "
+            };
+            let text = format!("{} {}", marker, name);
+            if i == browser.selected {
+                lines.push(Line::from(Span::styled(
+                    format!("> {}", text),
+                    Style::default()
+                        .fg(theme.highlight)
+                        .add_modifier(Modifier::BOLD),
+                )));
+            } else {
+                lines.push(Line::from(format!("  {}", text)));
+            }
+        }
+        f.render_widget(Paragraph::new(lines), rows[0]);
+
+        let footer = match browser.prompt {
+            Some(prompt) => {
+                let label = match prompt {
+                    PresetPrompt::SaveAs => "Save as",
+                    PresetPrompt::Rename => "Rename to",
+                };
+                Line::from(vec![
+                    Span::styled(
+                        format!("{}: ", label),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        format!("{}_", browser.input),
+                        Style::default().fg(theme.highlight),
+                    ),
+                ])
+            }
+            None if browser.confirm_delete => Line::from(Span::styled(
+                "Delete this preset? (y/n)",
+                Style::default().fg(theme.error),
+            )),
+            None => Line::from(Span::styled(
+                "Everything from volume to the master bus is saved",
+                Style::default().fg(theme.dim),
+            )),
+        };
+        f.render_widget(Paragraph::new(vec![Line::from(""), footer]), rows[1]);
+    }
+
+    if app.mode == AppMode::Presets {
+        render_presets_popup(f, app);
     }
 
     // Render help popup if needed
-    if app.show_help {
-        render_help_popup(f);
+    if !app.pending_keys.is_empty() {
+        render_help_popup(f, app);
     }
 }
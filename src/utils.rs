@@ -0,0 +1,91 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Our directory under the XDG config dir ($XDG_CONFIG_HOME or ~/.config)
+pub fn config_dir() -> Option<PathBuf> {
+    // Tests must never read or overwrite the user's own presets and loops, and
+    // each test thread gets its own directory so they can't see each other's
+    if cfg!(test) {
+        let thread = format!("{:?}", std::thread::current().id());
+        let name = format!("audirust-config-{}-{}", std::process::id(), thread);
+        return Some(env::temp_dir().join(name.replace(['(', ')'], "")));
+    }
+
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+
+    Some(base.join("audirust"))
+}
+
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(name))
+}
+
+// Write into a hidden file next to `path`, then rename it over `path`, so a
+// write that fails part way leaves the old file as it was
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".part");
+    let temp = path.with_file_name(name);
+
+    let written = write(&temp).and_then(|()| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+// Paths from the user (config, command line) may start with ~
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            PathBuf::from(home).join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_keep_out_of_the_real_config() {
+        let dir = config_dir().unwrap();
+        assert!(dir.starts_with(env::temp_dir()));
+        let other = std::thread::spawn(|| config_dir().unwrap()).join().unwrap();
+        assert_ne!(dir, other);
+    }
+
+    #[test]
+    fn failed_write_keeps_the_old_file() {
+        let dir = env::temp_dir().join(format!("audirust-utils-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("saved.toml");
+        fs::write(&path, "old").unwrap();
+
+        let failed = write_atomically(&path, |temp| {
+            fs::write(temp, "half")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(!dir.join(".saved.toml.part").exists());
+
+        write_atomically(&path, |temp| fs::write(temp, "new")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.join(".saved.toml.part").exists());
+    }
+}